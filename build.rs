@@ -0,0 +1,108 @@
+//! Checks the IDE assets that `src/ide.rs` embeds in the binary.
+//!
+//! The assets are committed in `assets/ide`, together with their SHA-256 sums in
+//! `assets/ide/SHA256SUMS`, so builds never need network access. A file that's missing or
+//! doesn't match its sum fails the build, and only checked files are copied into `OUT_DIR` for
+//! `src/ide.rs` to include.
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The file names `src/ide.rs` includes, and where they were downloaded from. Bump the versions
+/// here and in `src/ide.rs` together, and update `assets/ide/SHA256SUMS` with the new files.
+const ASSETS: &[(&str, &str)] = &[
+    (
+        "graphiql-0.13.2.css",
+        "https://cdn.jsdelivr.net/npm/graphiql@0.13.2/graphiql.css",
+    ),
+    (
+        "graphiql-0.13.2.min.js",
+        "https://cdn.jsdelivr.net/npm/graphiql@0.13.2/graphiql.min.js",
+    ),
+    (
+        "react-16.8.6.min.js",
+        "https://cdn.jsdelivr.net/npm/react@16.8.6/umd/react.production.min.js",
+    ),
+    (
+        "react-dom-16.8.6.min.js",
+        "https://cdn.jsdelivr.net/npm/react-dom@16.8.6/umd/react-dom.production.min.js",
+    ),
+    (
+        "whatwg-fetch-3.0.0.js",
+        "https://cdn.jsdelivr.net/npm/whatwg-fetch@3.0.0/dist/fetch.umd.js",
+    ),
+    (
+        "graphql-playground-1.7.20.css",
+        "https://cdn.jsdelivr.net/npm/graphql-playground-react@1.7.20/build/static/css/index.css",
+    ),
+    (
+        "graphql-playground-1.7.20.js",
+        "https://cdn.jsdelivr.net/npm/graphql-playground-react@1.7.20/build/static/js/middleware.js",
+    ),
+];
+
+fn main() {
+    let assets_dir = Path::new("assets/ide");
+    let sums_path = assets_dir.join("SHA256SUMS");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap()).join("ide-assets");
+    fs::create_dir_all(&out_dir).unwrap();
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", assets_dir.display());
+
+    let sums = match fs::read_to_string(&sums_path) {
+        Ok(contents) => contents,
+        Err(err) => panic!(
+            "\n\nfailed to read {}: {}\n\nDownload the files listed in build.rs into {} and \
+             record their sums with `sha256sum * > SHA256SUMS` after checking them.\n\n",
+            sums_path.display(),
+            err,
+            assets_dir.display(),
+        ),
+    };
+    let sums = parse_sums(&sums);
+
+    let mut failures = Vec::new();
+    for (name, url) in ASSETS {
+        let path = assets_dir.join(name);
+        if let Err(err) = check(&path, sums.get(name), &out_dir.join(name)) {
+            failures.push(format!("  {} from {}: {}", name, url, err));
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!(
+            "\n\nthe GraphQL IDE assets in {} are missing or don't match {}:\n{}\n\n",
+            assets_dir.display(),
+            sums_path.display(),
+            failures.join("\n"),
+        );
+    }
+}
+
+/// Parse lines in the format `sha256sum` writes, mapping file names to sums.
+fn parse_sums(contents: &str) -> HashMap<&str, &str> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let sum = parts.next()?;
+            let name = parts.next()?.trim_start_matches('*');
+            Some((name, sum))
+        })
+        .collect()
+}
+
+fn check(path: &Path, expected: Option<&&str>, out: &Path) -> Result<(), String> {
+    let expected = expected.ok_or("no sum in SHA256SUMS")?;
+    let contents =
+        fs::read(path).map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    let actual = format!("{:x}", Sha256::digest(&contents));
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(format!("SHA-256 is {} but should be {}", actual, expected));
+    }
+    fs::write(out, contents).map_err(|err| format!("failed to write {}: {}", out.display(), err))
+}
@@ -1,10 +1,17 @@
+// diesel 1.4's `table!` and derives put their impls inside `const` items.
+#![allow(non_local_definitions)]
+
 #[macro_use]
 extern crate diesel;
 
 use diesel::prelude::*;
 use gimme_graphql::{
+    authorization::Authorizer,
+    cli, get_connection,
     hyper_adapter::{self, hyper, HyperAdapter},
-    run_graphql_app, ConnectionManager, GraphqlApp, Pool,
+    sql_counting::CountingConnection,
+    upload::Uploads,
+    ConnectionManager, GraphqlApp, Pool,
 };
 use juniper::ID;
 use juniper_from_schema::graphql_schema_from_file;
@@ -32,7 +39,7 @@ impl QueryFields for Query {
     ) -> juniper::FieldResult<Vec<User>> {
         use schema::users;
 
-        let db = &executor.context().db_pool.get()?;
+        let db = &get_connection(&executor.context().db_pool)?;
 
         let users = users::table
             .limit(limit.into())
@@ -61,35 +68,52 @@ impl MutationFields for Mutation {
     fn field_noop(&self, _: &juniper::Executor<'_, Context>) -> juniper::FieldResult<&bool> {
         Ok(&true)
     }
+
+    fn field_upload_file(
+        &self,
+        executor: &juniper::Executor<'_, Context>,
+        file: Upload,
+    ) -> juniper::FieldResult<i32> {
+        let file = executor
+            .context()
+            .uploads
+            .get(&file.0)
+            .ok_or("unknown upload")?;
+        Ok(file.size() as i32)
+    }
 }
 
 pub fn main() {
-    run_graphql_app(App);
+    cli::run(App);
 }
 
 struct App;
 
 impl GraphqlApp for App {
     type Adapter = HyperAdapter;
-    type Connection = PgConnection;
+    type Connection = CountingConnection<PgConnection>;
     type Query = Query;
     type Mutation = Mutation;
     type Context = Context;
 }
 
 pub struct Context {
-    pub db_pool: Pool<ConnectionManager<PgConnection>>,
+    pub db_pool: Pool<ConnectionManager<CountingConnection<PgConnection>>>,
+    pub uploads: Uploads,
 }
 
 impl juniper::Context for Context {}
 
-impl hyper_adapter::CreateContext<PgConnection> for Context {
+impl Authorizer for Context {}
+
+impl hyper_adapter::CreateContext<CountingConnection<PgConnection>> for Context {
     fn create(
-        db_pool: &Pool<ConnectionManager<PgConnection>>,
-        _: &hyper::Request<hyper::Body>,
+        db_pool: &Pool<ConnectionManager<CountingConnection<PgConnection>>>,
+        request: &hyper::Request<hyper::Body>,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         Ok(Context {
             db_pool: db_pool.clone(),
+            uploads: hyper_adapter::uploads(request),
         })
     }
 }
@@ -1,4 +1,6 @@
 #![feature(proc_macro_hygiene, decl_macro)]
+// diesel 1.4's `table!` and derives put their impls inside `const` items.
+#![allow(non_local_definitions)]
 
 extern crate rocket;
 #[macro_use]
@@ -6,20 +8,23 @@ extern crate diesel;
 
 use diesel::prelude::*;
 use gimme_graphql::{
+    authorization::Authorizer,
+    cli, get_connection,
     rocket_adapter::{
+        self,
         rocket::{
-            fairing::AdHoc,
             http::Status,
             request::{FromRequest, Outcome, Request},
-            Rocket, State,
+            State,
         },
         RocketAdapter,
     },
-    run_graphql_app, ConnectionManager, GraphqlApp, Pool, PooledConnection,
+    sql_counting::CountingConnection,
+    upload::Uploads,
+    ConnectionManager, GraphqlApp, Pool, PooledConnection,
 };
 use juniper::ID;
 use juniper_from_schema::graphql_schema_from_file;
-use std::sync::Mutex;
 
 mod schema {
     table! {
@@ -73,36 +78,56 @@ impl MutationFields for Mutation {
     fn field_noop(&self, _: &juniper::Executor<'_, Context>) -> juniper::FieldResult<&bool> {
         Ok(&true)
     }
+
+    fn field_upload_file(
+        &self,
+        executor: &juniper::Executor<'_, Context>,
+        file: Upload,
+    ) -> juniper::FieldResult<i32> {
+        let file = executor
+            .context()
+            .uploads
+            .get(&file.0)
+            .ok_or("unknown upload")?;
+        Ok(file.size() as i32)
+    }
 }
 
 pub fn main() {
-    run_graphql_app(App);
+    cli::run(App);
 }
 
 struct App;
 
 impl GraphqlApp for App {
     type Adapter = RocketAdapter;
-    type Connection = PgConnection;
+    type Connection = CountingConnection<PgConnection>;
     type Query = Query;
     type Mutation = Mutation;
     type Context = Context;
 }
 
 pub struct Context {
-    pub db_con: PooledConnection<ConnectionManager<PgConnection>>,
+    pub db_con: PooledConnection<ConnectionManager<CountingConnection<PgConnection>>>,
+    pub uploads: Uploads,
 }
 
 impl juniper::Context for Context {}
 
+impl Authorizer for Context {}
+
 impl<'a, 'r> FromRequest<'a, 'r> for Context {
     type Error = ();
 
     fn from_request(request: &'a Request<'r>) -> Outcome<Context, ()> {
-        let db_pool = request.guard::<State<Pool<ConnectionManager<PgConnection>>>>()?;
-
-        match db_pool.get() {
-            Ok(db_con) => Outcome::Success(Context { db_con }),
+        let db_pool =
+            request.guard::<State<Pool<ConnectionManager<CountingConnection<PgConnection>>>>>()?;
+
+        match get_connection(&db_pool) {
+            Ok(db_con) => Outcome::Success(Context {
+                db_con,
+                uploads: rocket_adapter::uploads(request),
+            }),
             Err(_) => Outcome::Failure((Status::ServiceUnavailable, ())),
         }
     }
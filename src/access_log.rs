@@ -0,0 +1,320 @@
+//! A structured log line for every GraphQL request.
+//!
+//! The lines are logged at INFO with the target `gimme_graphql::access`, in JSON or logfmt, and
+//! have these fields:
+//!
+//! | Field | |
+//! |---|---|
+//! | `method`, `path`, `status` | Of the HTTP request and response. |
+//! | `duration_ms` | From receiving the request to sending the response. |
+//! | `operation_name`, `operation_type` | |
+//! | `query_hash` | SHA-256 of the query, as used by persisted queries. |
+//! | `variables` | With the values of sensitive variables replaced by `[REDACTED]`. |
+//! | `client_ip` | |
+//! | `request_id` | See the `request_id` module. |
+//!
+//! The operation fields are left out if the request couldn't be parsed. For batches they hold
+//! the values of every operation, separated by commas, and `variables` is a list.
+//!
+//! A variable is sensitive if its name contains one of the keys returned by
+//! `GraphqlApp::redacted_variables`, ignoring case. Fields of input objects are redacted the
+//! same way.
+
+use crate::request::OperationSummary;
+use crate::request_id::RequestId;
+use crate::telemetry;
+use serde_json::json;
+use std::net::IpAddr;
+use std::time::Duration;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Variable names redacted by default.
+pub const DEFAULT_REDACTED_VARIABLES: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "authorization",
+    "apikey",
+    "api_key",
+    "credential",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Json,
+    Logfmt,
+}
+
+/// How to write the access log.
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    format: LogFormat,
+    trust_forwarded_for: bool,
+}
+
+impl AccessLog {
+    pub fn json() -> Self {
+        AccessLog {
+            format: LogFormat::Json,
+            trust_forwarded_for: false,
+        }
+    }
+
+    pub fn logfmt() -> Self {
+        AccessLog {
+            format: LogFormat::Logfmt,
+            trust_forwarded_for: false,
+        }
+    }
+
+    /// Take the client IP from the `X-Forwarded-For` header. Only enable this behind a proxy
+    /// that sets the header, as clients can send anything.
+    pub fn trust_forwarded_for(mut self, trust_forwarded_for: bool) -> Self {
+        self.trust_forwarded_for = trust_forwarded_for;
+        self
+    }
+
+    /// The address of the client, which is the first one in `X-Forwarded-For` if that's
+    /// trusted.
+    pub(crate) fn client_ip(
+        &self,
+        remote_addr: Option<IpAddr>,
+        forwarded_for: Option<&str>,
+    ) -> Option<IpAddr> {
+        let forwarded_for = forwarded_for
+            .filter(|_| self.trust_forwarded_for)
+            .and_then(|value| value.split(',').next())
+            .and_then(|addr| addr.trim().parse().ok());
+        forwarded_for.or(remote_addr)
+    }
+
+    pub(crate) fn log(&self, entry: &AccessLogEntry) {
+        let line = match self.format {
+            LogFormat::Json => entry.to_json().to_string(),
+            LogFormat::Logfmt => entry.to_logfmt(),
+        };
+        log::info!(target: "gimme_graphql::access", "{}", line);
+    }
+}
+
+pub(crate) struct AccessLogEntry<'a> {
+    pub(crate) method: &'a str,
+    pub(crate) path: &'a str,
+    pub(crate) status: u16,
+    pub(crate) duration: Duration,
+    pub(crate) client_ip: Option<IpAddr>,
+    pub(crate) request_id: &'a RequestId,
+    pub(crate) operations: &'a [OperationSummary],
+}
+
+impl AccessLogEntry<'_> {
+    /// The fields in the order they're logged. Missing values are `null`.
+    fn fields(&self) -> Vec<(&'static str, serde_json::Value)> {
+        let join = |value: &dyn Fn(&OperationSummary) -> Option<&str>| {
+            if self.operations.is_empty() {
+                return serde_json::Value::Null;
+            }
+            let values = self
+                .operations
+                .iter()
+                .map(|operation| value(operation).unwrap_or(""))
+                .collect::<Vec<_>>();
+            json!(values.join(","))
+        };
+        let variables = match self.operations {
+            [] => serde_json::Value::Null,
+            [operation] => operation.variables.clone(),
+            operations => json!(operations
+                .iter()
+                .map(|operation| operation.variables.clone())
+                .collect::<Vec<_>>()),
+        };
+
+        vec![
+            ("method", json!(self.method)),
+            ("path", json!(self.path)),
+            ("status", json!(self.status)),
+            ("duration_ms", json!(telemetry::millis(self.duration))),
+            (
+                "operation_name",
+                join(&|operation| operation.operation_name.as_deref()),
+            ),
+            (
+                "operation_type",
+                join(&|operation| operation.operation_type),
+            ),
+            (
+                "query_hash",
+                join(&|operation| operation.query_hash.as_deref()),
+            ),
+            ("variables", variables),
+            (
+                "client_ip",
+                json!(self.client_ip.map(|client_ip| client_ip.to_string())),
+            ),
+            ("request_id", json!(self.request_id.as_str())),
+        ]
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::Value::Object(
+            self.fields()
+                .into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn to_logfmt(&self) -> String {
+        self.fields()
+            .into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| {
+                let value = match value {
+                    serde_json::Value::String(value) => value,
+                    value => value.to_string(),
+                };
+                format!("{}={}", key, logfmt_value(&value))
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn logfmt_value(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c == ' ' || c == '=' || c == '"' || c == '\\' || c.is_control());
+    if needs_quotes {
+        json!(value).to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Replace the values of sensitive variables, including fields of input objects.
+pub(crate) fn redact(value: &mut serde_json::Value, keys: &[String]) {
+    match value {
+        serde_json::Value::Object(object) => {
+            for (name, value) in object.iter_mut() {
+                let name = name.to_lowercase();
+                if keys.iter().any(|key| name.contains(key.as_str())) {
+                    *value = json!(REDACTED);
+                } else {
+                    redact(value, keys);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                redact(value, keys);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_keys() -> Vec<String> {
+        DEFAULT_REDACTED_VARIABLES
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn sensitive_variables_are_redacted() {
+        let mut variables = json!({ "password": "hunter2", "email": "a@example.com" });
+        redact(&mut variables, &default_keys());
+
+        assert_eq!(
+            variables,
+            json!({ "password": REDACTED, "email": "a@example.com" })
+        );
+    }
+
+    #[test]
+    fn names_containing_a_key_are_redacted_ignoring_case() {
+        let mut variables = json!({
+            "newPassword": "hunter2",
+            "ACCESS_TOKEN": "abc",
+            "userApiKey": "def",
+            "tokenless": "ghi",
+            "name": "Ann",
+        });
+        redact(&mut variables, &default_keys());
+
+        assert_eq!(
+            variables,
+            json!({
+                "newPassword": REDACTED,
+                "ACCESS_TOKEN": REDACTED,
+                "userApiKey": REDACTED,
+                "tokenless": REDACTED,
+                "name": "Ann",
+            })
+        );
+    }
+
+    #[test]
+    fn fields_of_input_objects_are_redacted() {
+        let mut variables = json!({
+            "input": { "user": { "name": "Ann", "password": "hunter2" } },
+        });
+        redact(&mut variables, &default_keys());
+
+        assert_eq!(
+            variables,
+            json!({ "input": { "user": { "name": "Ann", "password": REDACTED } } })
+        );
+    }
+
+    #[test]
+    fn objects_in_lists_are_redacted() {
+        let mut variables = json!({
+            "users": [{ "name": "Ann", "secret": "a" }, { "name": "Bob", "secret": "b" }],
+        });
+        redact(&mut variables, &default_keys());
+
+        assert_eq!(
+            variables,
+            json!({
+                "users": [
+                    { "name": "Ann", "secret": REDACTED },
+                    { "name": "Bob", "secret": REDACTED },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn a_sensitive_object_is_redacted_as_a_whole() {
+        let mut variables = json!({ "credentials": { "user": "ann", "pass": "hunter2" } });
+        redact(&mut variables, &default_keys());
+
+        assert_eq!(variables, json!({ "credentials": REDACTED }));
+    }
+
+    #[test]
+    fn custom_keys_replace_the_defaults() {
+        let mut variables = json!({ "password": "hunter2", "ssn": "123-45-6789" });
+        redact(&mut variables, &["ssn".to_string()]);
+
+        assert_eq!(variables, json!({ "password": "hunter2", "ssn": REDACTED }));
+    }
+
+    #[test]
+    fn values_without_names_are_left_alone() {
+        let mut variables = json!(["password", 1, null]);
+        redact(&mut variables, &default_keys());
+
+        assert_eq!(variables, json!(["password", 1, null]));
+    }
+}
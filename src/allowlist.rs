@@ -0,0 +1,379 @@
+//! Only accept operations from a known list.
+//!
+//! The list is either a directory of `.graphql` files or a JSON manifest mapping hashes to
+//! documents:
+//!
+//! ```ignore
+//! fn query_allowlist(&self) -> Option<QueryAllowlist> {
+//!     Some(QueryAllowlist::from_directory("queries"))
+//! }
+//! ```
+//!
+//! Every document is validated against the schema at startup. Documents are compared after
+//! being parsed and printed again, so whitespace and comments don't matter.
+//!
+//! The hashes in a manifest must be the hex encoded SHA-256 hashes of their documents. Clients
+//! can send just the hash of a listed document, the same way they send
+//! [persisted queries](crate::persisted_queries).
+
+use crate::persisted_queries::query_hash;
+use crate::validation;
+use graphql_parser::query::Document;
+use juniper::{GraphQLType, RootNode};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where to load allowed operations from, and what to do with operations not on the list.
+pub struct QueryAllowlist {
+    source: AllowlistSource,
+    log_only: bool,
+}
+
+enum AllowlistSource {
+    Directory(PathBuf),
+    Manifest(PathBuf),
+}
+
+impl QueryAllowlist {
+    /// Load every `.graphql` file in `path` and its subdirectories.
+    pub fn from_directory<P: Into<PathBuf>>(path: P) -> Self {
+        QueryAllowlist {
+            source: AllowlistSource::Directory(path.into()),
+            log_only: false,
+        }
+    }
+
+    /// Load a JSON object mapping the SHA-256 hashes of documents to the documents.
+    pub fn from_manifest<P: Into<PathBuf>>(path: P) -> Self {
+        QueryAllowlist {
+            source: AllowlistSource::Manifest(path.into()),
+            log_only: false,
+        }
+    }
+
+    /// Log operations not on the list instead of rejecting them.
+    pub fn log_only(mut self) -> Self {
+        self.log_only = true;
+        self
+    }
+
+    pub(crate) fn load(self) -> Result<Allowlist, String> {
+        let sources = match &self.source {
+            AllowlistSource::Directory(path) => {
+                let mut sources = Vec::new();
+                read_directory(path, &mut sources)?;
+                sources
+            }
+            AllowlistSource::Manifest(path) => read_manifest(path)?,
+        };
+
+        let mut documents = HashMap::new();
+        let mut sources_by_hash = HashMap::new();
+        for (origin, source) in sources {
+            let document = graphql_parser::parse_query(&source)
+                .map_err(|err| format!("failed to parse {}: {}", origin, err))?;
+            documents.insert(document.to_string(), origin);
+            sources_by_hash.insert(query_hash(&source), source);
+        }
+
+        Ok(Allowlist {
+            documents,
+            sources_by_hash,
+            log_only: self.log_only,
+        })
+    }
+}
+
+fn read_directory(path: &Path, sources: &mut Vec<(String, String)>) -> Result<(), String> {
+    let entries =
+        fs::read_dir(path).map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+
+    for entry in entries {
+        let path = entry
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?
+            .path();
+
+        if path.is_dir() {
+            read_directory(&path, sources)?;
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "graphql")
+        {
+            let source = fs::read_to_string(&path)
+                .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+            sources.push((path.display().to_string(), source));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_manifest(path: &Path) -> Result<Vec<(String, String)>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    let manifest = serde_json::from_str::<HashMap<String, String>>(&contents)
+        .map_err(|err| format!("failed to parse {}: {}", path.display(), err))?;
+
+    manifest
+        .into_iter()
+        .map(|(hash, source)| {
+            if hash.to_lowercase() != query_hash(&source) {
+                return Err(format!(
+                    "hash {} in {} isn't the SHA-256 hash of its document",
+                    hash,
+                    path.display()
+                ));
+            }
+            Ok((format!("{} ({})", path.display(), hash), source))
+        })
+        .collect()
+}
+
+/// The loaded allowlist, keyed by normalized document.
+pub(crate) struct Allowlist {
+    documents: HashMap<String, String>,
+    /// The documents as written, keyed by their hash.
+    sources_by_hash: HashMap<String, String>,
+    log_only: bool,
+}
+
+impl Allowlist {
+    /// Panics if any of the documents don't match the schema.
+    pub(crate) fn validate<Query, Mutation>(&self, root_node: &RootNode<Query, Mutation>)
+    where
+        Query: GraphQLType<TypeInfo = ()>,
+        Mutation: GraphQLType<TypeInfo = ()>,
+    {
+        let mut errors = Vec::new();
+        for (normalized, origin) in &self.documents {
+            let document = graphql_parser::parse_query(normalized)
+                .expect("normalized allowlist document doesn't parse");
+            for error in validation::validate_document(root_node, &document) {
+                errors.push(format!("{}: {}", origin, error));
+            }
+        }
+
+        if !errors.is_empty() {
+            errors.sort();
+            panic!(
+                "query allowlist doesn't match the schema:\n{}",
+                errors.join("\n")
+            );
+        }
+    }
+
+    /// The listed document with the given hash, for requests that only send the hash.
+    pub(crate) fn get(&self, hash: &str) -> Option<&str> {
+        self.sources_by_hash.get(hash).map(|source| source.as_str())
+    }
+
+    /// Whether the document is on the list, ignoring formatting.
+    pub(crate) fn contains(&self, document: &Document) -> bool {
+        self.documents.contains_key(&document.to_string())
+    }
+
+    /// Returns an error message if the query isn't on the list and the list is enforced.
+    pub(crate) fn check(&self, query: &str, operation_name: Option<&str>) -> Result<(), String> {
+        let is_allowed = match graphql_parser::parse_query(query) {
+            Ok(document) => self.contains(&document),
+            Err(_) => false,
+        };
+
+        if is_allowed {
+            return Ok(());
+        }
+
+        if self.log_only {
+            log::warn!(
+                "Operation {} is not in the query allowlist",
+                operation_name.unwrap_or("<anonymous>")
+            );
+            Ok(())
+        } else {
+            Err("Operation is not in the query allowlist".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_schema::schema;
+
+    fn write(dir: &Path, name: &str, contents: &str) {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn directory_documents_are_allowed_regardless_of_formatting() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "users.graphql",
+            "query Users { users { id name } }",
+        );
+        write(
+            dir.path(),
+            "nested/user.graphql",
+            "query User($id: Int!) { user(id: $id) { id } }",
+        );
+        write(dir.path(), "README.md", "not a query");
+
+        let allowlist = QueryAllowlist::from_directory(dir.path()).load().unwrap();
+
+        assert_eq!(allowlist.documents.len(), 2);
+        assert!(allowlist
+            .check(
+                "# comment\nquery Users {\n  users {\n    id\n    name\n  }\n}",
+                Some("Users")
+            )
+            .is_ok());
+        assert!(allowlist
+            .check("query User($id: Int!) { user(id: $id) { id } }", None)
+            .is_ok());
+    }
+
+    #[test]
+    fn operations_not_on_the_list_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "users.graphql", "query Users { users { id } }");
+        let allowlist = QueryAllowlist::from_directory(dir.path()).load().unwrap();
+
+        assert_eq!(
+            allowlist.check("query Users { users { id name } }", Some("Users")),
+            Err("Operation is not in the query allowlist".to_string())
+        );
+        assert!(allowlist.check("query Users {", Some("Users")).is_err());
+    }
+
+    #[test]
+    fn log_only_allows_everything() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "users.graphql", "query Users { users { id } }");
+        let allowlist = QueryAllowlist::from_directory(dir.path())
+            .log_only()
+            .load()
+            .unwrap();
+
+        assert!(allowlist.check("{ user(id: 1) { id } }", None).is_ok());
+    }
+
+    #[test]
+    fn log_only_still_knows_which_documents_are_listed() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "users.graphql", "query Users { users { id } }");
+        let allowlist = QueryAllowlist::from_directory(dir.path())
+            .log_only()
+            .load()
+            .unwrap();
+
+        let listed = graphql_parser::parse_query("query Users {\n  users { id }\n}").unwrap();
+        let unlisted = graphql_parser::parse_query("query Users { users { id name } }").unwrap();
+        assert!(allowlist.contains(&listed));
+        assert!(!allowlist.contains(&unlisted));
+    }
+
+    const USERS: &str = "query Users { users { id } }";
+
+    fn manifest(dir: &Path, hash: &str) -> Result<Allowlist, String> {
+        write(
+            dir,
+            "manifest.json",
+            &serde_json::json!({ hash: USERS }).to_string(),
+        );
+        QueryAllowlist::from_manifest(dir.join("manifest.json")).load()
+    }
+
+    #[test]
+    fn manifest_documents_are_allowed() {
+        let dir = tempfile::tempdir().unwrap();
+        let allowlist = manifest(dir.path(), &query_hash(USERS)).unwrap();
+
+        assert!(allowlist.check(USERS, None).is_ok());
+        assert!(allowlist.check("{ users { id } }", None).is_err());
+    }
+
+    #[test]
+    fn manifest_documents_can_be_found_by_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let allowlist = manifest(dir.path(), &query_hash(USERS).to_uppercase()).unwrap();
+
+        assert_eq!(allowlist.get(&query_hash(USERS)), Some(USERS));
+        assert_eq!(allowlist.get(&query_hash("{ users { id } }")), None);
+    }
+
+    #[test]
+    fn manifest_hashes_must_match_their_documents() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let err = manifest(dir.path(), "abc").err().unwrap();
+
+        assert!(
+            err.starts_with("hash abc in ")
+                && err.ends_with(" isn't the SHA-256 hash of its document"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn unparsable_documents_fail_to_load() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "broken.graphql", "query Users {");
+
+        let err = QueryAllowlist::from_directory(dir.path())
+            .load()
+            .err()
+            .unwrap();
+
+        assert!(err.starts_with("failed to parse"), "{}", err);
+        assert!(err.contains("broken.graphql"), "{}", err);
+    }
+
+    #[test]
+    fn invalid_manifest_fails_to_load() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "manifest.json",
+            r#"["query Users { users { id } }"]"#,
+        );
+
+        let err = QueryAllowlist::from_manifest(dir.path().join("manifest.json"))
+            .load()
+            .err()
+            .unwrap();
+
+        assert!(err.starts_with("failed to parse"), "{}", err);
+    }
+
+    #[test]
+    fn valid_documents_pass_validation() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "users.graphql",
+            "query Users { users(limit: 2) { id friends(first: 1) { name } } }",
+        );
+        let allowlist = QueryAllowlist::from_directory(dir.path()).load().unwrap();
+
+        allowlist.validate(&schema());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown field \"email\" on type \"User\"")]
+    fn documents_that_dont_match_the_schema_fail_validation() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "users.graphql",
+            "query Users { users { id email } }",
+        );
+        let allowlist = QueryAllowlist::from_directory(dir.path()).load().unwrap();
+
+        allowlist.validate(&schema());
+    }
+}
@@ -0,0 +1,554 @@
+//! Depth and complexity limits, checked before a query is executed.
+//!
+//! Every field costs 1 by default. Fields that return lists usually deserve a higher cost,
+//! which can be configured with [`FieldCosts`]:
+//!
+//! ```ignore
+//! fn field_costs(&self) -> FieldCosts {
+//!     FieldCosts::new().field("Query", "users", FieldCost::new(1).multiplied_by("limit"))
+//! }
+//! ```
+//!
+//! The complexity of a field is its own cost plus the complexity of its sub-selection,
+//! multiplied by the value of each of its multiplier arguments.
+
+use graphql_parser::query::{
+    Definition, Field, FragmentDefinition, OperationDefinition, Selection, SelectionSet,
+    TypeCondition, Value,
+};
+use juniper::{meta, DefaultScalarValue, GraphQLType, RootNode, Variables};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+pub struct QueryLimits {
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) max_complexity: Option<usize>,
+    pub(crate) field_costs: FieldCosts,
+}
+
+/// Costs of individual fields, used when calculating query complexity.
+#[derive(Default)]
+pub struct FieldCosts {
+    costs: HashMap<(String, String), FieldCost>,
+}
+
+impl FieldCosts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the cost of the field `field_name` on the type `type_name`.
+    pub fn field(mut self, type_name: &str, field_name: &str, cost: FieldCost) -> Self {
+        self.costs
+            .insert((type_name.to_string(), field_name.to_string()), cost);
+        self
+    }
+
+    fn get(&self, type_name: &str, field_name: &str) -> Option<&FieldCost> {
+        self.costs
+            .get(&(type_name.to_string(), field_name.to_string()))
+    }
+}
+
+#[derive(Clone)]
+pub struct FieldCost {
+    cost: usize,
+    multipliers: Vec<String>,
+}
+
+impl FieldCost {
+    pub fn new(cost: usize) -> Self {
+        FieldCost {
+            cost,
+            multipliers: Vec::new(),
+        }
+    }
+
+    /// Multiply the complexity of the field by the value of an integer argument.
+    ///
+    /// If the argument isn't given, its default value from the schema is used. Values below 1
+    /// count as 1, so a negative or zero argument can't hide the cost of the sub-selection.
+    pub fn multiplied_by(mut self, argument: &str) -> Self {
+        self.multipliers.push(argument.to_string());
+        self
+    }
+}
+
+impl Default for FieldCost {
+    fn default() -> Self {
+        FieldCost::new(1)
+    }
+}
+
+/// Returns an error message if the operation is deeper or more complex than allowed, or if it
+/// can't be analysed because the query doesn't parse or the operation can't be found.
+pub(crate) fn check_limits<Query, Mutation>(
+    root_node: &RootNode<Query, Mutation>,
+    query: &str,
+    operation_name: Option<&str>,
+    variables: &Variables,
+    limits: &QueryLimits,
+) -> Result<(), String>
+where
+    Query: GraphQLType<TypeInfo = ()>,
+    Mutation: GraphQLType<TypeInfo = ()>,
+{
+    if limits.max_depth.is_none() && limits.max_complexity.is_none() {
+        return Ok(());
+    }
+
+    let document = graphql_parser::parse_query(query)
+        .map_err(|err| format!("Failed to parse query: {}", err))?;
+
+    let mut operations = Vec::new();
+    let mut fragments = HashMap::new();
+    for definition in &document.definitions {
+        match definition {
+            Definition::Operation(operation) => operations.push(operation),
+            Definition::Fragment(fragment) => {
+                fragments.insert(fragment.name.as_str(), fragment);
+            }
+        }
+    }
+
+    let operation_count = operations.len();
+    let operation = match operation_name {
+        Some(name) => operations
+            .into_iter()
+            .find(|operation| self::operation_name(operation) == Some(name)),
+        None if operations.len() == 1 => operations.pop(),
+        None => None,
+    };
+    let operation = match (operation, operation_name) {
+        (Some(operation), _) => operation,
+        (None, Some(name)) => return Err(format!("Unknown operation \"{}\"", name)),
+        (None, None) if operation_count == 0 => {
+            return Err("Query doesn't contain an operation".to_string())
+        }
+        (None, None) => {
+            return Err(
+                "An operation name is required when the query contains multiple operations"
+                    .to_string(),
+            )
+        }
+    };
+
+    let (root_type, selection_set, variable_definitions) = match operation {
+        OperationDefinition::SelectionSet(selection_set) => (
+            root_node.schema.concrete_query_type().name(),
+            selection_set,
+            &[][..],
+        ),
+        OperationDefinition::Query(query) => (
+            root_node.schema.concrete_query_type().name(),
+            &query.selection_set,
+            &query.variable_definitions[..],
+        ),
+        OperationDefinition::Mutation(mutation) => (
+            root_node
+                .schema
+                .concrete_mutation_type()
+                .and_then(|mutation_type| mutation_type.name()),
+            &mutation.selection_set,
+            &mutation.variable_definitions[..],
+        ),
+        OperationDefinition::Subscription(subscription) => (
+            None,
+            &subscription.selection_set,
+            &subscription.variable_definitions[..],
+        ),
+    };
+
+    let analysis = Analysis {
+        root_node,
+        fragments,
+        variables,
+        variable_defaults: variable_definitions
+            .iter()
+            .filter_map(|definition| {
+                definition
+                    .default_value
+                    .as_ref()
+                    .map(|value| (definition.name.as_str(), value))
+            })
+            .collect(),
+        field_costs: &limits.field_costs,
+        fragment_costs: RefCell::new(HashMap::new()),
+    };
+    let cost = analysis.selection_set(root_type, selection_set, &mut Vec::new());
+
+    if let Some(max_depth) = limits.max_depth {
+        if cost.depth > max_depth {
+            return Err(format!(
+                "Query is too deep: it has a depth of {} but the maximum allowed is {}",
+                cost.depth, max_depth
+            ));
+        }
+    }
+
+    if let Some(max_complexity) = limits.max_complexity {
+        if cost.complexity > max_complexity {
+            return Err(format!(
+                "Query is too complex: it has a complexity of {} but the maximum allowed is {}",
+                cost.complexity, max_complexity
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn operation_name(operation: &OperationDefinition) -> Option<&str> {
+    match operation {
+        OperationDefinition::SelectionSet(_) => None,
+        OperationDefinition::Query(query) => query.name.as_deref(),
+        OperationDefinition::Mutation(mutation) => mutation.name.as_deref(),
+        OperationDefinition::Subscription(subscription) => subscription.name.as_deref(),
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Cost {
+    depth: usize,
+    complexity: usize,
+}
+
+impl Cost {
+    fn add(&mut self, other: Cost) {
+        self.depth = std::cmp::max(self.depth, other.depth);
+        self.complexity = self.complexity.saturating_add(other.complexity);
+    }
+}
+
+struct Analysis<'a, Query, Mutation>
+where
+    Query: GraphQLType<TypeInfo = ()>,
+    Mutation: GraphQLType<TypeInfo = ()>,
+{
+    root_node: &'a RootNode<'a, Query, Mutation>,
+    fragments: HashMap<&'a str, &'a FragmentDefinition>,
+    variables: &'a Variables,
+    variable_defaults: HashMap<&'a str, &'a Value>,
+    field_costs: &'a FieldCosts,
+    /// Cost of each fragment, which only depends on its type condition, so that fragments
+    /// spread many times are only walked once.
+    fragment_costs: RefCell<HashMap<&'a str, Cost>>,
+}
+
+impl<'a, Query, Mutation> Analysis<'a, Query, Mutation>
+where
+    Query: GraphQLType<TypeInfo = ()>,
+    Mutation: GraphQLType<TypeInfo = ()>,
+{
+    fn selection_set(
+        &self,
+        type_name: Option<&'a str>,
+        selection_set: &'a SelectionSet,
+        visited_fragments: &mut Vec<&'a str>,
+    ) -> Cost {
+        let mut cost = Cost::default();
+
+        for selection in &selection_set.items {
+            match selection {
+                Selection::Field(field) => {
+                    cost.add(self.field(type_name, field, visited_fragments));
+                }
+                Selection::FragmentSpread(spread) => {
+                    let name = spread.fragment_name.as_str();
+                    if let Some(&fragment_cost) = self.fragment_costs.borrow().get(name) {
+                        cost.add(fragment_cost);
+                        continue;
+                    }
+                    if visited_fragments.contains(&name) {
+                        continue;
+                    }
+
+                    if let Some(&fragment) = self.fragments.get(name) {
+                        let TypeCondition::On(fragment_type) = &fragment.type_condition;
+                        visited_fragments.push(name);
+                        let fragment_cost = self.selection_set(
+                            Some(fragment_type.as_str()),
+                            &fragment.selection_set,
+                            visited_fragments,
+                        );
+                        visited_fragments.pop();
+                        self.fragment_costs.borrow_mut().insert(name, fragment_cost);
+                        cost.add(fragment_cost);
+                    }
+                }
+                Selection::InlineFragment(fragment) => {
+                    let fragment_type = match &fragment.type_condition {
+                        Some(TypeCondition::On(fragment_type)) => Some(fragment_type.as_str()),
+                        None => type_name,
+                    };
+                    cost.add(self.selection_set(
+                        fragment_type,
+                        &fragment.selection_set,
+                        visited_fragments,
+                    ));
+                }
+            }
+        }
+
+        cost
+    }
+
+    fn field(
+        &self,
+        type_name: Option<&'a str>,
+        field: &'a Field,
+        visited_fragments: &mut Vec<&'a str>,
+    ) -> Cost {
+        let meta_field = type_name
+            .and_then(|type_name| self.root_node.schema.concrete_type_by_name(type_name))
+            .and_then(|meta_type| meta_type.field_by_name(&field.name));
+        let field_type = meta_field.map(|meta_field| meta_field.field_type.innermost_name());
+
+        let children = self.selection_set(field_type, &field.selection_set, visited_fragments);
+
+        let field_cost =
+            type_name.and_then(|type_name| self.field_costs.get(type_name, &field.name));
+        let mut complexity = field_cost
+            .map(|field_cost| field_cost.cost)
+            .unwrap_or(1)
+            .saturating_add(children.complexity);
+
+        if let Some(field_cost) = field_cost {
+            for argument in &field_cost.multipliers {
+                let multiplier = self.int_argument(field, meta_field, argument).unwrap_or(1);
+                complexity = complexity.saturating_mul(std::cmp::max(multiplier, 1) as usize);
+            }
+        }
+
+        Cost {
+            depth: children.depth + 1,
+            complexity,
+        }
+    }
+
+    fn int_argument(
+        &self,
+        field: &Field,
+        meta_field: Option<&meta::Field<'a, DefaultScalarValue>>,
+        name: &str,
+    ) -> Option<i64> {
+        let value = field
+            .arguments
+            .iter()
+            .find(|(argument_name, _)| argument_name == name)
+            .map(|(_, value)| value);
+
+        match value {
+            Some(Value::Variable(variable)) => self
+                .variables
+                .get(variable)
+                .and_then(|value| value.as_scalar_value::<i32>())
+                .map(|&number| i64::from(number))
+                .or_else(|| match self.variable_defaults.get(variable.as_str()) {
+                    Some(Value::Int(number)) => number.as_i64(),
+                    _ => None,
+                }),
+            Some(Value::Int(number)) => number.as_i64(),
+            Some(_) => None,
+            None => meta_field?
+                .arguments
+                .as_ref()?
+                .iter()
+                .find(|argument| argument.name == name)?
+                .default_value
+                .as_ref()?
+                .as_scalar_value::<i32>()
+                .map(|&number| i64::from(number)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_schema;
+    use juniper::InputValue;
+
+    fn limits(max_depth: Option<usize>, max_complexity: Option<usize>) -> QueryLimits {
+        QueryLimits {
+            max_depth,
+            max_complexity,
+            field_costs: FieldCosts::new()
+                .field("Query", "users", FieldCost::new(1).multiplied_by("limit"))
+                .field("User", "friends", FieldCost::new(1).multiplied_by("first")),
+        }
+    }
+
+    fn check(query: &str, limits: &QueryLimits) -> Result<(), String> {
+        check_with_variables(query, None, &Variables::new(), limits)
+    }
+
+    fn check_with_variables(
+        query: &str,
+        operation_name: Option<&str>,
+        variables: &Variables,
+        limits: &QueryLimits,
+    ) -> Result<(), String> {
+        check_limits(
+            &test_schema::schema(),
+            query,
+            operation_name,
+            variables,
+            limits,
+        )
+    }
+
+    fn complexity_error(complexity: usize, max_complexity: usize) -> Result<(), String> {
+        Err(format!(
+            "Query is too complex: it has a complexity of {} but the maximum allowed is {}",
+            complexity, max_complexity
+        ))
+    }
+
+    #[test]
+    fn limits_depth() {
+        let query = "{ users { friends(first: 1) { friends(first: 1) { id } } } }";
+
+        assert_eq!(check(query, &limits(Some(4), None)), Ok(()));
+        assert_eq!(
+            check(query, &limits(Some(3), None)),
+            Err("Query is too deep: it has a depth of 4 but the maximum allowed is 3".to_string())
+        );
+    }
+
+    #[test]
+    fn multiplies_complexity_by_arguments() {
+        // friends: (1 + 1) * 2 = 4, users: (1 + 1 + 4) * 3 = 18
+        let query = "{ users(limit: 3) { id friends(first: 2) { id } } }";
+
+        assert_eq!(check(query, &limits(None, Some(18))), Ok(()));
+        assert_eq!(
+            check(query, &limits(None, Some(17))),
+            complexity_error(18, 17)
+        );
+    }
+
+    #[test]
+    fn uses_argument_defaults_from_the_schema() {
+        // users: (1 + 1) * 10
+        assert_eq!(
+            check("{ users { id } }", &limits(None, Some(19))),
+            complexity_error(20, 19)
+        );
+    }
+
+    #[test]
+    fn uses_variables_and_their_defaults() {
+        let query = "query Users($limit: Int = 5) { users(limit: $limit) { id } }";
+        let mut variables = Variables::new();
+
+        assert_eq!(
+            check_with_variables(query, None, &variables, &limits(None, Some(9))),
+            complexity_error(10, 9)
+        );
+
+        variables.insert("limit".to_string(), InputValue::scalar(50));
+        assert_eq!(
+            check_with_variables(query, None, &variables, &limits(None, Some(99))),
+            complexity_error(100, 99)
+        );
+    }
+
+    #[test]
+    fn counts_negative_and_zero_multipliers_as_one() {
+        // friends: (1 + 1) * 1 = 2, users: (1 + 2) * 3 = 9
+        for first in &[-1, 0] {
+            let query = format!(
+                "{{ users(limit: 3) {{ friends(first: {}) {{ id }} }} }}",
+                first
+            );
+            assert_eq!(
+                check(&query, &limits(None, Some(8))),
+                complexity_error(9, 8)
+            );
+        }
+    }
+
+    #[test]
+    fn follows_fragments_once() {
+        let query = "
+            { users(limit: 2) { ...UserFields } }
+            fragment UserFields on User { id ... on User { name } ...UserFields }
+        ";
+
+        // users: (1 + 1 + 1) * 2
+        assert_eq!(check(query, &limits(None, Some(5))), complexity_error(6, 5));
+    }
+
+    #[test]
+    fn walks_each_fragment_once() {
+        // Each fragment spreads the next one twice, which would take 2^40 steps to walk.
+        let mut query = "{ user(id: 1) { ...F0 } }".to_string();
+        for index in 0..40 {
+            query.push_str(&format!(
+                " fragment F{0} on User {{ ...F{1} friends {{ ...F{1} }} }}",
+                index,
+                index + 1
+            ));
+        }
+        query.push_str(" fragment F40 on User { id }");
+
+        let err = check(&query, &limits(Some(10), None)).unwrap_err();
+        assert_eq!(
+            err,
+            "Query is too deep: it has a depth of 42 but the maximum allowed is 10"
+        );
+    }
+
+    #[test]
+    fn checks_the_named_operation() {
+        let query = "
+            query Small { user(id: 1) { id } }
+            query Large { users(limit: 100) { id } }
+        ";
+        let limits = limits(None, Some(10));
+
+        assert_eq!(
+            check_with_variables(query, Some("Small"), &Variables::new(), &limits),
+            Ok(())
+        );
+        assert_eq!(
+            check_with_variables(query, Some("Large"), &Variables::new(), &limits),
+            complexity_error(200, 10)
+        );
+    }
+
+    #[test]
+    fn rejects_queries_it_cannot_analyse() {
+        let limits = limits(Some(10), None);
+
+        assert!(check("{ users { id }", &limits)
+            .unwrap_err()
+            .starts_with("Failed to parse query: "));
+        assert_eq!(
+            check_with_variables(
+                "query A { users { id } }",
+                Some("B"),
+                &Variables::new(),
+                &limits
+            ),
+            Err("Unknown operation \"B\"".to_string())
+        );
+        assert_eq!(
+            check("query A { users { id } } query B { users { id } }", &limits),
+            Err(
+                "An operation name is required when the query contains multiple operations"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("fragment F on User { id }", &limits),
+            Err("Query doesn't contain an operation".to_string())
+        );
+    }
+
+    #[test]
+    fn does_nothing_without_limits() {
+        assert_eq!(check("not even a query", &limits(None, None)), Ok(()));
+    }
+}
@@ -0,0 +1,110 @@
+//! [Apollo Tracing](https://github.com/apollographql/apollo-tracing) in the `tracing` field of
+//! the response's `extensions`.
+//!
+//! By default a request gets a trace when it has an `X-Apollo-Tracing` header, except in
+//! production where tracing is disabled. Set `GraphqlApp::apollo_tracing` to change that.
+//!
+//! juniper doesn't have a way to hook into field resolution, so only the resolvers of the
+//! query and mutation types' fields, and of nested fields that call [`crate::time_resolver`],
+//! are in the trace. Their paths are made of response keys, but without list indices. The
+//! `parsing` and `validation` phases are the checks this crate does before handing the query to
+//! juniper, and `execution` covers everything juniper does.
+
+use crate::instrumentation::ResolverTiming;
+use serde_json::json;
+use std::time::{Duration, Instant, SystemTime};
+
+pub const APOLLO_TRACING_HEADER: &str = "X-Apollo-Tracing";
+
+/// When to include a trace in responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApolloTracing {
+    Disabled,
+    /// Only for requests with an `X-Apollo-Tracing` header.
+    OnRequest,
+    Always,
+}
+
+impl ApolloTracing {
+    pub(crate) fn is_enabled(&self, requested: bool) -> bool {
+        match self {
+            ApolloTracing::Disabled => false,
+            ApolloTracing::OnRequest => requested,
+            ApolloTracing::Always => true,
+        }
+    }
+}
+
+/// Times the phases of a request.
+pub(crate) struct Trace {
+    start_time: SystemTime,
+    started: Instant,
+    parsing: Option<(Instant, Duration)>,
+    validation: Option<(Instant, Duration)>,
+    resolvers: Vec<ResolverTiming>,
+}
+
+impl Trace {
+    pub(crate) fn start() -> Self {
+        Trace {
+            start_time: SystemTime::now(),
+            started: Instant::now(),
+            parsing: None,
+            validation: None,
+            resolvers: Vec::new(),
+        }
+    }
+
+    pub(crate) fn parsing(&mut self, started: Instant) {
+        self.parsing = Some((started, started.elapsed()));
+    }
+
+    pub(crate) fn validation(&mut self, started: Instant) {
+        self.validation = Some((started, started.elapsed()));
+    }
+
+    pub(crate) fn resolvers(&mut self, resolvers: Vec<ResolverTiming>) {
+        self.resolvers = resolvers;
+    }
+
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        let duration = self.started.elapsed();
+        let phase = |phase: Option<(Instant, Duration)>| {
+            phase.map(|(started, duration)| {
+                json!({
+                    "startOffset": nanos(started - self.started),
+                    "duration": nanos(duration),
+                })
+            })
+        };
+
+        let resolvers = self
+            .resolvers
+            .iter()
+            .map(|resolver| {
+                json!({
+                    "path": resolver.path,
+                    "parentType": resolver.parent_type,
+                    "fieldName": resolver.field_name,
+                    "returnType": resolver.return_type,
+                    "startOffset": nanos(resolver.started - self.started),
+                    "duration": nanos(resolver.duration),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "version": 1,
+            "startTime": humantime::format_rfc3339_millis(self.start_time).to_string(),
+            "endTime": humantime::format_rfc3339_millis(self.start_time + duration).to_string(),
+            "duration": nanos(duration),
+            "parsing": phase(self.parsing),
+            "validation": phase(self.validation),
+            "execution": { "resolvers": resolvers },
+        })
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + u64::from(duration.subsec_nanos())
+}
@@ -0,0 +1,1054 @@
+//! Field authorization declared with `@auth` directives in the schema file.
+//!
+//! ```graphql
+//! type Query {
+//!   # @auth(requires: ADMIN)
+//!   users: [User!]
+//!   me: User @auth(requires: [USER, VERIFIED])
+//! }
+//! ```
+//!
+//! The rules are read at startup from the file returned by `GraphqlApp::schema_file`, which is
+//! usually the file given to `graphql_schema_from_file!`. juniper-from-schema rejects
+//! directives it doesn't know, so in schemas it compiles the directive is written in a comment
+//! on the field's line or on the lines directly above it. Every rule is checked against the
+//! schema when the app starts.
+//!
+//! Before a query runs, the context is asked whether each requirement is met through the
+//! [`Authorizer`] trait. A field selected through an interface or union is forbidden if any of
+//! the types it could resolve to forbids it, and rules on an interface field apply to the types
+//! implementing it. When a field has multiple requirements all of them must be met.
+//!
+//! Forbidden fields are never resolved. Nullable ones are `null` in the response and come with
+//! a `FORBIDDEN` error. An operation that selects a forbidden non-null field is rejected, since
+//! the field can't be replaced by `null`. Queries that can't be parsed are rejected too, rather
+//! than run without the rules.
+
+use crate::errors::ErrorCode;
+use graphql_parser::query::{
+    Definition, Document, FragmentDefinition, OperationDefinition, Selection, SelectionSet,
+    TypeCondition,
+};
+use graphql_parser::schema::{self, Directive, Value};
+use juniper::{meta::MetaType, GraphQLType, RootNode};
+use serde_json::json;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Decides whether the current request meets the requirements of `@auth` directives.
+///
+/// Implemented by the context. The default denies everything, so apps that don't use `@auth`
+/// can implement it without any methods.
+pub trait Authorizer {
+    fn is_authorized(&self, requirement: &str) -> bool {
+        let _ = requirement;
+        false
+    }
+}
+
+/// Suffix of the alias given to fields that are replaced because they're forbidden. Clients
+/// can't use aliases ending with it.
+const FORBIDDEN_SUFFIX: &str = "__gimme_forbidden";
+
+/// Name given to an anonymous operation when the rewritten query is printed.
+const ANONYMOUS_OPERATION_NAME: &str = "__gimme_anonymous";
+
+/// Requirements of each field, keyed by type name and field name.
+#[derive(Default)]
+pub(crate) struct AuthorizationRules {
+    fields: HashMap<(String, String), Vec<String>>,
+}
+
+impl AuthorizationRules {
+    pub(crate) fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        AuthorizationRules::parse(&contents)
+            .map_err(|err| format!("failed to load {}: {}", path.display(), err))
+    }
+
+    fn parse(source: &str) -> Result<Self, String> {
+        let document = graphql_parser::parse_schema(source).map_err(|err| format!("{}", err))?;
+        let lines = source.lines().collect::<Vec<_>>();
+
+        let mut rules = AuthorizationRules::default();
+        for definition in &document.definitions {
+            let (type_name, fields) = match definition {
+                schema::Definition::TypeDefinition(schema::TypeDefinition::Object(object)) => {
+                    (&object.name, &object.fields)
+                }
+                schema::Definition::TypeDefinition(schema::TypeDefinition::Interface(
+                    interface,
+                )) => (&interface.name, &interface.fields),
+                schema::Definition::TypeExtension(schema::TypeExtension::Object(object)) => {
+                    (&object.name, &object.fields)
+                }
+                schema::Definition::TypeExtension(schema::TypeExtension::Interface(interface)) => {
+                    (&interface.name, &interface.fields)
+                }
+                _ => continue,
+            };
+
+            for field in fields {
+                let mut directives = field
+                    .directives
+                    .iter()
+                    .filter(|directive| directive.name == "auth")
+                    .cloned()
+                    .collect::<Vec<_>>();
+                directives.extend(comment_directives(
+                    &lines,
+                    field.position.line,
+                    type_name,
+                    &field.name,
+                )?);
+
+                for directive in directives {
+                    let requires = directive
+                        .arguments
+                        .iter()
+                        .find(|(name, _)| name == "requires")
+                        .map(|(_, value)| value)
+                        .ok_or_else(|| {
+                            format!(
+                                "@auth on {}.{} is missing the 'requires' argument",
+                                type_name, field.name
+                            )
+                        })?;
+
+                    let requirements = rules
+                        .fields
+                        .entry((type_name.clone(), field.name.clone()))
+                        .or_insert_with(Vec::new);
+                    match requires {
+                        Value::List(values) => {
+                            for value in values {
+                                requirements.push(requirement(value, type_name, &field.name)?);
+                            }
+                        }
+                        value => requirements.push(requirement(value, type_name, &field.name)?),
+                    }
+                }
+            }
+        }
+
+        Ok(rules)
+    }
+
+    /// Panics if a rule refers to a type or field that isn't in the schema.
+    pub(crate) fn validate<Query, Mutation>(&self, root_node: &RootNode<Query, Mutation>)
+    where
+        Query: GraphQLType<TypeInfo = ()>,
+        Mutation: GraphQLType<TypeInfo = ()>,
+    {
+        let mut errors = self
+            .fields
+            .keys()
+            .filter(|(type_name, field_name)| {
+                root_node
+                    .schema
+                    .concrete_type_by_name(type_name)
+                    .and_then(|meta_type| meta_type.field_by_name(field_name))
+                    .is_none()
+            })
+            .map(|(type_name, field_name)| format!("{}.{}", type_name, field_name))
+            .collect::<Vec<_>>();
+
+        if !errors.is_empty() {
+            errors.sort();
+            panic!(
+                "@auth directives refer to fields that aren't in the schema: {}",
+                errors.join(", ")
+            );
+        }
+    }
+
+    /// Rewrite the query so fields the authorizer doesn't allow are replaced by `__typename`
+    /// under a marked alias. Variables and fragments only the replaced fields used are removed,
+    /// as juniper rejects unused ones.
+    ///
+    /// Returns `None` if nothing is forbidden, and an error if the operation can't run.
+    pub(crate) fn restrict<Query, Mutation, A>(
+        &self,
+        root_node: &RootNode<Query, Mutation>,
+        query: &str,
+        authorizer: &A,
+    ) -> Result<Option<String>, Rejection>
+    where
+        Query: GraphQLType<TypeInfo = ()>,
+        Mutation: GraphQLType<TypeInfo = ()>,
+        A: Authorizer,
+    {
+        if self.fields.is_empty() {
+            return Ok(None);
+        }
+
+        let mut document = graphql_parser::parse_query(query).map_err(|err| Rejection {
+            code: ErrorCode::BadUserInput,
+            message: format!("Failed to parse the query: {}", err),
+        })?;
+        let used_before = Usage::of(&document);
+
+        let restrictor = Restrictor {
+            root_node,
+            rules: self,
+            authorizer,
+            decisions: RefCell::new(HashMap::new()),
+        };
+
+        let query_type = root_node.schema.concrete_query_type().name();
+        let mutation_type = root_node
+            .schema
+            .concrete_mutation_type()
+            .and_then(|mutation_type| mutation_type.name());
+
+        let mut restricted = false;
+        for definition in &mut document.definitions {
+            let (type_name, selection_set) = match definition {
+                Definition::Operation(OperationDefinition::SelectionSet(selection_set)) => {
+                    (query_type, selection_set)
+                }
+                Definition::Operation(OperationDefinition::Query(query)) => {
+                    (query_type, &mut query.selection_set)
+                }
+                Definition::Operation(OperationDefinition::Mutation(mutation)) => {
+                    (mutation_type, &mut mutation.selection_set)
+                }
+                Definition::Operation(OperationDefinition::Subscription(_)) => continue,
+                Definition::Fragment(fragment) => {
+                    let TypeCondition::On(type_name) = &fragment.type_condition;
+                    let type_name = type_name.clone();
+                    restricted |=
+                        restrictor.selection_set(Some(&type_name), &mut fragment.selection_set)?;
+                    continue;
+                }
+            };
+            restricted |= restrictor.selection_set(type_name, selection_set)?;
+        }
+
+        if !restricted {
+            return Ok(None);
+        }
+
+        let used_after = Usage::of(&document);
+        let unused = |before: &HashSet<String>, after: &HashSet<String>, name: &String| {
+            before.contains(name) && !after.contains(name)
+        };
+        let operation_count = document
+            .definitions
+            .iter()
+            .filter(|definition| matches!(definition, Definition::Operation(_)))
+            .count();
+        for (index, definition) in document.definitions.iter_mut().enumerate() {
+            let (name, variable_definitions) = match definition {
+                Definition::Operation(OperationDefinition::Query(query)) => {
+                    (&mut query.name, &mut query.variable_definitions)
+                }
+                Definition::Operation(OperationDefinition::Mutation(mutation)) => {
+                    (&mut mutation.name, &mut mutation.variable_definitions)
+                }
+                _ => continue,
+            };
+            variable_definitions.retain(|variable| {
+                !unused(
+                    &used_before.variables[index],
+                    &used_after.variables[index],
+                    &variable.name,
+                )
+            });
+            // graphql_parser only prints the variables of named operations. A lone operation
+            // still runs without an operation name once it has one.
+            if name.is_none() && !variable_definitions.is_empty() && operation_count == 1 {
+                *name = Some(ANONYMOUS_OPERATION_NAME.to_string());
+            }
+        }
+        document.definitions.retain(|definition| match definition {
+            Definition::Fragment(fragment) => !unused(
+                &used_before.fragments,
+                &used_after.fragments,
+                &fragment.name,
+            ),
+            Definition::Operation(_) => true,
+        });
+
+        Ok(Some(document.to_string()))
+    }
+}
+
+/// The variables each definition of a document uses, which is empty for fragments, and the
+/// fragments any operation spreads. Both include what's used through other fragments.
+struct Usage {
+    variables: Vec<HashSet<String>>,
+    fragments: HashSet<String>,
+}
+
+impl Usage {
+    fn of(document: &Document) -> Self {
+        let fragments = document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                Definition::Fragment(fragment) => Some((fragment.name.as_str(), fragment)),
+                Definition::Operation(_) => None,
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut usage = Usage {
+            variables: Vec::new(),
+            fragments: HashSet::new(),
+        };
+        for definition in &document.definitions {
+            let mut variables = HashSet::new();
+            let operation = match definition {
+                Definition::Operation(OperationDefinition::SelectionSet(selection_set)) => {
+                    Some((&[][..], selection_set))
+                }
+                Definition::Operation(OperationDefinition::Query(query)) => {
+                    Some((&query.directives[..], &query.selection_set))
+                }
+                Definition::Operation(OperationDefinition::Mutation(mutation)) => {
+                    Some((&mutation.directives[..], &mutation.selection_set))
+                }
+                Definition::Operation(OperationDefinition::Subscription(subscription)) => {
+                    Some((&subscription.directives[..], &subscription.selection_set))
+                }
+                Definition::Fragment(_) => None,
+            };
+            if let Some((directives, selection_set)) = operation {
+                let mut spread = HashSet::new();
+                directive_variables(directives, &mut variables);
+                selection_set_usage(selection_set, &fragments, &mut variables, &mut spread);
+                usage.fragments.extend(spread);
+            }
+            usage.variables.push(variables);
+        }
+        usage
+    }
+}
+
+fn selection_set_usage(
+    selection_set: &SelectionSet,
+    fragments: &HashMap<&str, &FragmentDefinition>,
+    variables: &mut HashSet<String>,
+    spread: &mut HashSet<String>,
+) {
+    for selection in &selection_set.items {
+        match selection {
+            Selection::Field(field) => {
+                for (_, value) in &field.arguments {
+                    value_variables(value, variables);
+                }
+                directive_variables(&field.directives, variables);
+                selection_set_usage(&field.selection_set, fragments, variables, spread);
+            }
+            Selection::FragmentSpread(fragment_spread) => {
+                directive_variables(&fragment_spread.directives, variables);
+                if !spread.insert(fragment_spread.fragment_name.clone()) {
+                    continue;
+                }
+                if let Some(fragment) = fragments.get(fragment_spread.fragment_name.as_str()) {
+                    directive_variables(&fragment.directives, variables);
+                    selection_set_usage(&fragment.selection_set, fragments, variables, spread);
+                }
+            }
+            Selection::InlineFragment(fragment) => {
+                directive_variables(&fragment.directives, variables);
+                selection_set_usage(&fragment.selection_set, fragments, variables, spread);
+            }
+        }
+    }
+}
+
+fn directive_variables(directives: &[Directive], variables: &mut HashSet<String>) {
+    for directive in directives {
+        for (_, value) in &directive.arguments {
+            value_variables(value, variables);
+        }
+    }
+}
+
+fn value_variables(value: &Value, variables: &mut HashSet<String>) {
+    match value {
+        Value::Variable(name) => {
+            variables.insert(name.clone());
+        }
+        Value::List(values) => {
+            for value in values {
+                value_variables(value, variables);
+            }
+        }
+        Value::Object(fields) => {
+            for value in fields.values() {
+                value_variables(value, variables);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `@auth` directives written in comments, on the field's line or on the comment lines
+/// directly above it. `line` starts at 1.
+fn comment_directives(
+    lines: &[&str],
+    line: usize,
+    type_name: &str,
+    field_name: &str,
+) -> Result<Vec<Directive>, String> {
+    let mut comments = Vec::new();
+    if let Some(comment) = lines
+        .get(line - 1)
+        .and_then(|field_line| field_line.find('#').map(|index| &field_line[index + 1..]))
+    {
+        comments.push(comment);
+    }
+    for comment_line in lines[..line - 1].iter().rev() {
+        match comment_line.trim_start().strip_prefix('#') {
+            Some(comment) => comments.push(comment),
+            None => break,
+        }
+    }
+
+    let mut directives = Vec::new();
+    for comment in comments {
+        let comment = comment.trim();
+        if !comment.starts_with("@auth") {
+            continue;
+        }
+
+        let document = graphql_parser::parse_schema(&format!("type T {{ f: Int {} }}", comment))
+            .map_err(|err| {
+                format!(
+                    "@auth comment on {}.{} doesn't parse: {}",
+                    type_name, field_name, err
+                )
+            })?;
+        if let Some(schema::Definition::TypeDefinition(schema::TypeDefinition::Object(object))) =
+            document.definitions.into_iter().next()
+        {
+            directives.extend(object.fields.into_iter().flat_map(|field| field.directives));
+        }
+    }
+
+    Ok(directives)
+}
+
+fn requirement(value: &Value, type_name: &str, field_name: &str) -> Result<String, String> {
+    match value {
+        Value::Enum(value) | Value::String(value) => Ok(value.clone()),
+        _ => Err(format!(
+            "@auth on {}.{} must require an enum value, a string or a list of those",
+            type_name, field_name
+        )),
+    }
+}
+
+/// Why an operation was rejected before running.
+pub(crate) struct Rejection {
+    code: ErrorCode,
+    message: String,
+}
+
+impl Rejection {
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        json!({
+            "errors": [{ "message": self.message, "extensions": { "code": self.code.as_str() } }]
+        })
+    }
+}
+
+struct Restrictor<'a, Query, Mutation, A>
+where
+    Query: GraphQLType<TypeInfo = ()>,
+    Mutation: GraphQLType<TypeInfo = ()>,
+{
+    root_node: &'a RootNode<'a, Query, Mutation>,
+    rules: &'a AuthorizationRules,
+    authorizer: &'a A,
+    decisions: RefCell<HashMap<String, bool>>,
+}
+
+impl<Query, Mutation, A> Restrictor<'_, Query, Mutation, A>
+where
+    Query: GraphQLType<TypeInfo = ()>,
+    Mutation: GraphQLType<TypeInfo = ()>,
+    A: Authorizer,
+{
+    /// Returns whether any field was replaced.
+    fn selection_set(
+        &self,
+        type_name: Option<&str>,
+        selection_set: &mut SelectionSet,
+    ) -> Result<bool, Rejection> {
+        let mut restricted = false;
+
+        for selection in &mut selection_set.items {
+            match selection {
+                Selection::Field(field) => {
+                    if let Some(alias) = &field.alias {
+                        if alias.ends_with(FORBIDDEN_SUFFIX) {
+                            return Err(Rejection {
+                                code: ErrorCode::BadUserInput,
+                                message: format!(
+                                    "Aliases ending with \"{}\" are reserved",
+                                    FORBIDDEN_SUFFIX
+                                ),
+                            });
+                        }
+                    }
+
+                    let meta_type = type_name.and_then(|type_name| {
+                        self.root_node.schema.concrete_type_by_name(type_name)
+                    });
+                    let meta_field =
+                        meta_type.and_then(|meta_type| meta_type.field_by_name(&field.name));
+
+                    if let Some(meta_type) = meta_type {
+                        if !self.is_allowed(meta_type, &field.name) {
+                            if meta_field
+                                .is_some_and(|meta_field| meta_field.field_type.is_non_null())
+                            {
+                                return Err(Rejection {
+                                    code: ErrorCode::Forbidden,
+                                    message: format!(
+                                        "Not authorized to query non-null field \"{}.{}\"",
+                                        meta_type.name().unwrap_or_default(),
+                                        field.name
+                                    ),
+                                });
+                            }
+
+                            let key = field.alias.take().unwrap_or_else(|| field.name.clone());
+                            field.alias = Some(format!("{}{}", key, FORBIDDEN_SUFFIX));
+                            field.name = "__typename".to_string();
+                            field.arguments.clear();
+                            field.selection_set.items.clear();
+                            restricted = true;
+                            continue;
+                        }
+                    }
+
+                    let field_type = meta_field
+                        .map(|meta_field| meta_field.field_type.innermost_name().to_string());
+                    restricted |=
+                        self.selection_set(field_type.as_deref(), &mut field.selection_set)?;
+                }
+                Selection::FragmentSpread(_) => {}
+                Selection::InlineFragment(fragment) => {
+                    let fragment_type = match &fragment.type_condition {
+                        Some(TypeCondition::On(fragment_type)) => Some(fragment_type.clone()),
+                        None => type_name.map(|type_name| type_name.to_string()),
+                    };
+                    restricted |=
+                        self.selection_set(fragment_type.as_deref(), &mut fragment.selection_set)?;
+                }
+            }
+        }
+
+        Ok(restricted)
+    }
+
+    /// Whether the field is allowed on every concrete type `meta_type` can be.
+    fn is_allowed(&self, meta_type: &MetaType, field_name: &str) -> bool {
+        let concrete_types = if meta_type.is_abstract() {
+            self.root_node.schema.possible_types(meta_type)
+        } else {
+            vec![meta_type]
+        };
+
+        let mut type_names = meta_type.name().into_iter().collect::<Vec<_>>();
+        for concrete_type in concrete_types {
+            type_names.extend(concrete_type.name());
+            if let MetaType::Object(object) = concrete_type {
+                type_names.extend(object.interface_names.iter().map(|name| name.as_str()));
+            }
+        }
+
+        type_names
+            .into_iter()
+            .filter_map(|type_name| {
+                self.rules
+                    .fields
+                    .get(&(type_name.to_string(), field_name.to_string()))
+            })
+            .flatten()
+            .all(|requirement| {
+                *self
+                    .decisions
+                    .borrow_mut()
+                    .entry(requirement.clone())
+                    .or_insert_with(|| self.authorizer.is_authorized(requirement))
+            })
+    }
+}
+
+/// Replace the placeholders of forbidden fields with `null`, returning a `FORBIDDEN` error for
+/// each of them.
+pub(crate) fn null_forbidden_fields(data: &mut serde_json::Value) -> Vec<serde_json::Value> {
+    let mut errors = Vec::new();
+    null_forbidden_fields_at(data, &mut Vec::new(), &mut errors);
+    errors
+}
+
+fn null_forbidden_fields_at(
+    value: &mut serde_json::Value,
+    path: &mut Vec<serde_json::Value>,
+    errors: &mut Vec<serde_json::Value>,
+) {
+    match value {
+        serde_json::Value::Object(object) => {
+            let forbidden = object
+                .keys()
+                .filter(|key| key.ends_with(FORBIDDEN_SUFFIX))
+                .cloned()
+                .collect::<Vec<_>>();
+            for alias in forbidden {
+                object.remove(&alias);
+                let key = alias[..alias.len() - FORBIDDEN_SUFFIX.len()].to_string();
+                path.push(json!(key));
+                errors.push(json!({
+                    "message": "Forbidden",
+                    "path": path.clone(),
+                    "extensions": { "code": ErrorCode::Forbidden.as_str() },
+                }));
+                path.pop();
+                object.insert(key, serde_json::Value::Null);
+            }
+
+            for (key, value) in object.iter_mut() {
+                path.push(json!(key));
+                null_forbidden_fields_at(value, path, errors);
+                path.pop();
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for (index, value) in values.iter_mut().enumerate() {
+                path.push(json!(index));
+                null_forbidden_fields_at(value, path, errors);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use juniper::{EmptyMutation, Executor, FieldResult, Variables};
+    use juniper_from_schema::graphql_schema;
+
+    graphql_schema! {
+        schema {
+            query: Query
+        }
+
+        type Query {
+            me: User! @juniper(ownership: "owned")
+            user(id: Int!): User @juniper(ownership: "owned")
+            node: Node! @juniper(ownership: "owned")
+            search: [SearchResult!]! @juniper(ownership: "owned")
+        }
+
+        interface Node {
+            id: Int! @juniper(ownership: "owned")
+        }
+
+        type User implements Node {
+            id: Int! @juniper(ownership: "owned")
+            email: String @juniper(ownership: "owned")
+            secret: String! @juniper(ownership: "owned")
+            friend(id: Int!): User @juniper(ownership: "owned")
+        }
+
+        type Post implements Node {
+            id: Int! @juniper(ownership: "owned")
+            draft: String @juniper(ownership: "owned")
+        }
+
+        union SearchResult = User | Post
+    }
+
+    const RULES: &str = "
+        type User implements Node {
+          id: Int!
+          email: String @auth(requires: ADMIN)
+          # @auth(requires: [ADMIN, \"owner\"])
+          secret: String!
+          friend(id: Int!): User @auth(requires: ADMIN)
+        }
+
+        type Post implements Node {
+          id: Int!
+          draft: String # @auth(requires: EDITOR)
+        }
+
+        interface Node {
+          id: Int!
+        }
+    ";
+
+    pub struct Context {
+        granted: Vec<&'static str>,
+    }
+
+    impl juniper::Context for Context {}
+
+    impl Authorizer for Context {
+        fn is_authorized(&self, requirement: &str) -> bool {
+            self.granted.contains(&requirement)
+        }
+    }
+
+    pub struct Query;
+
+    impl QueryFields for Query {
+        fn field_me(
+            &self,
+            _: &Executor<'_, Context>,
+            _: &QueryTrail<'_, User, Walked>,
+        ) -> FieldResult<User> {
+            Ok(User)
+        }
+
+        fn field_user(
+            &self,
+            _: &Executor<'_, Context>,
+            _: &QueryTrail<'_, User, Walked>,
+            _: i32,
+        ) -> FieldResult<Option<User>> {
+            Ok(Some(User))
+        }
+
+        fn field_node(
+            &self,
+            _: &Executor<'_, Context>,
+            _: &QueryTrail<'_, Node, Walked>,
+        ) -> FieldResult<Node> {
+            Ok(Node::from(Post))
+        }
+
+        fn field_search(
+            &self,
+            _: &Executor<'_, Context>,
+            _: &QueryTrail<'_, SearchResult, Walked>,
+        ) -> FieldResult<Vec<SearchResult>> {
+            Ok(vec![SearchResult::from(User), SearchResult::from(Post)])
+        }
+    }
+
+    pub struct User;
+
+    impl UserFields for User {
+        fn field_id(&self, _: &Executor<'_, Context>) -> FieldResult<i32> {
+            Ok(1)
+        }
+
+        fn field_email(&self, _: &Executor<'_, Context>) -> FieldResult<Option<String>> {
+            Ok(Some("user@example.com".to_string()))
+        }
+
+        fn field_secret(&self, _: &Executor<'_, Context>) -> FieldResult<String> {
+            Ok("secret".to_string())
+        }
+
+        fn field_friend(
+            &self,
+            _: &Executor<'_, Context>,
+            _: &QueryTrail<'_, User, Walked>,
+            _: i32,
+        ) -> FieldResult<Option<User>> {
+            Ok(Some(User))
+        }
+    }
+
+    pub struct Post;
+
+    impl PostFields for Post {
+        fn field_id(&self, _: &Executor<'_, Context>) -> FieldResult<i32> {
+            Ok(2)
+        }
+
+        fn field_draft(&self, _: &Executor<'_, Context>) -> FieldResult<Option<String>> {
+            Ok(Some("draft".to_string()))
+        }
+    }
+
+    fn rules() -> AuthorizationRules {
+        AuthorizationRules::parse(RULES).unwrap()
+    }
+
+    /// Run the query the way `request` does, returning the data and errors.
+    fn run(
+        query: &str,
+        granted: Vec<&'static str>,
+    ) -> Result<(serde_json::Value, Vec<serde_json::Value>), serde_json::Value> {
+        run_with_variables(query, Variables::new(), granted)
+    }
+
+    fn run_with_variables(
+        query: &str,
+        variables: Variables,
+        granted: Vec<&'static str>,
+    ) -> Result<(serde_json::Value, Vec<serde_json::Value>), serde_json::Value> {
+        let root_node = RootNode::new(Query, EmptyMutation::<Context>::new());
+        let context = Context { granted };
+        let restricted = rules()
+            .restrict(&root_node, query, &context)
+            .map_err(|rejection| rejection.to_json())?;
+
+        let (data, errors) = juniper::execute(
+            restricted.as_ref().map_or(query, |query| query.as_str()),
+            None,
+            &root_node,
+            &variables,
+            &context,
+        )
+        .unwrap();
+        assert!(errors.is_empty());
+
+        let mut data = json!(data);
+        let errors = if restricted.is_some() {
+            null_forbidden_fields(&mut data)
+        } else {
+            Vec::new()
+        };
+        Ok((data, errors))
+    }
+
+    #[test]
+    fn rules_are_read_from_directives_and_comments() {
+        let rules = rules();
+
+        let requirements = |type_name: &str, field_name: &str| {
+            rules
+                .fields
+                .get(&(type_name.to_string(), field_name.to_string()))
+                .cloned()
+        };
+        assert_eq!(
+            requirements("User", "email"),
+            Some(vec!["ADMIN".to_string()])
+        );
+        assert_eq!(
+            requirements("User", "secret"),
+            Some(vec!["ADMIN".to_string(), "owner".to_string()])
+        );
+        assert_eq!(
+            requirements("Post", "draft"),
+            Some(vec!["EDITOR".to_string()])
+        );
+        assert_eq!(
+            requirements("User", "friend"),
+            Some(vec!["ADMIN".to_string()])
+        );
+        assert_eq!(requirements("User", "id"), None);
+        assert_eq!(rules.fields.len(), 4);
+    }
+
+    #[test]
+    fn invalid_rules_fail_to_load() {
+        let err = AuthorizationRules::parse("type User { email: String @auth(role: ADMIN) }")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            "@auth on User.email is missing the 'requires' argument"
+        );
+
+        let err =
+            AuthorizationRules::parse("type User {\n  # @auth(requires: 1)\n  email: String\n}")
+                .err()
+                .unwrap();
+        assert_eq!(
+            err,
+            "@auth on User.email must require an enum value, a string or a list of those"
+        );
+    }
+
+    #[test]
+    fn comments_elsewhere_are_ignored() {
+        let rules = AuthorizationRules::parse(
+            "type User {\n  # @auth(requires: ADMIN)\n\n  email: String # not @auth\n}",
+        )
+        .unwrap();
+
+        assert!(rules.fields.is_empty());
+    }
+
+    #[test]
+    fn allowed_fields_are_untouched() {
+        let (data, errors) = run("{ me { id email } }", vec!["ADMIN"]).unwrap();
+
+        assert_eq!(
+            data,
+            json!({ "me": { "id": 1, "email": "user@example.com" } })
+        );
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn forbidden_nullable_field_is_null_with_an_error() {
+        let (data, errors) = run("{ me { id mail: email } }", vec![]).unwrap();
+
+        assert_eq!(data, json!({ "me": { "id": 1, "mail": null } }));
+        assert_eq!(
+            errors,
+            vec![json!({
+                "message": "Forbidden",
+                "path": ["me", "mail"],
+                "extensions": { "code": "FORBIDDEN" },
+            })]
+        );
+    }
+
+    #[test]
+    fn forbidden_non_null_field_rejects_the_operation() {
+        let err = run("{ me { id secret } }", vec!["ADMIN"]).unwrap_err();
+
+        assert_eq!(
+            err,
+            json!({ "errors": [{
+                "message": "Not authorized to query non-null field \"User.secret\"",
+                "extensions": { "code": "FORBIDDEN" },
+            }] })
+        );
+        assert!(run("{ me { secret } }", vec!["ADMIN", "owner"]).is_ok());
+    }
+
+    #[test]
+    fn rules_apply_through_unions() {
+        let (data, errors) = run(
+            "{ search { ... on Post { draft } ... on User { id } } }",
+            vec![],
+        )
+        .unwrap();
+
+        assert_eq!(data, json!({ "search": [{ "id": 1 }, { "draft": null }] }));
+        assert_eq!(errors[0]["path"], json!(["search", 1, "draft"]));
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn rules_of_implementing_types_apply_through_interfaces() {
+        let rules = AuthorizationRules::parse("type Post { id: Int! # @auth(requires: EDITOR)\n }")
+            .unwrap();
+        let root_node = RootNode::new(Query, EmptyMutation::<Context>::new());
+        let context = Context { granted: vec![] };
+
+        let err = rules
+            .restrict(&root_node, "{ node { id } }", &context)
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.message,
+            "Not authorized to query non-null field \"Node.id\""
+        );
+    }
+
+    #[test]
+    fn rules_on_interfaces_apply_to_implementing_types() {
+        let rules = AuthorizationRules::parse("interface Node { id: Int! @auth(requires: ADMIN) }")
+            .unwrap();
+        let root_node = RootNode::new(Query, EmptyMutation::<Context>::new());
+        let context = Context { granted: vec![] };
+
+        assert!(rules
+            .restrict(&root_node, "{ me { id } }", &context)
+            .is_err());
+        assert!(rules
+            .restrict(&root_node, "{ me { email } }", &context)
+            .ok()
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn rules_apply_in_fragments() {
+        let (data, errors) = run(
+            "{ me { ...UserFields } } fragment UserFields on User { id email }",
+            vec![],
+        )
+        .unwrap();
+
+        assert_eq!(data, json!({ "me": { "id": 1, "email": null } }));
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn reserved_aliases_are_rejected() {
+        let err = run("{ me { id__gimme_forbidden: id } }", vec!["ADMIN"]).unwrap_err();
+
+        assert_eq!(
+            err["errors"][0]["message"],
+            "Aliases ending with \"__gimme_forbidden\" are reserved"
+        );
+        assert_eq!(err["errors"][0]["extensions"]["code"], "BAD_USER_INPUT");
+    }
+
+    #[test]
+    fn variables_only_forbidden_fields_used_are_removed() {
+        let mut variables = Variables::new();
+        variables.insert("id".to_string(), juniper::InputValue::scalar(2));
+        let (data, errors) = run_with_variables(
+            "query($id: Int!) { me { id friend(id: $id) { id } } }",
+            variables,
+            vec![],
+        )
+        .unwrap();
+
+        assert_eq!(data, json!({ "me": { "id": 1, "friend": null } }));
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn fragments_only_forbidden_fields_spread_are_removed() {
+        let (data, errors) = run(
+            "{ me { friend(id: 2) { ...Friend } } }
+            fragment Friend on User { ...Ids }
+            fragment Ids on User { id }",
+            vec![],
+        )
+        .unwrap();
+
+        assert_eq!(data, json!({ "me": { "friend": null } }));
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn variables_and_fragments_used_elsewhere_are_kept() {
+        let mut variables = Variables::new();
+        variables.insert("id".to_string(), juniper::InputValue::scalar(2));
+        let (data, errors) = run_with_variables(
+            "query($id: Int!) {
+              user(id: $id) { ...Ids }
+              me { ...Ids friend(id: $id) { ...Ids } }
+            }
+            fragment Ids on User { id }",
+            variables,
+            vec![],
+        )
+        .unwrap();
+
+        assert_eq!(
+            data,
+            json!({ "user": { "id": 1 }, "me": { "id": 1, "friend": null } })
+        );
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn unparsable_queries_are_rejected() {
+        let root_node = RootNode::new(Query, EmptyMutation::<Context>::new());
+        let context = Context { granted: vec![] };
+
+        let err = rules()
+            .restrict(&root_node, "{ me { id }", &context)
+            .err()
+            .unwrap();
+
+        assert_eq!(err.code.as_str(), "BAD_USER_INPUT");
+        assert!(err.message.starts_with("Failed to parse the query: "));
+    }
+}
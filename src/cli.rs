@@ -0,0 +1,225 @@
+//! A command line interface for apps, to use in `main` in place of `run_graphql_app`.
+//!
+//! ```ignore
+//! pub fn main() {
+//!     gimme_graphql::cli::run(App);
+//! }
+//! ```
+//!
+//! The commands are:
+//!
+//! - `serve`: run the server, which is also what happens without a command.
+//! - `print-schema`: print the schema in the GraphQL schema language.
+//! - `check <FILE>...`: check that query documents pass the validation juniper runs before
+//!   executing them, without resolving anything, so no database is needed. Values of variables
+//!   aren't known, so they aren't checked.
+//! - `migrate`: run pending Diesel migrations and create the tables the persisted query store
+//!   needs.
+//! - `routes`: list the routes the server serves.
+//!
+//! `--port` and `--database-url` override `GraphqlApp::port` and `GraphqlApp::database_url`.
+
+use crate::{instrumentation, sdl, validation, Adapter, GraphqlApp};
+use clap::{value_t, AppSettings, Arg, ArgMatches, SubCommand};
+use diesel::Connection;
+use diesel_migrations::MigrationConnection;
+use juniper::GraphQLType;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+
+/// Settings given on the command line, which take precedence over the app's.
+#[derive(Default)]
+pub(crate) struct Overrides {
+    pub(crate) port: Option<u16>,
+    pub(crate) database_url: Option<String>,
+}
+
+/// Run the command given on the command line, exiting with status 1 if it fails.
+pub fn run<App>(app: App)
+where
+    App: GraphqlApp,
+    App::Connection: MigrationConnection,
+    App::Query: GraphQLType<TypeInfo = ()> + Default,
+    App::Mutation: GraphQLType<TypeInfo = ()> + Default,
+{
+    crate::init();
+
+    let matches = clap::App::new("gimme-graphql")
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(
+            Arg::with_name("port")
+                .long("port")
+                .takes_value(true)
+                .global(true)
+                .help("The port to serve on"),
+        )
+        .arg(
+            Arg::with_name("database-url")
+                .long("database-url")
+                .takes_value(true)
+                .global(true)
+                .help("The database to connect to"),
+        )
+        .subcommand(SubCommand::with_name("serve").about("Run the server (the default)"))
+        .subcommand(
+            SubCommand::with_name("print-schema")
+                .about("Print the schema in the GraphQL schema language"),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Check that query documents are valid against the schema")
+                .long_about(
+                    "Check that query documents pass the validation juniper runs before executing \
+                     them, without executing them. Values of variables aren't known, so they \
+                     aren't checked.",
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .value_name("FILE")
+                        .required(true)
+                        .multiple(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("migrate")
+                .about("Run pending database migrations and prepare the persisted query store")
+                .arg(
+                    Arg::with_name("migrations-dir")
+                        .long("migrations-dir")
+                        .takes_value(true)
+                        .help("Defaults to the nearest `migrations` directory"),
+                ),
+        )
+        .subcommand(SubCommand::with_name("routes").about("List the routes the server serves"))
+        .get_matches();
+
+    let (command, command_matches) = match matches.subcommand() {
+        (command, Some(command_matches)) => (command, command_matches),
+        _ => ("serve", &matches),
+    };
+    let overrides = overrides(command_matches, &matches);
+
+    let result = match command {
+        "print-schema" => {
+            print!("{}", schema_sdl::<App>());
+            Ok(())
+        }
+        "check" => check::<App>(command_matches.values_of("files").into_iter().flatten()),
+        "migrate" => migrate::<App>(
+            &app,
+            &overrides,
+            command_matches.value_of("migrations-dir").map(Path::new),
+        ),
+        "routes" => {
+            print_routes(&app);
+            Ok(())
+        }
+        _ => {
+            crate::serve(app, &overrides);
+            Ok(())
+        }
+    };
+
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+/// Global flags can be given before or after the command.
+fn overrides(command_matches: &ArgMatches, matches: &ArgMatches) -> Overrides {
+    let port = [command_matches, matches]
+        .iter()
+        .find(|matches| matches.is_present("port"))
+        .map(|matches| value_t!(matches, "port", u16).unwrap_or_else(|err| err.exit()));
+    let database_url = command_matches
+        .value_of("database-url")
+        .or_else(|| matches.value_of("database-url"))
+        .map(|database_url| database_url.to_string());
+
+    Overrides { port, database_url }
+}
+
+fn schema_sdl<App>() -> String
+where
+    App: GraphqlApp,
+    App::Query: GraphQLType<TypeInfo = ()> + Default,
+    App::Mutation: GraphQLType<TypeInfo = ()> + Default,
+{
+    let root_node = instrumentation::schema(App::Query::default(), App::Mutation::default());
+    sdl::print_schema(&root_node)
+}
+
+fn check<'a, App>(files: impl Iterator<Item = &'a str>) -> Result<(), String>
+where
+    App: GraphqlApp,
+    App::Query: GraphQLType<TypeInfo = ()>,
+    App::Mutation: GraphQLType<TypeInfo = ()>,
+{
+    let mut checked = 0;
+    let mut errors = Vec::new();
+    for file in files {
+        let source =
+            fs::read_to_string(file).map_err(|err| format!("failed to read {}: {}", file, err))?;
+        match graphql_parser::parse_query(&source) {
+            Ok(document) => errors.extend(
+                validation::validate_with_juniper::<App::Query, App::Mutation>(&document)
+                    .into_iter()
+                    .map(|error| format!("{}: {}", file, error)),
+            ),
+            Err(err) => errors.push(format!("{}: failed to parse: {}", file, err)),
+        }
+        checked += 1;
+    }
+
+    if errors.is_empty() {
+        println!("{} documents are valid", checked);
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+fn migrate<App>(
+    app: &App,
+    overrides: &Overrides,
+    migrations_dir: Option<&Path>,
+) -> Result<(), String>
+where
+    App: GraphqlApp,
+    App::Connection: MigrationConnection,
+{
+    let database_url = overrides
+        .database_url
+        .clone()
+        .unwrap_or_else(|| app.database_url());
+    let connection = App::Connection::establish(&database_url)
+        .map_err(|err| format!("failed to connect to database: {}", err))?;
+    let migrations_dir = match migrations_dir {
+        Some(migrations_dir) => migrations_dir.to_path_buf(),
+        None => diesel_migrations::find_migrations_directory()
+            .map_err(|err| format!("failed to find migrations directory: {}", err))?,
+    };
+
+    diesel_migrations::run_pending_migrations_in_directory(
+        &connection,
+        &migrations_dir,
+        &mut io::stdout(),
+    )
+    .map_err(|err| format!("failed to run migrations: {}", err))?;
+
+    let database_connection_pool = crate::create_database_connection_pool(app, database_url);
+    app.persisted_query_store(&database_connection_pool)
+        .migrate()
+}
+
+fn print_routes<App: GraphqlApp>(app: &App) {
+    for route in App::Adapter::new().routes(app) {
+        println!(
+            "{:<7} {:<30} {}",
+            route.method, route.path, route.description
+        );
+    }
+}
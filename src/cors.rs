@@ -0,0 +1,354 @@
+//! Cross-origin resource sharing, applied to every route of both adapters.
+//!
+//! ```ignore
+//! fn cors(&self) -> Option<Cors> {
+//!     Some(
+//!         Cors::new()
+//!             .allow_origin("https://example.com")
+//!             .allow_origin_pattern("https://*.example.com")
+//!             .allow_credentials(true),
+//!     )
+//! }
+//! ```
+
+use std::time::Duration;
+
+pub struct Cors {
+    allowed_origins: Vec<AllowedOrigin>,
+    allowed_methods: Vec<String>,
+    allowed_headers: Vec<String>,
+    allow_credentials: bool,
+    max_age: Option<Duration>,
+}
+
+enum AllowedOrigin {
+    Any,
+    Exact(String),
+    Pattern(String),
+}
+
+impl Cors {
+    /// No origins are allowed until one is added. `GET`, `POST` and `OPTIONS` are allowed along
+    /// with the `Content-Type` and `Authorization` headers.
+    pub fn new() -> Self {
+        Cors {
+            allowed_origins: Vec::new(),
+            allowed_methods: vec!["GET".to_string(), "POST".to_string(), "OPTIONS".to_string()],
+            allowed_headers: vec!["Content-Type".to_string(), "Authorization".to_string()],
+            allow_credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Allow every origin. Panics if credentials are allowed, since any site could then make
+    /// requests with the user's cookies.
+    pub fn allow_any_origin(mut self) -> Self {
+        self.allowed_origins.push(AllowedOrigin::Any);
+        self.check_credentials();
+        self
+    }
+
+    /// Allow an origin such as `https://example.com`.
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        self.allowed_origins
+            .push(AllowedOrigin::Exact(origin.to_string()));
+        self
+    }
+
+    /// Allow origins matching a pattern where `*` matches a single DNS label, such as
+    /// `https://*.example.com`. That matches `https://api.example.com` but not
+    /// `https://a.b.example.com`.
+    pub fn allow_origin_pattern(mut self, pattern: &str) -> Self {
+        self.allowed_origins
+            .push(AllowedOrigin::Pattern(pattern.to_string()));
+        self
+    }
+
+    /// Replace the allowed methods.
+    pub fn allow_methods(mut self, methods: &[&str]) -> Self {
+        self.allowed_methods = methods.iter().map(|method| method.to_string()).collect();
+        self
+    }
+
+    /// Replace the allowed request headers.
+    pub fn allow_headers(mut self, headers: &[&str]) -> Self {
+        self.allowed_headers = headers.iter().map(|header| header.to_string()).collect();
+        self
+    }
+
+    /// Panics if any origin is allowed. See `allow_any_origin`.
+    pub fn allow_credentials(mut self, allow_credentials: bool) -> Self {
+        self.allow_credentials = allow_credentials;
+        self.check_credentials();
+        self
+    }
+
+    /// How long browsers may cache the result of a preflight request.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn check_credentials(&self) {
+        let allows_any_origin = self
+            .allowed_origins
+            .iter()
+            .any(|allowed| matches!(allowed, AllowedOrigin::Any));
+        if self.allow_credentials && allows_any_origin {
+            panic!("CORS can't allow credentials together with any origin, list the allowed origins instead");
+        }
+    }
+
+    fn is_allowed(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|allowed| match allowed {
+            AllowedOrigin::Any => true,
+            AllowedOrigin::Exact(allowed) => allowed == origin,
+            AllowedOrigin::Pattern(pattern) => matches_pattern(pattern, origin),
+        })
+    }
+
+    /// Headers to add to a regular response.
+    ///
+    /// The request's origin is echoed back rather than using `*`, since browsers reject `*`
+    /// when credentials are allowed.
+    pub(crate) fn response_headers(&self, origin: Option<&str>) -> Vec<(&'static str, String)> {
+        let mut headers = vec![("Vary", "Origin".to_string())];
+
+        let origin = match origin {
+            Some(origin) if self.is_allowed(origin) => origin,
+            _ => return headers,
+        };
+
+        headers.push(("Access-Control-Allow-Origin", origin.to_string()));
+        if self.allow_credentials {
+            headers.push(("Access-Control-Allow-Credentials", "true".to_string()));
+        }
+        headers
+    }
+
+    /// Whether the method and headers of `Access-Control-Request-Method` and
+    /// `Access-Control-Request-Headers` are all allowed.
+    fn is_request_allowed(
+        &self,
+        request_method: Option<&str>,
+        request_headers: Option<&str>,
+    ) -> bool {
+        let is_method_allowed = request_method.is_some_and(|request_method| {
+            self.allowed_methods
+                .iter()
+                .any(|method| method == request_method.trim())
+        });
+
+        let are_headers_allowed = request_headers
+            .unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .filter(|header| !header.is_empty())
+            .all(|header| {
+                self.allowed_headers
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(header))
+            });
+
+        is_method_allowed && are_headers_allowed
+    }
+
+    /// Headers to add to the response of a preflight request, given the values of its
+    /// `Origin`, `Access-Control-Request-Method` and `Access-Control-Request-Headers` headers.
+    ///
+    /// The allow headers are left out if the method or any header isn't allowed, which makes
+    /// the browser fail the preflight.
+    pub(crate) fn preflight_headers(
+        &self,
+        origin: Option<&str>,
+        request_method: Option<&str>,
+        request_headers: Option<&str>,
+    ) -> Vec<(&'static str, String)> {
+        if !self.is_request_allowed(request_method, request_headers) {
+            return vec![("Vary", "Origin".to_string())];
+        }
+
+        let mut headers = self.response_headers(origin);
+
+        if origin.is_some_and(|origin| self.is_allowed(origin)) {
+            headers.push((
+                "Access-Control-Allow-Methods",
+                self.allowed_methods.join(", "),
+            ));
+            headers.push((
+                "Access-Control-Allow-Headers",
+                self.allowed_headers.join(", "),
+            ));
+            if let Some(max_age) = self.max_age {
+                headers.push(("Access-Control-Max-Age", max_age.as_secs().to_string()));
+            }
+        }
+
+        headers
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors::new()
+    }
+}
+
+/// Match `value` against `pattern`, where each `*` stands for one DNS label: one or more
+/// letters, digits or hyphens.
+fn matches_pattern(pattern: &str, value: &str) -> bool {
+    match pattern.find('*') {
+        None => pattern == value,
+        Some(index) => {
+            let (literal, rest) = (&pattern[..index], &pattern[index + 1..]);
+            if !value.starts_with(literal) {
+                return false;
+            }
+            let value = &value[literal.len()..];
+            let label_len = value
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+                .unwrap_or(value.len());
+
+            (1..=label_len).any(|len| matches_pattern(rest, &value[len..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header<'a>(headers: &'a [(&'static str, String)], name: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|(header, _)| *header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn wildcard_matches_a_single_label() {
+        let pattern = "https://*.example.com";
+
+        assert!(matches_pattern(pattern, "https://api.example.com"));
+        assert!(matches_pattern(pattern, "https://my-app.example.com"));
+        assert!(!matches_pattern(pattern, "https://a.b.example.com"));
+        assert!(!matches_pattern(pattern, "https://.example.com"));
+        assert!(!matches_pattern(pattern, "https://example.com"));
+        assert!(!matches_pattern(pattern, "https://evil.com/.example.com"));
+        assert!(!matches_pattern(pattern, "https://evil.com?.example.com"));
+        assert!(!matches_pattern(
+            pattern,
+            "https://api.example.com.evil.com"
+        ));
+        assert!(!matches_pattern(pattern, "http://api.example.com"));
+    }
+
+    #[test]
+    fn wildcard_backtracks_within_a_label() {
+        let pattern = "https://*-preview.example.com";
+
+        assert!(matches_pattern(
+            pattern,
+            "https://pr-12-preview.example.com"
+        ));
+        assert!(!matches_pattern(pattern, "https://-preview.example.com"));
+        assert!(!matches_pattern(pattern, "https://a.b-preview.example.com"));
+    }
+
+    #[test]
+    fn wildcards_in_several_places() {
+        let pattern = "https://*.*.example.com:*";
+
+        assert!(matches_pattern(pattern, "https://a.b.example.com:8080"));
+        assert!(!matches_pattern(pattern, "https://a.example.com:8080"));
+        assert!(!matches_pattern(pattern, "https://a.b.example.com:"));
+    }
+
+    #[test]
+    fn pattern_without_wildcard_must_match_exactly() {
+        assert!(matches_pattern(
+            "https://example.com",
+            "https://example.com"
+        ));
+        assert!(!matches_pattern(
+            "https://example.com",
+            "https://example.com.evil.com"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "can't allow credentials together with any origin")]
+    fn credentials_with_any_origin_are_rejected() {
+        Cors::new().allow_any_origin().allow_credentials(true);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't allow credentials together with any origin")]
+    fn any_origin_with_credentials_is_rejected() {
+        Cors::new().allow_credentials(true).allow_any_origin();
+    }
+
+    #[test]
+    fn allowed_origin_is_echoed_with_credentials() {
+        let cors = Cors::new()
+            .allow_origin_pattern("https://*.example.com")
+            .allow_credentials(true);
+
+        let headers = cors.response_headers(Some("https://api.example.com"));
+        assert_eq!(
+            header(&headers, "Access-Control-Allow-Origin"),
+            Some("https://api.example.com")
+        );
+        assert_eq!(
+            header(&headers, "Access-Control-Allow-Credentials"),
+            Some("true")
+        );
+
+        let headers = cors.response_headers(Some("https://evil.com"));
+        assert_eq!(headers, vec![("Vary", "Origin".to_string())]);
+    }
+
+    #[test]
+    fn preflight_allows_configured_methods_and_headers() {
+        let cors = Cors::new()
+            .allow_origin("https://example.com")
+            .max_age(Duration::from_secs(600));
+
+        let headers = cors.preflight_headers(
+            Some("https://example.com"),
+            Some("POST"),
+            Some("content-type, Authorization"),
+        );
+
+        assert_eq!(
+            header(&headers, "Access-Control-Allow-Origin"),
+            Some("https://example.com")
+        );
+        assert_eq!(
+            header(&headers, "Access-Control-Allow-Methods"),
+            Some("GET, POST, OPTIONS")
+        );
+        assert_eq!(
+            header(&headers, "Access-Control-Allow-Headers"),
+            Some("Content-Type, Authorization")
+        );
+        assert_eq!(header(&headers, "Access-Control-Max-Age"), Some("600"));
+    }
+
+    #[test]
+    fn preflight_rejects_other_methods_and_headers() {
+        let cors = Cors::new().allow_origin("https://example.com");
+        let origin = Some("https://example.com");
+        let rejected = vec![("Vary", "Origin".to_string())];
+
+        assert_eq!(
+            cors.preflight_headers(origin, Some("DELETE"), None),
+            rejected
+        );
+        assert_eq!(cors.preflight_headers(origin, None, None), rejected);
+        assert_eq!(
+            cors.preflight_headers(origin, Some("POST"), Some("Content-Type, X-Debug")),
+            rejected
+        );
+    }
+}
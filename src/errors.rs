@@ -0,0 +1,190 @@
+//! Error codes, and masking of unexpected errors.
+//!
+//! Errors returned by resolvers are sent to clients with an `extensions.code`. Errors made with
+//! [`field_error`] are considered safe to show:
+//!
+//! ```ignore
+//! let user = users::table.find(id).first::<User>(db).optional()?;
+//! user.ok_or_else(|| field_error(ErrorCode::NotFound, "User not found"))
+//! ```
+//!
+//! Every other error, like a Diesel error raised with `?`, is replaced by
+//! `Internal server error` with the code `INTERNAL` and a correlation ID, which is the request's
+//! ID. That includes errors that set a `code` extension themselves, since only `field_error`
+//! can add the marker that is checked. The original message is logged along with the same ID.
+//! Set `GraphqlApp::expose_internal_errors` to send the original messages instead, which is the
+//! default in development.
+
+use juniper::{FieldError, Object, Value};
+use lazy_static::lazy_static;
+use serde_json::json;
+use std::fmt;
+use uuid::Uuid;
+
+/// Extension that marks errors made with `field_error`. It's removed before errors are sent.
+const SAFE_MARKER: &str = "__gimmeSafe";
+
+lazy_static! {
+    /// The value of the marker, random so that it can't be set by anything but `field_error`.
+    static ref SAFE_MARKER_VALUE: String = Uuid::new_v4().to_simple().to_string();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    Internal,
+    NotFound,
+    Unauthenticated,
+    Forbidden,
+    BadUserInput,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::Internal => "INTERNAL",
+            ErrorCode::NotFound => "NOT_FOUND",
+            ErrorCode::Unauthenticated => "UNAUTHENTICATED",
+            ErrorCode::Forbidden => "FORBIDDEN",
+            ErrorCode::BadUserInput => "BAD_USER_INPUT",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An error whose message is safe to show to clients.
+pub fn field_error<M: fmt::Display>(code: ErrorCode, message: M) -> FieldError {
+    let mut extensions = Object::with_capacity(2);
+    extensions.add_field("code", Value::scalar(code.as_str().to_string()));
+    extensions.add_field(SAFE_MARKER, Value::scalar(SAFE_MARKER_VALUE.clone()));
+    FieldError::new(message, Value::Object(extensions))
+}
+
+/// A response body with a single error.
+pub(crate) fn error_response(
+    code: ErrorCode,
+    message: &str,
+    correlation_id: Option<&str>,
+) -> serde_json::Value {
+    let mut extensions = json!({ "code": code.as_str() });
+    if let Some(correlation_id) = correlation_id {
+        extensions["correlationId"] = json!(correlation_id);
+    }
+    json!({ "errors": [{ "message": message, "extensions": extensions }] })
+}
+
+/// Replace errors not made with `field_error` by a generic internal error, logging the
+/// original.
+pub(crate) fn mask_errors(
+    errors: &mut [serde_json::Value],
+    expose_internal_errors: bool,
+    correlation_id: &str,
+) {
+    for error in errors {
+        let marker = error
+            .get_mut("extensions")
+            .and_then(|extensions| extensions.as_object_mut())
+            .and_then(|extensions| extensions.remove(SAFE_MARKER));
+        if marker.is_some_and(|marker| marker == json!(*SAFE_MARKER_VALUE)) {
+            continue;
+        }
+
+        log::error!(
+            "Internal error (correlation id {}): {}",
+            correlation_id,
+            error
+        );
+
+        if let serde_json::Value::Object(error) = error {
+            if expose_internal_errors {
+                let extensions = error.entry("extensions").or_insert_with(|| json!({}));
+                if let serde_json::Value::Object(extensions) = extensions {
+                    extensions.insert("code".to_string(), json!(ErrorCode::Internal.as_str()));
+                    extensions.insert("correlationId".to_string(), json!(correlation_id));
+                }
+            } else {
+                error.insert("message".to_string(), json!("Internal server error"));
+                error.insert(
+                    "extensions".to_string(),
+                    json!({
+                        "code": ErrorCode::Internal.as_str(),
+                        "correlationId": correlation_id,
+                    }),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use juniper::graphql_value;
+
+    fn serialized(error: FieldError) -> serde_json::Value {
+        let error = juniper::ExecutionError::new(
+            juniper::parser::SourcePosition::new(0, 0, 0),
+            &["user"],
+            error,
+        );
+        json!(error)
+    }
+
+    #[test]
+    fn field_errors_pass_through_without_the_marker() {
+        let mut errors = vec![serialized(field_error(
+            ErrorCode::NotFound,
+            "User not found",
+        ))];
+
+        mask_errors(&mut errors, false, "abc");
+
+        assert_eq!(errors[0]["message"], "User not found");
+        assert_eq!(errors[0]["extensions"], json!({ "code": "NOT_FOUND" }));
+    }
+
+    #[test]
+    fn errors_with_their_own_code_are_masked() {
+        let mut errors = vec![serialized(FieldError::new(
+            "connection refused by db.internal:5432",
+            graphql_value!({ "code": "NOT_FOUND" }),
+        ))];
+
+        mask_errors(&mut errors, false, "abc");
+
+        assert_eq!(errors[0]["message"], "Internal server error");
+        assert_eq!(
+            errors[0]["extensions"],
+            json!({ "code": "INTERNAL", "correlationId": "abc" })
+        );
+    }
+
+    #[test]
+    fn forged_markers_are_masked() {
+        let mut errors = vec![serialized(FieldError::new(
+            "secret",
+            graphql_value!({ "code": "NOT_FOUND", "__gimmeSafe": "guess" }),
+        ))];
+
+        mask_errors(&mut errors, false, "abc");
+
+        assert_eq!(errors[0]["message"], "Internal server error");
+    }
+
+    #[test]
+    fn internal_errors_are_kept_when_exposed() {
+        let mut errors = vec![serialized(FieldError::from("connection refused"))];
+
+        mask_errors(&mut errors, true, "abc");
+
+        assert_eq!(errors[0]["message"], "connection refused");
+        assert_eq!(
+            errors[0]["extensions"],
+            json!({ "code": "INTERNAL", "correlationId": "abc" })
+        );
+    }
+}
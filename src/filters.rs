@@ -0,0 +1,659 @@
+//! Filtering and ordering Diesel queries with GraphQL arguments.
+//!
+//! `filter_types!` generates a filter input type and an order input type for the columns of a
+//! table. Each column gets one of the column filters of this module, and the filter type also
+//! gets `and`, `or` and `not` fields to combine filters:
+//!
+//! ```ignore
+//! // main.rs
+//! filter_types! {
+//!     users::table, Pg,
+//!     UserFilter {
+//!         id: IntFilter => users::id,
+//!         name: StringFilter => users::name,
+//!     }
+//!     UserOrderBy {
+//!         id => users::id,
+//!         name => users::name,
+//!     }
+//! }
+//!
+//! fn field_users(
+//!     &self,
+//!     executor: &Executor<'_, Context>,
+//!     _: &QueryTrail<'_, User, Walked>,
+//!     filter: Option<UserFilter>,
+//!     order_by: Option<Vec<UserOrderBy>>,
+//! ) -> FieldResult<Vec<User>> {
+//!     let db = &gimme_graphql::get_connection(&executor.context().db_pool)?;
+//!     let users = filters::apply(
+//!         users::table.into_boxed(),
+//!         filter.as_ref(),
+//!         &order_by.unwrap_or_default(),
+//!     )?
+//!     .load::<User>(db)?;
+//!     Ok(users)
+//! }
+//! ```
+//!
+//! The generated types are registered with juniper like any other Rust type, so use them in the
+//! schema given to `graphql_schema_from_file!` without declaring them:
+//!
+//! ```ignore
+//! type Query {
+//!     users(filter: UserFilter, orderBy: [UserOrderBy!]): [User!]! @juniper(ownership: "owned")
+//! }
+//! ```
+//!
+//! `sdl` prints their declarations, for tools that need the whole schema in a file. Clients
+//! can then query for example:
+//!
+//! ```graphql
+//! {
+//!     users(
+//!         filter: { or: [{ name: { startsWith: "a" } }, { id: { in: [1, 2] } }] }
+//!         orderBy: [{ name: ASC }]
+//!     ) { id }
+//! }
+//! ```
+//!
+//! Set one field of each order item; its position in the list decides precedence. Column filters
+//! only work on columns that aren't nullable.
+//!
+//! `and`, `or` and `not` can be nested at most `MAX_FILTER_DEPTH` levels deep, so a client can't
+//! make the server build an arbitrarily large `WHERE` clause.
+
+use crate::sdl;
+use diesel::backend::Backend;
+use diesel::dsl;
+use diesel::expression::BoxableExpression;
+use diesel::query_builder::BoxedSelectStatement;
+use diesel::query_dsl::methods::{FilterDsl, ThenOrderDsl};
+use diesel::sql_types::{Bool, Double, Integer, Text};
+use diesel::{
+    BoolExpressionMethods, EscapeExpressionMethods, Expression, ExpressionMethods,
+    TextExpressionMethods,
+};
+use fnv::FnvHashMap;
+use juniper::{GraphQLEnum, GraphQLInputObject, GraphQLType, Registry};
+
+/// How deep `and`, `or` and `not` can be nested. A filter without them has a depth of 1.
+pub const MAX_FILTER_DEPTH: usize = 5;
+
+type Page<'a, ST, QS, DB> = BoxedSelectStatement<'a, ST, QS, DB>;
+
+/// A condition on the rows of `QS`.
+pub type Predicate<QS, DB> = Box<dyn BoxableExpression<QS, DB, SqlType = Bool>>;
+
+/// The conditions of a filter type on one column.
+pub trait ColumnFilter<C, QS, DB> {
+    /// `None` if every row matches.
+    fn predicate(&self, column: C) -> Option<Predicate<QS, DB>>;
+}
+
+/// A filter type generated by `filter_types!`.
+pub trait Filter<QS, DB> {
+    /// `None` if every row matches.
+    fn predicate(&self) -> Option<Predicate<QS, DB>>;
+
+    /// How deep `and`, `or` and `not` are nested, counting this filter.
+    fn depth(&self) -> usize;
+}
+
+/// An order type generated by `filter_types!`.
+pub trait OrderBy<QS, DB> {
+    fn order<'a, ST>(&self, query: Page<'a, ST, QS, DB>) -> Page<'a, ST, QS, DB>;
+}
+
+#[derive(GraphQLEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+macro_rules! comparison_filter {
+    ($(#[$attr:meta])* $name:ident($value:ty, $sql_type:ty)) => {
+        $(#[$attr])*
+        #[derive(GraphQLInputObject, Debug, Clone, Default)]
+        pub struct $name {
+            pub eq: Option<$value>,
+            pub ne: Option<$value>,
+            pub gt: Option<$value>,
+            pub gte: Option<$value>,
+            pub lt: Option<$value>,
+            pub lte: Option<$value>,
+            #[graphql(name = "in")]
+            pub in_: Option<Vec<$value>>,
+            pub not_in: Option<Vec<$value>>,
+        }
+
+        impl<C, QS, DB> ColumnFilter<C, QS, DB> for $name
+        where
+            C: Expression<SqlType = $sql_type> + Copy,
+            dsl::Eq<C, $value>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+            dsl::NotEq<C, $value>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+            dsl::Gt<C, $value>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+            dsl::GtEq<C, $value>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+            dsl::Lt<C, $value>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+            dsl::LtEq<C, $value>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+            dsl::EqAny<C, Vec<$value>>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+            dsl::NeAny<C, Vec<$value>>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+            QS: 'static,
+            DB: Backend + 'static,
+        {
+            fn predicate(&self, column: C) -> Option<Predicate<QS, DB>> {
+                all(comparisons!(self, column))
+            }
+        }
+    };
+}
+
+macro_rules! comparisons {
+    ($filter:expr, $column:expr) => {{
+        let filter = $filter;
+        let column = $column;
+        let mut predicates = Vec::<Predicate<QS, DB>>::new();
+        if let Some(value) = &filter.eq {
+            predicates.push(Box::new(column.eq(value.clone())));
+        }
+        if let Some(value) = &filter.ne {
+            predicates.push(Box::new(column.ne(value.clone())));
+        }
+        if let Some(value) = &filter.gt {
+            predicates.push(Box::new(column.gt(value.clone())));
+        }
+        if let Some(value) = &filter.gte {
+            predicates.push(Box::new(column.ge(value.clone())));
+        }
+        if let Some(value) = &filter.lt {
+            predicates.push(Box::new(column.lt(value.clone())));
+        }
+        if let Some(value) = &filter.lte {
+            predicates.push(Box::new(column.le(value.clone())));
+        }
+        if let Some(values) = &filter.in_ {
+            predicates.push(Box::new(column.eq_any(values.clone())));
+        }
+        if let Some(values) = &filter.not_in {
+            predicates.push(Box::new(column.ne_all(values.clone())));
+        }
+        predicates
+    }};
+}
+
+comparison_filter! {
+    /// Conditions on an `Integer` column.
+    IntFilter(i32, Integer)
+}
+
+comparison_filter! {
+    /// Conditions on a `Double` column.
+    FloatFilter(f64, Double)
+}
+
+/// Conditions on a `Text` column.
+#[derive(GraphQLInputObject, Debug, Clone, Default)]
+pub struct StringFilter {
+    pub eq: Option<String>,
+    pub ne: Option<String>,
+    pub gt: Option<String>,
+    pub gte: Option<String>,
+    pub lt: Option<String>,
+    pub lte: Option<String>,
+    #[graphql(name = "in")]
+    pub in_: Option<Vec<String>>,
+    pub not_in: Option<Vec<String>>,
+    /// Matches values containing this.
+    pub contains: Option<String>,
+    /// Matches values starting with this.
+    pub starts_with: Option<String>,
+    /// Matches values ending with this.
+    pub ends_with: Option<String>,
+}
+
+impl<C, QS, DB> ColumnFilter<C, QS, DB> for StringFilter
+where
+    C: Expression<SqlType = Text> + Copy,
+    dsl::Eq<C, String>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+    dsl::NotEq<C, String>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+    dsl::Gt<C, String>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+    dsl::GtEq<C, String>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+    dsl::Lt<C, String>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+    dsl::LtEq<C, String>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+    dsl::EqAny<C, Vec<String>>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+    dsl::NeAny<C, Vec<String>>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+    dsl::Escape<dsl::Like<C, String>>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+    QS: 'static,
+    DB: Backend + 'static,
+{
+    fn predicate(&self, column: C) -> Option<Predicate<QS, DB>> {
+        let mut predicates = comparisons!(self, column);
+        // Not every backend treats backslashes as the escape character by default.
+        let like = |pattern: String| Box::new(column.like(pattern).escape('\\'));
+        if let Some(value) = &self.contains {
+            predicates.push(like(format!("%{}%", escape_like(value))));
+        }
+        if let Some(value) = &self.starts_with {
+            predicates.push(like(format!("{}%", escape_like(value))));
+        }
+        if let Some(value) = &self.ends_with {
+            predicates.push(like(format!("%{}", escape_like(value))));
+        }
+        all(predicates)
+    }
+}
+
+/// Conditions on a `Bool` column.
+#[derive(GraphQLInputObject, Debug, Clone, Default)]
+pub struct BooleanFilter {
+    pub eq: Option<bool>,
+    pub ne: Option<bool>,
+}
+
+impl<C, QS, DB> ColumnFilter<C, QS, DB> for BooleanFilter
+where
+    C: Expression<SqlType = Bool> + Copy,
+    dsl::Eq<C, bool>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+    dsl::NotEq<C, bool>: BoxableExpression<QS, DB, SqlType = Bool> + 'static,
+    QS: 'static,
+    DB: Backend + 'static,
+{
+    fn predicate(&self, column: C) -> Option<Predicate<QS, DB>> {
+        let mut predicates = Vec::<Predicate<QS, DB>>::new();
+        if let Some(value) = self.eq {
+            predicates.push(Box::new(column.eq(value)));
+        }
+        if let Some(value) = self.ne {
+            predicates.push(Box::new(column.ne(value)));
+        }
+        all(predicates)
+    }
+}
+
+/// Filter and order `query`. Fails if the filter is nested deeper than `MAX_FILTER_DEPTH`.
+pub fn apply<'a, ST, QS, DB, F, O>(
+    query: Page<'a, ST, QS, DB>,
+    filter: Option<&F>,
+    order_by: &[O],
+) -> Result<Page<'a, ST, QS, DB>, String>
+where
+    F: Filter<QS, DB>,
+    O: OrderBy<QS, DB>,
+    Page<'a, ST, QS, DB>: FilterDsl<Predicate<QS, DB>, Output = Page<'a, ST, QS, DB>>,
+{
+    if filter.map_or(0, Filter::depth) > MAX_FILTER_DEPTH {
+        return Err(format!(
+            "filter is nested more than {} levels deep",
+            MAX_FILTER_DEPTH
+        ));
+    }
+
+    let mut query = query;
+    if let Some(predicate) = filter.and_then(Filter::predicate) {
+        query = FilterDsl::filter(query, predicate);
+    }
+    Ok(order_by
+        .iter()
+        .fold(query, |query, order| order.order(query)))
+}
+
+/// The SDL of `T` and the input types and enums it uses, such as the types generated by
+/// `filter_types!`.
+pub fn sdl<T>() -> String
+where
+    T: GraphQLType<TypeInfo = ()>,
+{
+    let mut registry = Registry::new(FnvHashMap::default());
+    registry.get_type::<T>(&());
+    sdl::print_input_types(registry.types.values())
+}
+
+/// Generate a filter type and an order type for a table. See the module docs.
+///
+/// ```ignore
+/// filter_types! {
+///     table, backend,
+///     FilterType { field: ColumnFilterType => column, ... }
+///     OrderType { field => column, ... }
+/// }
+/// ```
+#[macro_export]
+macro_rules! filter_types {
+    (
+        $table:ty, $db:ty,
+        $filter:ident { $($filter_field:ident: $column_filter:ident => $filter_column:expr),* $(,)* }
+        $order_by:ident { $($order_field:ident => $order_column:expr),* $(,)* }
+    ) => {
+        #[derive(juniper::GraphQLInputObject, Debug, Clone, Default)]
+        pub struct $filter {
+            $(pub $filter_field: Option<$crate::filters::$column_filter>,)*
+            /// Matches rows matching all of these.
+            pub and: Option<Vec<$filter>>,
+            /// Matches rows matching any of these.
+            pub or: Option<Vec<$filter>>,
+            /// Matches rows not matching this.
+            pub not: Option<Box<$filter>>,
+        }
+
+        impl $crate::filters::Filter<$table, $db> for $filter {
+            fn predicate(&self) -> Option<$crate::filters::Predicate<$table, $db>> {
+                let mut predicates = Vec::new();
+                $(
+                    if let Some(filter) = &self.$filter_field {
+                        predicates.extend($crate::filters::ColumnFilter::predicate(
+                            filter,
+                            $filter_column,
+                        ));
+                    }
+                )*
+                $crate::filters::combine(predicates, &self.and, &self.or, &self.not)
+            }
+
+            fn depth(&self) -> usize {
+                $crate::filters::depth(&self.and, &self.or, &self.not)
+            }
+        }
+
+        #[derive(juniper::GraphQLInputObject, Debug, Clone, Default)]
+        pub struct $order_by {
+            $(pub $order_field: Option<$crate::filters::SortDirection>,)*
+        }
+
+        impl $crate::filters::OrderBy<$table, $db> for $order_by {
+            fn order<'a, ST>(
+                &self,
+                query: diesel::query_builder::BoxedSelectStatement<'a, ST, $table, $db>,
+            ) -> diesel::query_builder::BoxedSelectStatement<'a, ST, $table, $db> {
+                let query = query;
+                $(
+                    let query = $crate::filters::order_by_column(
+                        query,
+                        $order_column,
+                        self.$order_field,
+                    );
+                )*
+                query
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+pub fn combine<F, QS, DB>(
+    mut predicates: Vec<Predicate<QS, DB>>,
+    and: &Option<Vec<F>>,
+    or: &Option<Vec<F>>,
+    not: &Option<Box<F>>,
+) -> Option<Predicate<QS, DB>>
+where
+    F: Filter<QS, DB>,
+    QS: 'static,
+    DB: Backend + 'static,
+{
+    if let Some(and) = and {
+        predicates.extend(and.iter().filter_map(Filter::predicate));
+    }
+
+    if let Some(or) = or {
+        // A filter without a predicate matches every row, and so does the `or` containing it.
+        let any = or.iter().map(Filter::predicate).collect::<Option<Vec<_>>>();
+        if let Some(any) = any {
+            let any =
+                any.into_iter().fold(
+                    None,
+                    |any: Option<Predicate<QS, DB>>, predicate| match any {
+                        Some(any) => Some(Box::new(any.or(predicate)) as Predicate<QS, DB>),
+                        None => Some(predicate),
+                    },
+                );
+            predicates.push(any.unwrap_or_else(none));
+        }
+    }
+
+    if let Some(not) = not {
+        predicates.push(match not.predicate() {
+            Some(predicate) => Box::new(dsl::not(predicate)),
+            None => none(),
+        });
+    }
+
+    all(predicates)
+}
+
+#[doc(hidden)]
+pub fn depth<F, QS, DB>(and: &Option<Vec<F>>, or: &Option<Vec<F>>, not: &Option<Box<F>>) -> usize
+where
+    F: Filter<QS, DB>,
+{
+    let nested = and
+        .iter()
+        .chain(or)
+        .flatten()
+        .chain(not.iter().map(|not| &**not))
+        .map(Filter::depth)
+        .max()
+        .unwrap_or(0);
+    nested + 1
+}
+
+#[doc(hidden)]
+pub fn order_by_column<'a, ST, QS, DB, C>(
+    query: Page<'a, ST, QS, DB>,
+    column: C,
+    direction: Option<SortDirection>,
+) -> Page<'a, ST, QS, DB>
+where
+    C: ExpressionMethods,
+    Page<'a, ST, QS, DB>: ThenOrderDsl<dsl::Asc<C>, Output = Page<'a, ST, QS, DB>>
+        + ThenOrderDsl<dsl::Desc<C>, Output = Page<'a, ST, QS, DB>>,
+{
+    match direction {
+        Some(SortDirection::Asc) => ThenOrderDsl::then_order_by(query, column.asc()),
+        Some(SortDirection::Desc) => ThenOrderDsl::then_order_by(query, column.desc()),
+        None => query,
+    }
+}
+
+fn all<QS, DB>(predicates: Vec<Predicate<QS, DB>>) -> Option<Predicate<QS, DB>>
+where
+    QS: 'static,
+    DB: Backend + 'static,
+{
+    predicates.into_iter().fold(
+        None,
+        |all: Option<Predicate<QS, DB>>, predicate| match all {
+            Some(all) => Some(Box::new(all.and(predicate)) as Predicate<QS, DB>),
+            None => Some(predicate),
+        },
+    )
+}
+
+/// A predicate no row matches.
+fn none<QS, DB: Backend>() -> Predicate<QS, DB> {
+    Box::new(dsl::sql::<Bool>("FALSE"))
+}
+
+/// Escape the wildcards of a `LIKE` pattern.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::debug_query;
+    use diesel::pg::Pg;
+    use diesel::query_dsl::QueryDsl;
+
+    mod schema {
+        table! {
+            users {
+                id -> Integer,
+                name -> Text,
+            }
+        }
+    }
+
+    use self::schema::users;
+
+    filter_types! {
+        users::table, Pg,
+        UserFilter {
+            id: IntFilter => users::id,
+            name: StringFilter => users::name,
+        }
+        UserOrderBy {
+            id => users::id,
+            name => users::name,
+        }
+    }
+
+    fn id(eq: i32) -> UserFilter {
+        UserFilter {
+            id: Some(IntFilter {
+                eq: Some(eq),
+                ..IntFilter::default()
+            }),
+            ..UserFilter::default()
+        }
+    }
+
+    fn sql(filter: &UserFilter) -> String {
+        let query = apply::<_, _, _, _, UserOrderBy>(
+            users::table.select(users::id).into_boxed(),
+            Some(filter),
+            &[],
+        )
+        .unwrap();
+        debug_query::<Pg, _>(&query).to_string()
+    }
+
+    #[test]
+    fn and_combines_with_the_column_filters() {
+        let filter = UserFilter {
+            and: Some(vec![id(1), id(2)]),
+            ..id(3)
+        };
+
+        let sql = sql(&filter);
+        assert!(
+            sql.contains(r#"WHERE "users"."id" = $1 AND "users"."id" = $2 AND "users"."id" = $3"#),
+            "{}",
+            sql
+        );
+    }
+
+    #[test]
+    fn or_matches_any() {
+        let filter = UserFilter {
+            or: Some(vec![id(1), id(2)]),
+            ..UserFilter::default()
+        };
+
+        let sql = sql(&filter);
+        assert!(
+            sql.contains(r#"WHERE ("users"."id" = $1 OR "users"."id" = $2)"#),
+            "{}",
+            sql
+        );
+    }
+
+    #[test]
+    fn an_empty_filter_in_or_matches_every_row() {
+        let filter = UserFilter {
+            or: Some(vec![id(1), UserFilter::default()]),
+            ..UserFilter::default()
+        };
+
+        let sql = sql(&filter);
+        assert!(!sql.contains("WHERE"), "{}", sql);
+    }
+
+    #[test]
+    fn an_empty_or_matches_no_row() {
+        let filter = UserFilter {
+            or: Some(Vec::new()),
+            ..UserFilter::default()
+        };
+
+        let sql = sql(&filter);
+        assert!(sql.contains("WHERE FALSE"), "{}", sql);
+    }
+
+    #[test]
+    fn not_negates() {
+        let filter = UserFilter {
+            not: Some(Box::new(id(1))),
+            ..UserFilter::default()
+        };
+
+        let negated = sql(&filter);
+        assert!(
+            negated.contains(r#"WHERE NOT ("users"."id" = $1)"#),
+            "{}",
+            negated
+        );
+
+        let filter = UserFilter {
+            not: Some(Box::new(UserFilter::default())),
+            ..UserFilter::default()
+        };
+        let negated_empty = sql(&filter);
+        assert!(negated_empty.contains("WHERE FALSE"), "{}", negated_empty);
+    }
+
+    #[test]
+    fn like_patterns_are_escaped_with_an_explicit_escape_character() {
+        let filter = UserFilter {
+            name: Some(StringFilter {
+                contains: Some("50%_off\\".to_string()),
+                ..StringFilter::default()
+            }),
+            ..UserFilter::default()
+        };
+
+        let sql = sql(&filter);
+        assert!(
+            sql.contains(r#""users"."name" LIKE $1 ESCAPE $2"#),
+            "{}",
+            sql
+        );
+        assert!(sql.contains(r#""%50\\%\\_off\\\\%""#), "{}", sql);
+    }
+
+    fn nested(depth: usize) -> UserFilter {
+        (1..depth).fold(id(1), |filter, _| UserFilter {
+            not: Some(Box::new(filter)),
+            ..UserFilter::default()
+        })
+    }
+
+    #[test]
+    fn filters_up_to_the_maximum_depth_are_applied() {
+        let filter = UserFilter {
+            and: Some(vec![id(1), nested(MAX_FILTER_DEPTH - 1)]),
+            ..UserFilter::default()
+        };
+
+        assert_eq!(Filter::<users::table, Pg>::depth(&filter), MAX_FILTER_DEPTH);
+        assert!(
+            apply::<_, _, _, _, UserOrderBy>(users::table.into_boxed(), Some(&filter), &[]).is_ok()
+        );
+    }
+
+    #[test]
+    fn deeper_filters_are_rejected() {
+        let filter = UserFilter {
+            or: Some(vec![id(1), nested(MAX_FILTER_DEPTH)]),
+            ..UserFilter::default()
+        };
+
+        let err = apply::<_, _, _, _, UserOrderBy>(users::table.into_boxed(), Some(&filter), &[])
+            .err()
+            .unwrap();
+        assert_eq!(err, "filter is nested more than 5 levels deep");
+    }
+}
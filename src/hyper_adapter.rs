@@ -1,17 +1,38 @@
-use crate::{Adapter, GraphqlApp, WebFrameworkConfig};
+use crate::access_log::AccessLogEntry;
+use crate::apollo_tracing::APOLLO_TRACING_HEADER;
+#[cfg(feature = "auth")]
+use crate::auth::{AuthError, Claims, JwtAuth};
+use crate::authorization::Authorizer;
+use crate::errors::{self, ErrorCode};
+use crate::ide::{self, Ide};
+use crate::instrumentation::{self, Schema};
+#[cfg(feature = "metrics")]
+use crate::metrics::{self, MetricsEndpoint};
+use crate::request::{
+    ExecutionConfig, GraphqlBatchRequest, GraphqlResponse, OperationSummary, RequestInfo,
+};
+use crate::request_id::{RequestId, REQUEST_ID_HEADER};
+use crate::telemetry::{PoolCheckouts, RequestSpan};
+use crate::upload::{self, UploadConfig, UploadError, Uploads};
+use crate::{enabled_ide, shared_routes, Adapter, GraphqlApp, RouteInfo, WebFrameworkConfig};
 use juniper::GraphQLType;
 
 use diesel::r2d2::ConnectionManager;
-use futures::future;
+use futures::future::{self, Either};
+use futures::stream::{Stream, Wait};
+use futures::Poll;
+use hyper::header::{self, HeaderValue};
 use hyper::rt::{self, Future};
-use hyper::service::service_fn;
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
 use hyper::Method;
 use hyper::Request;
-use hyper::{Body, Response, Server, StatusCode};
-use juniper::EmptyMutation;
-use juniper::RootNode;
-use r2d2::{Pool, PooledConnection};
+use hyper::{Body, Chunk, Response, Server, StatusCode};
+use r2d2::Pool;
+use std::io::{self, Read};
 use std::sync::Arc;
+use std::time::Instant;
+use tracing::Span;
 
 pub use hyper;
 
@@ -36,7 +57,7 @@ where
     Connection: 'static + diesel::Connection,
     Query: 'static + Send + Sync + Default + GraphQLType<TypeInfo = (), Context = Context>,
     Mutation: 'static + Send + Sync + Default + GraphQLType<TypeInfo = (), Context = Context>,
-    Context: 'static + Send + Sync + juniper::Context + CreateContext<Connection>,
+    Context: 'static + Send + Sync + juniper::Context + Authorizer + CreateContext<Connection>,
 {
     type Inner = ();
 
@@ -44,7 +65,7 @@ where
         HyperAdapter { _unit: () }
     }
 
-    fn run<App>(&self, app: App, config: WebFrameworkConfig<Connection>)
+    fn run<App>(&self, _app: App, config: WebFrameworkConfig<Connection>)
     where
         App: GraphqlApp<
             Adapter = Self,
@@ -55,38 +76,143 @@ where
         >,
     {
         let WebFrameworkConfig {
+            access_log,
+            cors,
             database_connection_pool,
-            graphql_path,
-            mount_graphiql_at,
-            mount_graphql_at,
+            graphql_path: _,
+            ide,
+            #[cfg(feature = "auth")]
+            jwt_auth,
+            #[cfg(feature = "metrics")]
+            metrics,
+            mount_graphiql_at: _,
+            mount_graphql_at: _,
             port,
+            profile: _,
+            execution_config,
+            upload_config,
         } = config;
 
         let addr = ([127, 0, 0, 1], port).into();
 
-        let root_node = Arc::new(RootNode::new(Query::default(), Mutation::default()));
+        let root_node = Arc::new(instrumentation::schema(
+            Query::default(),
+            Mutation::default(),
+        ));
+        execution_config.validate(&*root_node);
+        let execution_config = Arc::new(execution_config);
+        let upload_config = Arc::new(upload_config);
+        let ide = Arc::new(ide);
+        let cors = Arc::new(cors);
+        let access_log = Arc::new(access_log);
+        #[cfg(feature = "auth")]
+        let jwt_auth = Arc::new(jwt_auth);
+        #[cfg(feature = "metrics")]
+        let metrics_path = metrics.path;
+        #[cfg(feature = "metrics")]
+        let metrics_endpoint = {
+            let endpoint = Arc::new(MetricsEndpoint::new(database_connection_pool.clone()));
+            match metrics.admin_port {
+                Some(admin_port) => {
+                    metrics::serve_on_admin_port(endpoint, metrics.path, admin_port);
+                    None
+                }
+                None => Some(endpoint),
+            }
+        };
 
-        let new_service = move || {
+        let new_service = make_service_fn(move |socket: &AddrStream| {
+            let remote_addr = socket.remote_addr();
             let root_node = root_node.clone();
             let database_connection_pool = database_connection_pool.clone();
-            service_fn(move |req| -> Box<dyn Future<Item = _, Error = _> + Send> {
+            let execution_config = execution_config.clone();
+            let upload_config = upload_config.clone();
+            let ide = ide.clone();
+            let cors = cors.clone();
+            let access_log = access_log.clone();
+            #[cfg(feature = "auth")]
+            let jwt_auth = jwt_auth.clone();
+            #[cfg(feature = "metrics")]
+            let metrics_endpoint = metrics_endpoint.clone();
+            let handle = move |req: Request<Body>,
+                               request_id: RequestId|
+                  -> Box<
+                dyn Future<Item = Response<Body>, Error = hyper::Error> + Send,
+            > {
                 let root_node = root_node.clone();
 
-                let ctx =
-                    <Context as CreateContext<Connection>>::create(&database_connection_pool, &req);
+                #[cfg(feature = "metrics")]
+                {
+                    if let Some(endpoint) = &metrics_endpoint {
+                        if req.method() == Method::GET && req.uri().path() == metrics_path {
+                            return Box::new(future::ok(metrics_response(endpoint)));
+                        }
+                    }
+                }
+
+                // Like the Rocket adapter, only the GraphQL endpoint requires a token. The IDE
+                // and its assets are public and send the token with their GraphQL requests.
+                #[cfg(feature = "auth")]
+                let req = if req.uri().path() == "/graphql" {
+                    match authenticate(&jwt_auth, req) {
+                        Ok(req) => req,
+                        Err(response) => return Box::new(future::ok(response)),
+                    }
+                } else {
+                    req
+                };
+
+                let request_info = RequestInfo {
+                    id: request_id,
+                    wants_apollo_tracing: req.headers().contains_key(APOLLO_TRACING_HEADER),
+                    pool_checkouts: PoolCheckouts::default(),
+                };
+
+                let ctx = request_info.pool_checkouts.in_scope(|| {
+                    <Context as CreateContext<Connection>>::create(&database_connection_pool, &req)
+                });
 
                 match ctx {
                     Ok(ctx) => {
                         let ctx = Arc::new(ctx);
+                        let multipart_boundary = req
+                            .headers()
+                            .get(header::CONTENT_TYPE)
+                            .and_then(|value| value.to_str().ok())
+                            .and_then(upload::multipart_boundary);
 
-                        match (req.method(), req.uri().path()) {
-                            (&Method::GET, "/") => Box::new(juniper_hyper::graphiql("/graphql")),
-                            (&Method::GET, "/graphql") => {
-                                Box::new(juniper_hyper::graphql(root_node, ctx, req))
-                            }
-                            (&Method::POST, "/graphql") => {
-                                Box::new(juniper_hyper::graphql(root_node, ctx, req))
+                        if req.method() == Method::GET {
+                            if let Some(response) = ide_response(&ide, req.uri().path()) {
+                                return Box::new(future::ok(response));
                             }
+                        }
+
+                        match (req.method(), req.uri().path()) {
+                            (&Method::GET, "/graphql") => Box::new(graphql_get(
+                                root_node,
+                                ctx,
+                                req,
+                                request_info,
+                                execution_config.clone(),
+                            )),
+                            (&Method::POST, "/graphql") => match multipart_boundary {
+                                Some(boundary) => Box::new(graphql_multipart(
+                                    root_node,
+                                    ctx,
+                                    req,
+                                    request_info,
+                                    boundary,
+                                    upload_config.clone(),
+                                    execution_config.clone(),
+                                )),
+                                None => Box::new(graphql_post(
+                                    root_node,
+                                    ctx,
+                                    req,
+                                    request_info,
+                                    execution_config.clone(),
+                                )),
+                            },
                             _ => {
                                 let mut response = Response::new(Body::empty());
                                 *response.status_mut() = StatusCode::NOT_FOUND;
@@ -95,21 +221,474 @@ where
                         }
                     }
                     Err(err) => {
-                        let err_msg = err.to_string();
-                        let mut response = Response::new(Body::from(err_msg));
-                        *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
-                        Box::new(future::ok(response))
+                        log::error!(
+                            "Failed to create context (correlation id {}): {}",
+                            request_info.id,
+                            err
+                        );
+                        let message = if execution_config.expose_internal_errors {
+                            err.to_string()
+                        } else {
+                            "Service unavailable".to_string()
+                        };
+                        let body = errors::error_response(
+                            ErrorCode::Internal,
+                            &message,
+                            Some(request_info.id.as_str()),
+                        );
+                        Box::new(future::ok(json_error_response(
+                            StatusCode::SERVICE_UNAVAILABLE,
+                            body,
+                        )))
                     }
                 }
-            })
-        };
+            };
+
+            Ok::<_, hyper::Error>(service_fn(
+                move |mut req| -> Box<dyn Future<Item = _, Error = _> + Send> {
+                    let started = Instant::now();
+                    let request_id = RequestId::from_header(
+                        req.headers()
+                            .get(REQUEST_ID_HEADER)
+                            .and_then(|value| value.to_str().ok()),
+                    );
+                    req.extensions_mut().insert(request_id.clone());
+                    req.extensions_mut().insert(Uploads::default());
+                    let mut headers = vec![(REQUEST_ID_HEADER, request_id.to_string())];
+
+                    if let Some(cors) = &*cors {
+                        let origin = req
+                            .headers()
+                            .get(header::ORIGIN)
+                            .and_then(|value| value.to_str().ok());
+
+                        if req.method() == Method::OPTIONS
+                            && req
+                                .headers()
+                                .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+                        {
+                            let mut response = Response::new(Body::empty());
+                            *response.status_mut() = StatusCode::NO_CONTENT;
+                            let request_header = |name| {
+                                req.headers()
+                                    .get(name)
+                                    .and_then(|value| value.to_str().ok())
+                            };
+                            headers.extend(cors.preflight_headers(
+                                origin,
+                                request_header(header::ACCESS_CONTROL_REQUEST_METHOD),
+                                request_header(header::ACCESS_CONTROL_REQUEST_HEADERS),
+                            ));
+                            add_headers(&mut response, headers);
+                            return Box::new(future::ok(response));
+                        }
+
+                        headers.extend(cors.response_headers(origin));
+                    }
+
+                    let access_log = access_log.clone();
+                    let method = req.method().clone();
+                    let path = req.uri().path().to_string();
+                    let client_ip = (*access_log).as_ref().and_then(|access_log| {
+                        access_log.client_ip(
+                            Some(remote_addr.ip()),
+                            req.headers()
+                                .get("X-Forwarded-For")
+                                .and_then(|value| value.to_str().ok()),
+                        )
+                    });
+                    let request_span = RequestSpan::new(&request_id);
+                    let response = request_span
+                        .span()
+                        .in_scope(|| handle(req, request_id.clone()));
+                    let response = InSpan {
+                        span: request_span.span().clone(),
+                        inner: response,
+                    };
+                    Box::new(response.map(move |mut response| {
+                        drop(request_span);
+                        let operations = response.extensions_mut().remove::<Operations>();
+                        match &*access_log {
+                            Some(access_log) if path == "/graphql" => {
+                                access_log.log(&AccessLogEntry {
+                                    method: method.as_str(),
+                                    path: &path,
+                                    status: response.status().as_u16(),
+                                    duration: started.elapsed(),
+                                    client_ip,
+                                    request_id: &request_id,
+                                    operations: operations
+                                        .as_ref()
+                                        .map_or(&[][..], |operations| operations.0.as_slice()),
+                                });
+                            }
+                            _ => {}
+                        }
+                        add_headers(&mut response, headers);
+                        response
+                    }))
+                },
+            ))
+        });
 
         let server = Server::bind(&addr)
             .serve(new_service)
             .map_err(|e| panic!("server error: {}", e));
 
-        println!("Listening on http://{}", addr);
+        log::info!("Listening on http://{}", addr);
 
         rt::run(server);
     }
+
+    fn routes<App>(&self, app: &App) -> Vec<RouteInfo>
+    where
+        App: GraphqlApp<
+            Adapter = Self,
+            Connection = Connection,
+            Query = Query,
+            Mutation = Mutation,
+            Context = Context,
+        >,
+    {
+        let mut routes = vec![
+            RouteInfo::new("GET", "/graphql", "GraphQL queries"),
+            RouteInfo::new("POST", "/graphql", "GraphQL queries, mutations and uploads"),
+        ];
+        if enabled_ide(app).is_enabled() {
+            routes.push(RouteInfo::new("GET", "/", "GraphQL IDE"));
+            routes.push(RouteInfo::new(
+                "GET",
+                "/ide-assets/<name>",
+                "GraphQL IDE assets",
+            ));
+        }
+        routes.extend(shared_routes(app));
+        routes
+    }
+}
+
+/// The ID of the request, for use in `CreateContext::create`.
+pub fn request_id(request: &Request<Body>) -> Option<&RequestId> {
+    request.extensions().get::<RequestId>()
+}
+
+/// The files uploaded with the request, for the context to keep. See [`upload`].
+pub fn uploads(request: &Request<Body>) -> Uploads {
+    request
+        .extensions()
+        .get::<Uploads>()
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// The operations of a GraphQL response, passed to the access log in the response's extensions.
+struct Operations(Vec<OperationSummary>);
+
+/// A future that enters a span whenever it's polled, so work done while polling is recorded in
+/// the span.
+struct InSpan<F> {
+    span: Span,
+    inner: F,
+}
+
+impl<F: Future> Future for InSpan<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let _enter = self.span.enter();
+        self.inner.poll()
+    }
+}
+
+/// The verified claims of the request's bearer token, for use in `CreateContext::create`.
+///
+/// Returns `None` for anonymous requests.
+#[cfg(feature = "auth")]
+pub fn claims<T: serde::de::DeserializeOwned>(
+    request: &Request<Body>,
+) -> Result<Option<T>, AuthError> {
+    request
+        .extensions()
+        .get::<Claims>()
+        .map(|claims| claims.deserialize())
+        .transpose()
+}
+
+#[cfg(feature = "auth")]
+#[allow(clippy::result_large_err)]
+fn authenticate(
+    jwt_auth: &Option<JwtAuth>,
+    mut req: Request<Body>,
+) -> Result<Request<Body>, Response<Body>> {
+    let jwt_auth = match jwt_auth {
+        Some(jwt_auth) => jwt_auth,
+        None => return Ok(req),
+    };
+
+    let authorization = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+
+    match jwt_auth.authenticate(authorization) {
+        Ok(Some(claims)) => {
+            req.extensions_mut().insert(claims);
+            Ok(req)
+        }
+        Ok(None) => Ok(req),
+        Err(err) => {
+            let body = errors::error_response(ErrorCode::Unauthenticated, &err.to_string(), None);
+            let mut resp = json_error_response(StatusCode::UNAUTHORIZED, body);
+            resp.headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            Err(resp)
+        }
+    }
+}
+
+fn graphql_get<Query, Mutation, Context>(
+    root_node: Arc<Schema<Query, Mutation>>,
+    ctx: Arc<Context>,
+    req: Request<Body>,
+    request_info: RequestInfo,
+    execution_config: Arc<ExecutionConfig>,
+) -> impl Future<Item = Response<Body>, Error = hyper::Error>
+where
+    Query: 'static + Send + Sync + GraphQLType<TypeInfo = (), Context = Context>,
+    Mutation: 'static + Send + Sync + GraphQLType<TypeInfo = (), Context = Context>,
+    Context: 'static + Send + Sync + Authorizer,
+{
+    match GraphqlBatchRequest::from_query_string(req.uri().query().unwrap_or("")) {
+        Ok(request) => Either::A(execute(
+            root_node,
+            ctx,
+            request,
+            request_info,
+            execution_config,
+        )),
+        Err(err) => Either::B(future::ok(bad_request(err))),
+    }
+}
+
+fn graphql_post<Query, Mutation, Context>(
+    root_node: Arc<Schema<Query, Mutation>>,
+    ctx: Arc<Context>,
+    req: Request<Body>,
+    request_info: RequestInfo,
+    execution_config: Arc<ExecutionConfig>,
+) -> impl Future<Item = Response<Body>, Error = hyper::Error>
+where
+    Query: 'static + Send + Sync + GraphQLType<TypeInfo = (), Context = Context>,
+    Mutation: 'static + Send + Sync + GraphQLType<TypeInfo = (), Context = Context>,
+    Context: 'static + Send + Sync + Authorizer,
+{
+    req.into_body().concat2().and_then(move |body| {
+        match serde_json::from_slice::<GraphqlBatchRequest>(&body) {
+            Ok(request) => Either::A(execute(
+                root_node,
+                ctx,
+                request,
+                request_info,
+                execution_config,
+            )),
+            Err(err) => Either::B(future::ok(bad_request(err.to_string()))),
+        }
+    })
+}
+
+fn graphql_multipart<Query, Mutation, Context>(
+    root_node: Arc<Schema<Query, Mutation>>,
+    ctx: Arc<Context>,
+    req: Request<Body>,
+    request_info: RequestInfo,
+    boundary: String,
+    upload_config: Arc<UploadConfig>,
+    execution_config: Arc<ExecutionConfig>,
+) -> impl Future<Item = Response<Body>, Error = hyper::Error>
+where
+    Query: 'static + Send + Sync + GraphQLType<TypeInfo = (), Context = Context>,
+    Mutation: 'static + Send + Sync + GraphQLType<TypeInfo = (), Context = Context>,
+    Context: 'static + Send + Sync + Authorizer,
+{
+    let uploads = uploads(&req);
+    let mut body = Some(req.into_body());
+
+    future::poll_fn(move || {
+        tokio_threadpool::blocking(|| {
+            let body = body.take().expect("multipart body read twice");
+            let request =
+                upload::decode(BodyReader::new(body), &boundary, &upload_config, &uploads)?;
+            let response = request.execute(&*root_node, &*ctx, &request_info, &execution_config);
+            Ok(response)
+        })
+    })
+    .map_err(|_| -> hyper::Error { unreachable!("thread pool has shut down?!") })
+    .map(
+        |result: Result<GraphqlResponse, UploadError>| match result {
+            Ok(response) => json_response(response),
+            Err(err) => upload_error_response(err),
+        },
+    )
+}
+
+fn execute<Query, Mutation, Context>(
+    root_node: Arc<Schema<Query, Mutation>>,
+    ctx: Arc<Context>,
+    request: GraphqlBatchRequest,
+    request_info: RequestInfo,
+    execution_config: Arc<ExecutionConfig>,
+) -> impl Future<Item = Response<Body>, Error = hyper::Error>
+where
+    Query: 'static + Send + Sync + GraphQLType<TypeInfo = (), Context = Context>,
+    Mutation: 'static + Send + Sync + GraphQLType<TypeInfo = (), Context = Context>,
+    Context: 'static + Send + Sync + Authorizer,
+{
+    future::poll_fn(move || {
+        tokio_threadpool::blocking(|| {
+            request.execute(&*root_node, &*ctx, &request_info, &execution_config)
+        })
+    })
+    .map(json_response)
+    .map_err(|_| -> hyper::Error { unreachable!("thread pool has shut down?!") })
+}
+
+fn json_response(response: GraphqlResponse) -> Response<Body> {
+    let mut resp = Response::new(Body::from(response.body));
+    resp.extensions_mut()
+        .insert(Operations(response.operations));
+    *resp.status_mut() = if response.panicked {
+        StatusCode::INTERNAL_SERVER_ERROR
+    } else if response.is_ok {
+        StatusCode::OK
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    resp.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    resp
+}
+
+/// The IDE page at `/` and its assets under `/ide-assets/`.
+fn ide_response(ide: &Ide, path: &str) -> Option<Response<Body>> {
+    let resp = if path == "/" {
+        let page = ide.page("/graphql", "/ide-assets")?;
+        let mut resp = Response::new(Body::from(page.html));
+        resp.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        resp.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(ide::PAGE_CACHE_CONTROL),
+        );
+        resp.headers_mut().insert(
+            header::CONTENT_SECURITY_POLICY,
+            HeaderValue::from_str(&page.content_security_policy)
+                .expect("invalid Content-Security-Policy header"),
+        );
+        resp
+    } else if path.starts_with("/ide-assets/") && ide.is_enabled() {
+        let (content_type, contents) = ide::asset(&path["/ide-assets/".len()..])?;
+        let mut resp = Response::new(Body::from(contents));
+        resp.headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+        resp.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(ide::ASSET_CACHE_CONTROL),
+        );
+        resp
+    } else {
+        return None;
+    };
+
+    Some(resp)
+}
+
+#[cfg(feature = "metrics")]
+fn metrics_response(endpoint: &MetricsEndpoint) -> Response<Body> {
+    let mut resp = Response::new(Body::from(endpoint.render()));
+    if let Ok(content_type) = HeaderValue::from_str(&endpoint.content_type()) {
+        resp.headers_mut()
+            .insert(header::CONTENT_TYPE, content_type);
+    }
+    resp
+}
+
+fn json_error_response(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    let mut resp = Response::new(Body::from(body.to_string()));
+    *resp.status_mut() = status;
+    resp.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    resp
+}
+
+fn add_headers(response: &mut Response<Body>, headers: Vec<(&'static str, String)>) {
+    for (name, value) in headers {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response.headers_mut().append(name, value);
+        }
+    }
+}
+
+fn bad_request(message: String) -> Response<Body> {
+    let mut resp = Response::new(Body::from(message));
+    *resp.status_mut() = StatusCode::BAD_REQUEST;
+    resp
+}
+
+fn upload_error_response(err: UploadError) -> Response<Body> {
+    let mut resp = Response::new(Body::from(err.to_string()));
+    *resp.status_mut() = if err.is_too_large() {
+        StatusCode::PAYLOAD_TOO_LARGE
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    resp
+}
+
+/// Blocking `Read` over a hyper body.
+///
+/// Must only be used inside `tokio_threadpool::blocking`.
+struct BodyReader {
+    chunks: Wait<Body>,
+    current: Chunk,
+    position: usize,
+}
+
+impl BodyReader {
+    fn new(body: Body) -> Self {
+        BodyReader {
+            chunks: body.wait(),
+            current: Chunk::default(),
+            position: 0,
+        }
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let remaining = &self.current[self.position..];
+            if !remaining.is_empty() {
+                let len = std::cmp::min(buf.len(), remaining.len());
+                buf[..len].copy_from_slice(&remaining[..len]);
+                self.position += len;
+                return Ok(len);
+            }
+
+            match self.chunks.next() {
+                Some(Ok(chunk)) => {
+                    self.current = chunk;
+                    self.position = 0;
+                }
+                Some(Err(err)) => return Err(io::Error::other(err)),
+                None => return Ok(0),
+            }
+        }
+    }
 }
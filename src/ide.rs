@@ -0,0 +1,404 @@
+//! The in-browser IDE served next to the GraphQL endpoint.
+//!
+//! ```ignore
+//! fn ide(&self) -> Ide {
+//!     Ide::playground()
+//!         .header("Authorization", "Bearer <token>")
+//!         .tab("Users", "{ users { id } }")
+//! }
+//! ```
+//!
+//! The IDE's JavaScript and CSS are embedded in the binary and served from `ide-assets/` next
+//! to the IDE page, so it works without internet access. The page is served with a
+//! `Content-Security-Policy` that only allows those assets and its own nonce'd scripts.
+//! Playground adds `<style>` tags while it runs, so its page also allows inline styles.
+
+use serde_json::json;
+use uuid::Uuid;
+
+/// Which IDE to serve and how to set it up.
+pub struct Ide {
+    kind: IdeKind,
+    headers: Vec<(String, String)>,
+    tabs: Vec<(String, String)>,
+    subscription_endpoint: Option<String>,
+}
+
+enum IdeKind {
+    Graphiql,
+    Playground,
+    None,
+}
+
+impl Ide {
+    pub fn graphiql() -> Self {
+        Ide::new(IdeKind::Graphiql)
+    }
+
+    pub fn playground() -> Self {
+        Ide::new(IdeKind::Playground)
+    }
+
+    /// Don't serve an IDE.
+    pub fn none() -> Self {
+        Ide::new(IdeKind::None)
+    }
+
+    fn new(kind: IdeKind) -> Self {
+        Ide {
+            kind,
+            headers: Vec::new(),
+            tabs: Vec::new(),
+            subscription_endpoint: None,
+        }
+    }
+
+    /// A header sent with every request, such as a placeholder auth token.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// A tab opened with the given query.
+    ///
+    /// GraphiQL doesn't have tabs, so it only shows the query of the first one.
+    pub fn tab(mut self, name: &str, query: &str) -> Self {
+        self.tabs.push((name.to_string(), query.to_string()));
+        self
+    }
+
+    /// Where the IDE connects for subscriptions. Only used by GraphQL Playground.
+    pub fn subscription_endpoint(mut self, path: &str) -> Self {
+        self.subscription_endpoint = Some(path.to_string());
+        self
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !matches!(self.kind, IdeKind::None)
+    }
+
+    /// The HTML page for the IDE, talking to the GraphQL endpoint at `graphql_path` and
+    /// loading its assets from `assets_path`.
+    ///
+    /// A new nonce is generated for every page, so the page must not be cached.
+    pub(crate) fn page(&self, graphql_path: &str, assets_path: &str) -> Option<IdePage> {
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| (name.clone(), json!(value)))
+            .collect::<serde_json::Map<_, _>>();
+
+        let (stylesheets, scripts, body, settings, inline_styles) = match self.kind {
+            IdeKind::Graphiql => {
+                let settings = json!({
+                    "endpoint": graphql_path,
+                    "headers": headers,
+                    "defaultQuery": self.tabs.first().map(|(_, query)| query),
+                });
+                (
+                    GRAPHIQL_STYLESHEETS,
+                    GRAPHIQL_SCRIPTS,
+                    GRAPHIQL_BODY,
+                    settings,
+                    false,
+                )
+            }
+            IdeKind::Playground => {
+                let tabs = self
+                    .tabs
+                    .iter()
+                    .map(|(name, query)| {
+                        json!({
+                            "name": name,
+                            "endpoint": graphql_path,
+                            "query": query,
+                            "headers": headers,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let settings = json!({
+                    "endpoint": graphql_path,
+                    "subscriptionEndpoint": self.subscription_endpoint,
+                    "headers": headers,
+                    "tabs": if tabs.is_empty() { None } else { Some(tabs) },
+                });
+                (
+                    PLAYGROUND_STYLESHEETS,
+                    PLAYGROUND_SCRIPTS,
+                    PLAYGROUND_BODY,
+                    settings,
+                    true,
+                )
+            }
+            IdeKind::None => return None,
+        };
+
+        let nonce = Uuid::new_v4().to_simple().to_string();
+        let assets_path = assets_path.trim_end_matches('/');
+
+        let mut head = String::new();
+        for stylesheet in stylesheets {
+            head.push_str(&format!(
+                "<link rel=\"stylesheet\" href=\"{}/{}\">\n",
+                assets_path, stylesheet
+            ));
+        }
+        head.push_str(&format!(
+            "<style nonce=\"{}\">body {{ margin: 0; }} #ide {{ height: 100vh; }}</style>\n",
+            nonce
+        ));
+        for script in scripts {
+            head.push_str(&format!(
+                "<script nonce=\"{}\" src=\"{}/{}\"></script>\n",
+                nonce, assets_path, script
+            ));
+        }
+
+        // `</script>` inside a JSON string would end the script tag early.
+        let settings = settings.to_string().replace("</", "<\\/");
+
+        let html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>GraphQL</title>\n{head}</head>\n<body>\n<div id=\"ide\"></div>\n<script nonce=\"{nonce}\">var settings = {settings};</script>\n<script nonce=\"{nonce}\">{body}</script>\n</body>\n</html>\n",
+            head = head,
+            nonce = nonce,
+            settings = settings,
+            body = body,
+        );
+
+        // Browsers ignore 'unsafe-inline' when a nonce is given, so it's one or the other.
+        let style_src = if inline_styles {
+            "'unsafe-inline'".to_string()
+        } else {
+            format!("'nonce-{}'", nonce)
+        };
+        let content_security_policy = format!(
+            "default-src 'self'; script-src 'self' 'nonce-{nonce}'; style-src 'self' {style_src}; img-src 'self' data:; font-src 'self' data:; connect-src 'self' ws: wss:",
+            nonce = nonce,
+            style_src = style_src,
+        );
+
+        Some(IdePage {
+            html,
+            content_security_policy,
+        })
+    }
+}
+
+pub(crate) struct IdePage {
+    pub(crate) html: String,
+    pub(crate) content_security_policy: String,
+}
+
+/// Asset names contain the version of the package, so they can be cached forever.
+pub(crate) const ASSET_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// The IDE page embeds a fresh nonce, so it must never be reused.
+pub(crate) const PAGE_CACHE_CONTROL: &str = "no-store";
+
+/// Look up an embedded asset by file name, returning its content type and contents.
+pub(crate) fn asset(name: &str) -> Option<(&'static str, &'static [u8])> {
+    ASSETS
+        .iter()
+        .find(|(asset_name, _, _)| *asset_name == name)
+        .map(|(_, content_type, contents)| (*content_type, *contents))
+}
+
+const CSS: &str = "text/css; charset=utf-8";
+const JS: &str = "application/javascript; charset=utf-8";
+
+/// The files `build.rs` checks against `assets/ide/SHA256SUMS`.
+const ASSETS: &[(&str, &str, &[u8])] = &[
+    (
+        "graphiql-0.13.2.css",
+        CSS,
+        include_bytes!(concat!(env!("OUT_DIR"), "/ide-assets/graphiql-0.13.2.css")),
+    ),
+    (
+        "graphiql-0.13.2.min.js",
+        JS,
+        include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/ide-assets/graphiql-0.13.2.min.js"
+        )),
+    ),
+    (
+        "react-16.8.6.min.js",
+        JS,
+        include_bytes!(concat!(env!("OUT_DIR"), "/ide-assets/react-16.8.6.min.js")),
+    ),
+    (
+        "react-dom-16.8.6.min.js",
+        JS,
+        include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/ide-assets/react-dom-16.8.6.min.js"
+        )),
+    ),
+    (
+        "whatwg-fetch-3.0.0.js",
+        JS,
+        include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/ide-assets/whatwg-fetch-3.0.0.js"
+        )),
+    ),
+    (
+        "graphql-playground-1.7.20.css",
+        CSS,
+        include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/ide-assets/graphql-playground-1.7.20.css"
+        )),
+    ),
+    (
+        "graphql-playground-1.7.20.js",
+        JS,
+        include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/ide-assets/graphql-playground-1.7.20.js"
+        )),
+    ),
+];
+
+const GRAPHIQL_STYLESHEETS: &[&str] = &["graphiql-0.13.2.css"];
+
+const GRAPHIQL_SCRIPTS: &[&str] = &[
+    "whatwg-fetch-3.0.0.js",
+    "react-16.8.6.min.js",
+    "react-dom-16.8.6.min.js",
+    "graphiql-0.13.2.min.js",
+];
+
+const GRAPHIQL_BODY: &str = r#"
+function fetcher(params) {
+  var headers = Object.assign(
+    { "Accept": "application/json", "Content-Type": "application/json" },
+    settings.headers
+  );
+  return fetch(settings.endpoint, {
+    method: "post",
+    headers: headers,
+    credentials: "include",
+    body: JSON.stringify(params)
+  }).then(function (response) {
+    return response.text();
+  }).then(function (body) {
+    try {
+      return JSON.parse(body);
+    } catch (error) {
+      return body;
+    }
+  });
+}
+
+ReactDOM.render(
+  React.createElement(GraphiQL, {
+    fetcher: fetcher,
+    defaultQuery: settings.defaultQuery || undefined
+  }),
+  document.getElementById("ide")
+);
+"#;
+
+const PLAYGROUND_STYLESHEETS: &[&str] = &["graphql-playground-1.7.20.css"];
+
+const PLAYGROUND_SCRIPTS: &[&str] = &["graphql-playground-1.7.20.js"];
+
+const PLAYGROUND_BODY: &str = r#"
+window.addEventListener("load", function () {
+  var options = { endpoint: settings.endpoint, settings: { "request.credentials": "include" } };
+  if (settings.subscriptionEndpoint) {
+    options.subscriptionEndpoint = settings.subscriptionEndpoint;
+  }
+  if (settings.tabs) {
+    options.tabs = settings.tabs;
+  } else {
+    options.headers = settings.headers;
+  }
+  GraphQLPlayground.init(document.getElementById("ide"), options);
+});
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directive<'a>(page: &'a IdePage, name: &str) -> &'a str {
+        page.content_security_policy
+            .split("; ")
+            .find(|directive| directive.starts_with(name))
+            .unwrap()
+    }
+
+    fn nonce(page: &IdePage) -> &str {
+        let start = page.html.find("nonce=\"").unwrap() + "nonce=\"".len();
+        let end = start + page.html[start..].find('"').unwrap();
+        &page.html[start..end]
+    }
+
+    #[test]
+    fn graphiql_only_allows_nonced_scripts_and_styles() {
+        let page = Ide::graphiql().page("/graphql", "/ide-assets/").unwrap();
+        let nonce = nonce(&page);
+
+        assert_eq!(
+            directive(&page, "script-src"),
+            format!("script-src 'self' 'nonce-{}'", nonce)
+        );
+        assert_eq!(
+            directive(&page, "style-src"),
+            format!("style-src 'self' 'nonce-{}'", nonce)
+        );
+        assert!(page.html.contains(&format!("<style nonce=\"{}\">", nonce)));
+        assert_eq!(
+            page.html.matches("<script ").count(),
+            page.html
+                .matches(&format!("<script nonce=\"{}\"", nonce))
+                .count()
+        );
+    }
+
+    #[test]
+    fn playground_allows_inline_styles_but_only_nonced_scripts() {
+        let page = Ide::playground().page("/graphql", "/ide-assets").unwrap();
+        let nonce = nonce(&page);
+
+        assert_eq!(
+            directive(&page, "script-src"),
+            format!("script-src 'self' 'nonce-{}'", nonce)
+        );
+        assert_eq!(
+            directive(&page, "style-src"),
+            "style-src 'self' 'unsafe-inline'"
+        );
+        assert!(page.html.contains(&format!(
+            "<script nonce=\"{}\" src=\"/ide-assets/graphql-playground-1.7.20.js\">",
+            nonce
+        )));
+    }
+
+    #[test]
+    fn every_page_gets_a_new_nonce() {
+        let ide = Ide::graphiql();
+        let first = ide.page("/graphql", "/ide-assets").unwrap();
+        let second = ide.page("/graphql", "/ide-assets").unwrap();
+
+        assert_ne!(nonce(&first), nonce(&second));
+    }
+
+    #[test]
+    fn settings_cant_close_the_script_tag() {
+        let page = Ide::graphiql()
+            .tab("Users", "# </script><script>alert(1)</script>")
+            .page("/graphql", "/ide-assets")
+            .unwrap();
+
+        assert!(!page.html.contains("</script><script>alert(1)"));
+        assert!(page.html.contains("<\\/script>"));
+    }
+
+    #[test]
+    fn disabled_ide_has_no_page() {
+        assert!(Ide::none().page("/graphql", "/ide-assets").is_none());
+    }
+}
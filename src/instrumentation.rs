@@ -0,0 +1,384 @@
+//! Timing of resolvers.
+//!
+//! juniper doesn't have a way to hook into field resolution, so the query and mutation types
+//! are wrapped in [`Instrumented`], which times the fields it resolves. Resolvers of nested
+//! fields are timed when they call [`time_resolver`].
+//!
+//! The timings are reported to the metrics, and collected for Apollo Tracing and the slow query
+//! log while a request that needs them is executed. juniper only exposes the path of the field
+//! being resolved through the errors pushed while resolving it, so while timings are collected
+//! each one pushes an error carrying its index, and [`take_resolver_paths`] copies the errors'
+//! paths to the timings and removes the errors before the response is built.
+
+use juniper::meta::MetaType;
+use juniper::{
+    graphql_value, Arguments, DefaultScalarValue, ExecutionError, ExecutionResult, Executor,
+    FieldError, GraphQLType, Registry, RootNode,
+};
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    static RESOLVER_TIMINGS: RefCell<Option<Vec<ResolverTiming>>> = const { RefCell::new(None) };
+}
+
+/// Marks the errors that carry the index of a resolver's timing.
+const PATH_MARKER: &str = "__gimmeResolverPath";
+
+/// How long a resolver took, collected by [`collect_resolver_timings`].
+pub(crate) struct ResolverTiming {
+    /// The response keys of the field and its parents, so aliases rather than field names.
+    /// juniper doesn't put list indices in paths, so the items of a list share theirs.
+    pub(crate) path: Vec<String>,
+    pub(crate) parent_type: String,
+    pub(crate) field_name: String,
+    pub(crate) return_type: String,
+    pub(crate) started: Instant,
+    pub(crate) duration: Duration,
+}
+
+/// Run `f`, returning the timings of the resolvers it ran on this thread.
+pub(crate) fn collect_resolver_timings<F, T>(f: F) -> (T, Vec<ResolverTiming>)
+where
+    F: FnOnce() -> T,
+{
+    struct Reset;
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            RESOLVER_TIMINGS.with(|timings| timings.borrow_mut().take());
+        }
+    }
+
+    RESOLVER_TIMINGS.with(|timings| *timings.borrow_mut() = Some(Vec::new()));
+    let reset = Reset;
+    let result = f();
+    let timings = RESOLVER_TIMINGS.with(|timings| timings.borrow_mut().take());
+    drop(reset);
+
+    (result, timings.unwrap_or_default())
+}
+
+/// Set the paths of the timings from the errors their resolvers pushed, and remove those errors.
+pub(crate) fn take_resolver_paths(
+    errors: &mut Vec<ExecutionError<DefaultScalarValue>>,
+    timings: &mut [ResolverTiming],
+) {
+    errors.retain(|error| {
+        if error.error().message() != PATH_MARKER {
+            return true;
+        }
+
+        let index = error
+            .error()
+            .extensions()
+            .as_object_value()
+            .and_then(|extensions| extensions.get_field_value("index"))
+            .and_then(|index| index.as_scalar_value::<i32>());
+        if let Some(timing) = index.and_then(|index| timings.get_mut(*index as usize)) {
+            timing.path = error.path().to_vec();
+        }
+        false
+    });
+}
+
+/// Time a resolver of a nested field for Apollo Tracing, the slow query log and the
+/// `graphql_resolver_duration_seconds` metric. The fields of the query and mutation types are
+/// already timed.
+///
+/// ```ignore
+/// impl UserFields for User {
+///     fn field_posts(
+///         &self,
+///         executor: &Executor<'_, Context>,
+///         _: &QueryTrail<'_, Post, Walked>,
+///     ) -> FieldResult<Vec<Post>> {
+///         gimme_graphql::time_resolver(executor, "User", "posts", || {
+///             let db = &gimme_graphql::get_connection(&executor.context().db_pool)?;
+///             Ok(Post::belonging_to(self).load(db)?)
+///         })
+///     }
+/// }
+/// ```
+pub fn time_resolver<Context, F, T>(
+    executor: &Executor<Context>,
+    parent_type: &str,
+    field_name: &str,
+    resolve: F,
+) -> T
+where
+    F: FnOnce() -> T,
+{
+    let started = Instant::now();
+    let result = resolve();
+    record(
+        executor,
+        parent_type,
+        field_name,
+        started,
+        started.elapsed(),
+    );
+    result
+}
+
+fn record<Context>(
+    executor: &Executor<Context>,
+    parent_type: &str,
+    field_name: &str,
+    started: Instant,
+    duration: Duration,
+) {
+    #[cfg(feature = "metrics")]
+    crate::metrics::observe_resolver(parent_type, field_name, duration);
+
+    let index = RESOLVER_TIMINGS.with(|timings| {
+        let mut timings = timings.borrow_mut();
+        let timings = timings.as_mut()?;
+        let return_type = executor
+            .schema()
+            .concrete_type_by_name(parent_type)
+            .and_then(|meta_type| meta_type.field_by_name(field_name))
+            .map(|field| field.field_type.to_string())
+            .unwrap_or_default();
+        timings.push(ResolverTiming {
+            path: vec![field_name.to_string()],
+            parent_type: parent_type.to_string(),
+            field_name: field_name.to_string(),
+            return_type,
+            started,
+            duration,
+        });
+        Some(timings.len() - 1)
+    });
+
+    if let Some(index) = index {
+        executor.push_error(FieldError::new(
+            PATH_MARKER,
+            graphql_value!({ "index": (index as i32) }),
+        ));
+    }
+}
+
+/// The root node of the schema, with instrumented root types.
+pub(crate) type Schema<Query, Mutation> =
+    RootNode<'static, Instrumented<Query>, Instrumented<Mutation>>;
+
+pub(crate) fn schema<Query, Mutation>(query: Query, mutation: Mutation) -> Schema<Query, Mutation>
+where
+    Query: GraphQLType<TypeInfo = ()>,
+    Mutation: GraphQLType<TypeInfo = ()>,
+{
+    RootNode::new(Instrumented(query), Instrumented(mutation))
+}
+
+/// A root type that behaves like the wrapped one, but times the fields it resolves.
+pub(crate) struct Instrumented<T>(T);
+
+impl<T> GraphQLType for Instrumented<T>
+where
+    T: GraphQLType<TypeInfo = ()>,
+{
+    type Context = T::Context;
+    type TypeInfo = ();
+
+    fn name(info: &()) -> Option<&str> {
+        T::name(info)
+    }
+
+    fn meta<'r>(info: &(), registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        T::meta(info, registry)
+    }
+
+    fn resolve_field(
+        &self,
+        info: &(),
+        field_name: &str,
+        arguments: &Arguments,
+        executor: &Executor<Self::Context>,
+    ) -> ExecutionResult {
+        let started = Instant::now();
+        let result = self.0.resolve_field(info, field_name, arguments, executor);
+        let parent_type = T::name(info).unwrap_or("");
+        record(
+            executor,
+            parent_type,
+            field_name,
+            started,
+            started.elapsed(),
+        );
+        result
+    }
+
+    fn concrete_type_name(&self, context: &Self::Context, info: &()) -> String {
+        self.0.concrete_type_name(context, info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{FieldCosts, QueryLimits};
+    use crate::apollo_tracing::ApolloTracing;
+    use crate::authorization::AuthorizationRules;
+    use crate::persisted_queries::InMemoryPersistedQueryStore;
+    use crate::request::{ExecutionConfig, GraphqlBatchRequest, RequestInfo};
+    use crate::request_id::RequestId;
+    use crate::telemetry::PoolCheckouts;
+    use crate::test_schema;
+    use juniper::{EmptyMutation, Variables};
+
+    fn execute(query: &str) -> (Vec<ExecutionError<DefaultScalarValue>>, Vec<ResolverTiming>) {
+        let root_node = schema(test_schema::Query, EmptyMutation::<()>::new());
+        let (result, mut timings) = collect_resolver_timings(|| {
+            juniper::execute(query, None, &root_node, &Variables::new(), &())
+        });
+        let (_, mut errors) = result.unwrap();
+        take_resolver_paths(&mut errors, &mut timings);
+        (errors, timings)
+    }
+
+    #[test]
+    fn nested_resolvers_are_timed_with_their_response_keys() {
+        let (errors, timings) =
+            execute("{ people: users(limit: 1) { id pals: friends(first: 2) { id } } }");
+
+        assert!(errors.is_empty());
+        let resolvers = timings
+            .iter()
+            .map(|timing| {
+                (
+                    timing.path.join("."),
+                    format!("{}.{}", timing.parent_type, timing.field_name),
+                    timing.return_type.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            resolvers,
+            vec![
+                (
+                    "people.pals".to_string(),
+                    "User.friends".to_string(),
+                    "[User!]!"
+                ),
+                ("people".to_string(), "Query.users".to_string(), "[User!]!"),
+            ]
+        );
+    }
+
+    #[test]
+    fn list_items_share_a_path() {
+        let (_, timings) = execute("{ users(limit: 2) { friends(first: 1) { id } } }");
+
+        let paths = timings
+            .iter()
+            .map(|timing| timing.path.join("."))
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["users.friends", "users.friends", "users"]);
+    }
+
+    #[test]
+    fn nothing_is_pushed_while_timings_arent_collected() {
+        let root_node = schema(test_schema::Query, EmptyMutation::<()>::new());
+        let (_, errors) = juniper::execute(
+            "{ users(limit: 1) { friends(first: 1) { id } } }",
+            None,
+            &root_node,
+            &Variables::new(),
+            &(),
+        )
+        .unwrap();
+
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn other_errors_are_kept() {
+        let mut errors = vec![ExecutionError::at_origin(FieldError::new(
+            "boom",
+            juniper::Value::null(),
+        ))];
+        take_resolver_paths(&mut errors, &mut []);
+
+        assert_eq!(errors.len(), 1);
+    }
+
+    struct Failing;
+
+    #[juniper::object]
+    impl Failing {
+        fn ok() -> i32 {
+            1
+        }
+
+        fn users() -> Vec<test_schema::User> {
+            vec![test_schema::User(1)]
+        }
+
+        fn failing(executor: &Executor) -> juniper::FieldResult<Option<i32>> {
+            time_resolver(executor, "Query", "failing", || Err("boom".into()))
+        }
+
+        fn panicking(executor: &Executor) -> i32 {
+            time_resolver(executor, "Query", "panicking", || panic!("boom"))
+        }
+    }
+
+    fn respond(query: &str) -> serde_json::Value {
+        let config = ExecutionConfig {
+            query_limits: QueryLimits {
+                max_depth: None,
+                max_complexity: None,
+                field_costs: FieldCosts::new(),
+            },
+            persisted_query_store: Box::new(InMemoryPersistedQueryStore::new(10)),
+            allowlist: None,
+            enable_introspection: true,
+            expose_internal_errors: true,
+            authorization_rules: AuthorizationRules::default(),
+            apollo_tracing: ApolloTracing::Always,
+            redacted_variables: Vec::new(),
+            slow_query_threshold: Some(Duration::from_secs(0)),
+            count_sql_statements: false,
+            n_plus_one_threshold: 1,
+        };
+        let root_node = schema(Failing, EmptyMutation::<()>::new());
+        let request =
+            serde_json::from_value::<GraphqlBatchRequest>(serde_json::json!({ "query": query }))
+                .unwrap();
+        let request_info = RequestInfo {
+            id: RequestId::generate(),
+            wants_apollo_tracing: true,
+            pool_checkouts: PoolCheckouts::default(),
+        };
+        let response = request.execute(&root_node, &(), &request_info, &config);
+        assert!(!response.body.contains(PATH_MARKER), "{}", response.body);
+        serde_json::from_str(&response.body).unwrap()
+    }
+
+    #[test]
+    fn no_path_markers_reach_responses_with_errors() {
+        let body = respond("{ ok failing users { friends(first: 1) { id } } }");
+
+        assert_eq!(body["data"]["ok"], 1);
+        assert_eq!(body["errors"].as_array().unwrap().len(), 1);
+        assert_eq!(body["errors"][0]["message"], "boom");
+        assert_eq!(body["errors"][0]["path"], serde_json::json!(["failing"]));
+    }
+
+    #[test]
+    fn no_path_markers_reach_responses_after_panics() {
+        let body = respond("{ ok users { friends(first: 1) { id } } panicking }");
+
+        assert!(body["data"].is_null(), "{}", body);
+        assert_eq!(body["errors"].as_array().unwrap().len(), 1);
+
+        let body = respond("{ ok users { id } }");
+
+        assert!(body.get("errors").is_none(), "{}", body);
+        assert_eq!(body["data"]["ok"], 1);
+    }
+}
@@ -1,25 +1,144 @@
 #![feature(proc_macro_hygiene, decl_macro)]
 #![forbid(unknown_lints)]
+// diesel 1.4's `table!` and derives put their impls inside `const` items.
+#![allow(non_local_definitions)]
 // #![deny(unused_imports, dead_code, unused_variables)]
 
+#[macro_use]
+extern crate diesel;
+
+pub mod access_log;
+pub mod allowlist;
+pub mod analysis;
+pub mod apollo_tracing;
+#[cfg(feature = "auth")]
+pub mod auth;
+pub mod authorization;
+pub mod cli;
+pub mod cors;
+pub mod errors;
+pub mod filters;
 pub mod hyper_adapter;
+pub mod ide;
+mod instrumentation;
+pub mod loader;
+pub mod lookahead;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod panics;
+pub mod persisted_queries;
+pub mod relay;
+mod request;
+pub mod request_id;
 pub mod rocket_adapter;
+pub mod scaffold;
+mod sdl;
+mod slow_log;
+pub mod sql_counting;
+mod telemetry;
+#[cfg(test)]
+mod test_connection;
+#[cfg(test)]
+mod test_schema;
+pub mod upload;
+mod validation;
 
 pub use diesel::r2d2::ConnectionManager;
+pub use instrumentation::time_resolver;
 pub use r2d2::{Pool, PooledConnection};
+pub use telemetry::get_connection;
 
-use juniper::GraphQLType;
+use access_log::{AccessLog, DEFAULT_REDACTED_VARIABLES};
+use allowlist::QueryAllowlist;
+use analysis::{FieldCosts, QueryLimits};
+use apollo_tracing::ApolloTracing;
+#[cfg(feature = "auth")]
+use auth::JwtAuth;
+use authorization::AuthorizationRules;
+use cli::Overrides;
+use cors::Cors;
+use ide::Ide;
+#[cfg(feature = "metrics")]
+use metrics::MetricsConfig;
+use persisted_queries::{InMemoryPersistedQueryStore, PersistedQueryStore};
+use request::ExecutionConfig;
+use std::path::PathBuf;
+use std::time::Duration;
+use upload::UploadConfig;
 
 pub fn run_graphql_app<App: GraphqlApp>(app: App) {
+    init();
+    serve(app, &Overrides::default());
+}
+
+fn init() {
     dotenv::dotenv().ok();
     env_logger::init();
+    panics::install_hook();
+}
+
+fn serve<App: GraphqlApp>(app: App, overrides: &Overrides) {
+    let database_url = overrides
+        .database_url
+        .clone()
+        .unwrap_or_else(|| app.database_url());
+    let database_connection_pool = create_database_connection_pool(&app, database_url);
+
+    let execution_config = ExecutionConfig {
+        query_limits: QueryLimits {
+            max_depth: app.max_query_depth(),
+            max_complexity: app.max_query_complexity(),
+            field_costs: app.field_costs(),
+        },
+        persisted_query_store: app.persisted_query_store(&database_connection_pool),
+        allowlist: app.query_allowlist().map(|allowlist| {
+            allowlist
+                .load()
+                .unwrap_or_else(|err| panic!("failed to load query allowlist: {}", err))
+        }),
+        enable_introspection: app.enable_introspection(),
+        expose_internal_errors: app.expose_internal_errors(),
+        authorization_rules: app
+            .schema_file()
+            .map(|path| {
+                AuthorizationRules::load(&path)
+                    .unwrap_or_else(|err| panic!("failed to load authorization rules: {}", err))
+            })
+            .unwrap_or_default(),
+        apollo_tracing: app.apollo_tracing(),
+        redacted_variables: app
+            .redacted_variables()
+            .iter()
+            .map(|name| name.to_lowercase())
+            .collect(),
+        slow_query_threshold: app.slow_query_threshold(),
+        count_sql_statements: counts_sql_statements(&app),
+        n_plus_one_threshold: app.n_plus_one_threshold(),
+    };
 
     let config = WebFrameworkConfig {
-        database_connection_pool: create_database_connection_pool(&app),
+        access_log: app.access_log(),
+        cors: app.cors(),
+        database_connection_pool,
+        execution_config,
         graphql_path: app.graphql_path(),
+        #[cfg(feature = "auth")]
+        jwt_auth: app.jwt_auth(),
+        ide: enabled_ide(&app),
         mount_graphiql_at: app.mount_graphiql_at(),
+        #[cfg(feature = "metrics")]
+        metrics: MetricsConfig {
+            path: app.metrics_path(),
+            admin_port: app.metrics_admin_port(),
+        },
         mount_graphql_at: app.mount_graphql_at(),
-        port: app.port(),
+        port: overrides.port.unwrap_or_else(|| app.port()),
+        profile: app.profile(),
+        upload_config: UploadConfig {
+            max_file_size: app.max_upload_file_size(),
+            max_total_size: app.max_upload_total_size(),
+            temp_dir: app.upload_temp_dir(),
+        },
     };
 
     App::Adapter::new().run(app, config);
@@ -27,15 +146,58 @@ pub fn run_graphql_app<App: GraphqlApp>(app: App) {
 
 fn create_database_connection_pool<App: GraphqlApp>(
     app: &App,
+    database_url: String,
 ) -> Pool<ConnectionManager<App::Connection>> {
-    let connection_manager = ConnectionManager::<App::Connection>::new(app.database_url());
+    let connection_manager = ConnectionManager::<App::Connection>::new(database_url);
 
     r2d2::Pool::builder()
         .max_size(app.database_connection_pool_max_size())
+        .event_handler(Box::new(telemetry::PoolEventHandler))
         .build(connection_manager)
         .expect("failed to create db connection pool")
 }
 
+/// Whether `GraphqlApp::count_sql_statements` is enabled and the pool's connections can count
+/// statements. Must be called after the pool is built.
+fn counts_sql_statements<App: GraphqlApp>(app: &App) -> bool {
+    if !app.count_sql_statements() {
+        return false;
+    }
+
+    let counted = sql_counting::connections_are_counted();
+    if !counted {
+        log::warn!("Not counting SQL statements, the connection type isn't a CountingConnection");
+    }
+    counted
+}
+
+/// The IDE to serve, if `GraphqlApp::enable_ide` allows it.
+fn enabled_ide<App: GraphqlApp>(app: &App) -> Ide {
+    if app.enable_ide() {
+        app.ide()
+    } else {
+        Ide::none()
+    }
+}
+
+/// Routes every adapter serves the same way.
+fn shared_routes<App: GraphqlApp>(app: &App) -> Vec<RouteInfo> {
+    let mut routes = Vec::new();
+    if app.cors().is_some() {
+        routes.push(RouteInfo::new("OPTIONS", "*", "CORS preflight requests"));
+    }
+    #[cfg(feature = "metrics")]
+    routes.push(match app.metrics_admin_port() {
+        Some(admin_port) => RouteInfo::new(
+            "GET",
+            app.metrics_path(),
+            &format!("Prometheus metrics, on port {}", admin_port),
+        ),
+        None => RouteInfo::new("GET", app.metrics_path(), "Prometheus metrics"),
+    });
+    routes
+}
+
 pub trait GraphqlApp {
     type Connection: 'static + diesel::Connection;
     type Adapter: Adapter<Self::Connection, Self::Query, Self::Mutation, Self::Context>;
@@ -43,6 +205,7 @@ pub trait GraphqlApp {
     type Mutation;
     type Context;
 
+    #[allow(clippy::type_complexity)]
     fn configure_web_framework(
         &self,
         web_framework: <Self::Adapter as Adapter<
@@ -56,12 +219,12 @@ pub trait GraphqlApp {
         Self::Query,
         Self::Mutation,
         Self::Context,
->>::Inner{
+    >>::Inner{
         web_framework
     }
 
     fn port(&self) -> u16 {
-        if let Some(env_value) = std::env::var("PORT").ok() {
+        if let Ok(env_value) = std::env::var("PORT") {
             env_value
                 .parse()
                 .expect("failed to parse PORT env var to u16")
@@ -70,6 +233,52 @@ pub trait GraphqlApp {
         }
     }
 
+    fn profile_env_var(&self) -> &'static str {
+        "APP_ENV"
+    }
+
+    /// Read from the env var named by `profile_env_var`, defaulting to production so that a
+    /// deploy which forgets to set it doesn't expose the IDE or error details.
+    fn profile(&self) -> Profile {
+        let var = self.profile_env_var();
+        match std::env::var(var).ok().as_deref() {
+            Some("dev") | Some("development") => Profile::Development,
+            Some("staging") => Profile::Staging,
+            None | Some("prod") | Some("production") => Profile::Production,
+            Some(other) => panic!(
+                "{} must be one of dev, staging or prod, got {:?}",
+                var, other
+            ),
+        }
+    }
+
+    /// Whether to serve the IDE chosen by `ide`.
+    fn enable_ide(&self) -> bool {
+        self.profile() != Profile::Production
+    }
+
+    fn ide(&self) -> Ide {
+        Ide::graphiql()
+    }
+
+    fn enable_introspection(&self) -> bool {
+        self.profile() != Profile::Production
+    }
+
+    /// When to add Apollo Tracing data to responses. See the `apollo_tracing` module.
+    fn apollo_tracing(&self) -> ApolloTracing {
+        if self.profile() == Profile::Production {
+            ApolloTracing::Disabled
+        } else {
+            ApolloTracing::OnRequest
+        }
+    }
+
+    /// Send the messages of unexpected errors to clients. See the `errors` module.
+    fn expose_internal_errors(&self) -> bool {
+        self.profile() == Profile::Development
+    }
+
     fn graphql_path(&self) -> &'static str {
         "/graphql"
     }
@@ -82,6 +291,57 @@ pub trait GraphqlApp {
         "/"
     }
 
+    fn cors(&self) -> Option<Cors> {
+        None
+    }
+
+    /// Log a line for every GraphQL request. See the `access_log` module.
+    fn access_log(&self) -> Option<AccessLog> {
+        Some(AccessLog::json())
+    }
+
+    /// Variables whose names contain one of these, ignoring case, are redacted in logs.
+    fn redacted_variables(&self) -> Vec<String> {
+        DEFAULT_REDACTED_VARIABLES
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    /// Log operations that take longer than this at WARN, with their query, variables and
+    /// slowest resolvers.
+    fn slow_query_threshold(&self) -> Option<Duration> {
+        Some(Duration::from_secs(1))
+    }
+
+    /// Count the SQL statements of each operation. Requires `Connection` to be a
+    /// `CountingConnection`, otherwise nothing is counted. See the `sql_counting` module.
+    fn count_sql_statements(&self) -> bool {
+        self.profile() == Profile::Development
+    }
+
+    /// Warn when the same SQL statement runs more than this many times in one operation.
+    fn n_plus_one_threshold(&self) -> usize {
+        10
+    }
+
+    #[cfg(feature = "metrics")]
+    fn metrics_path(&self) -> &'static str {
+        "/metrics"
+    }
+
+    /// Serve metrics on this port rather than next to the GraphQL endpoint.
+    #[cfg(feature = "metrics")]
+    fn metrics_admin_port(&self) -> Option<u16> {
+        None
+    }
+
+    /// Verify bearer tokens before the context is created.
+    #[cfg(feature = "auth")]
+    fn jwt_auth(&self) -> Option<JwtAuth> {
+        None
+    }
+
     fn database_connection_pool_max_size(&self) -> u32 {
         10
     }
@@ -92,7 +352,49 @@ pub trait GraphqlApp {
 
     fn database_url(&self) -> String {
         let var = self.database_url_env_var();
-        std::env::var(var).expect(&format!("{} must be set", var))
+        std::env::var(var).unwrap_or_else(|_| panic!("{} must be set", var))
+    }
+
+    fn max_query_depth(&self) -> Option<usize> {
+        None
+    }
+
+    fn max_query_complexity(&self) -> Option<usize> {
+        None
+    }
+
+    fn field_costs(&self) -> FieldCosts {
+        FieldCosts::new()
+    }
+
+    fn persisted_query_store(
+        &self,
+        _database_connection_pool: &Pool<ConnectionManager<Self::Connection>>,
+    ) -> Box<dyn PersistedQueryStore> {
+        Box::new(InMemoryPersistedQueryStore::new(1000))
+    }
+
+    fn query_allowlist(&self) -> Option<QueryAllowlist> {
+        None
+    }
+
+    /// The SDL file the schema is generated from, usually the file given to
+    /// `graphql_schema_from_file!`. Its `@auth` directives are enforced, see the
+    /// `authorization` module.
+    fn schema_file(&self) -> Option<PathBuf> {
+        None
+    }
+
+    fn max_upload_file_size(&self) -> u64 {
+        10 * 1024 * 1024
+    }
+
+    fn max_upload_total_size(&self) -> u64 {
+        50 * 1024 * 1024
+    }
+
+    fn upload_temp_dir(&self) -> PathBuf {
+        std::env::temp_dir()
     }
 }
 
@@ -105,7 +407,18 @@ where
 
     fn new() -> Self;
 
-    fn run<App: GraphqlApp>(&self, app: App, config: WebFrameworkConfig<Connection>)
+    fn run<App>(&self, app: App, config: WebFrameworkConfig<Connection>)
+    where
+        App: GraphqlApp<
+            Adapter = Self,
+            Connection = Connection,
+            Query = Query,
+            Mutation = Mutation,
+            Context = Context,
+        >;
+
+    /// The routes `run` serves for `app`, for the `routes` command.
+    fn routes<App>(&self, app: &App) -> Vec<RouteInfo>
     where
         App: GraphqlApp<
             Adapter = Self,
@@ -116,10 +429,47 @@ where
         >;
 }
 
+/// A route an adapter serves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteInfo {
+    pub method: &'static str,
+    pub path: String,
+    pub description: String,
+}
+
+impl RouteInfo {
+    fn new(method: &'static str, path: &str, description: &str) -> Self {
+        RouteInfo {
+            method,
+            path: path.to_string(),
+            description: description.to_string(),
+        }
+    }
+}
+
+/// Which environment the app is running in. Decides the defaults of settings that shouldn't
+/// be enabled in production.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Development,
+    Staging,
+    Production,
+}
+
 pub struct WebFrameworkConfig<Connection: 'static + diesel::Connection> {
+    access_log: Option<AccessLog>,
+    cors: Option<Cors>,
     database_connection_pool: Pool<ConnectionManager<Connection>>,
+    execution_config: ExecutionConfig,
     graphql_path: &'static str,
+    ide: Ide,
+    #[cfg(feature = "auth")]
+    jwt_auth: Option<JwtAuth>,
     mount_graphiql_at: &'static str,
+    #[cfg(feature = "metrics")]
+    metrics: MetricsConfig,
     mount_graphql_at: &'static str,
     port: u16,
+    profile: Profile,
+    upload_config: UploadConfig,
 }
@@ -0,0 +1,385 @@
+//! Batching and caching of loads, in the style of DataLoader.
+//!
+//! A `Loader` loads values by key with a `BatchFn`, and caches them for as long as it lives. Put
+//! loaders in the context so each request gets its own. Batch functions are given the context,
+//! so they can use the connection it holds:
+//!
+//! ```ignore
+//! struct PostsByUser;
+//!
+//! impl BatchFn<i32, Vec<Post>> for PostsByUser {
+//!     type Context = Context;
+//!     type Error = diesel::result::Error;
+//!
+//!     fn load(&self, context: &Context, ids: &[i32]) -> Result<HashMap<i32, Vec<Post>>, Error> {
+//!         let posts = posts::table
+//!             .filter(posts::user_id.eq_any(ids))
+//!             .load::<Post>(&*context.db_con)?;
+//!         Ok(group_by_user_id(posts))
+//!     }
+//! }
+//!
+//! pub struct Context {
+//!     pub db_con: PooledConnection<ConnectionManager<PgConnection>>,
+//!     pub posts_by_user: Loader<i32, Vec<Post>, PostsByUser>,
+//! }
+//! ```
+//!
+//! juniper resolves fields one at a time, so a loader can't see which keys sibling fields will
+//! ask for until they do. When the values with those fields come from another loader, it can
+//! queue the keys for you: after every batch it loads, `queue_children` hands the loaded values
+//! to a function that picks the keys they'll load from the child loader, and the first `load`
+//! of a sibling loads them all in one batch:
+//!
+//! ```ignore
+//! let posts_by_user = Arc::new(Loader::new(PostsByUser));
+//! let users = Loader::new(UsersById)
+//!     .queue_children(posts_by_user.clone(), |user: &User| Some(user.id));
+//!
+//! fn field_posts(&self, executor: &Executor<'_, Context>, ...) -> FieldResult<Vec<Post>> {
+//!     let context = executor.context();
+//!     let posts = context.posts_by_user.load(context, self.id)?;
+//!     Ok(posts.unwrap_or_default())
+//! }
+//! ```
+//!
+//! Otherwise the resolver returning a list queues the keys its items will load:
+//!
+//! ```ignore
+//! fn field_users(&self, executor: &Executor<'_, Context>, ...) -> FieldResult<Vec<User>> {
+//!     let users = ...;
+//!     executor
+//!         .context()
+//!         .posts_by_user
+//!         .queue(users.iter().map(|user| user.id));
+//!     Ok(users)
+//! }
+//! ```
+//!
+//! If a batch fails, every key in it gets the error, so the siblings of the key that triggered
+//! the batch don't each try again. Errors are cached like values until `clear` is called.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Loads the values of many keys at once.
+pub trait BatchFn<K, V> {
+    /// What loads are given, usually the juniper context the loader lives in.
+    type Context;
+    type Error;
+
+    /// Keys missing from the returned map don't have a value.
+    fn load(&self, context: &Self::Context, keys: &[K]) -> Result<HashMap<K, V>, Self::Error>;
+}
+
+/// The error of a failed batch, shared by all of the batch's keys.
+#[derive(Debug)]
+pub struct LoadError<E>(Arc<E>);
+
+impl<E> LoadError<E> {
+    pub fn inner(&self) -> &E {
+        &self.0
+    }
+}
+
+impl<E> Clone for LoadError<E> {
+    fn clone(&self) -> Self {
+        LoadError(self.0.clone())
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for LoadError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<E: Error> Error for LoadError<E> {}
+
+type Loaded<V, E> = Result<Option<V>, LoadError<E>>;
+type QueueChildren<V> = Box<dyn Fn(&V) + Send + Sync>;
+
+/// Batches and caches the loads of a `BatchFn`. See the module docs.
+pub struct Loader<K, V, F: BatchFn<K, V>> {
+    batch_fn: F,
+    max_batch_size: Option<usize>,
+    children: Vec<QueueChildren<V>>,
+    state: Mutex<State<K, V, F::Error>>,
+}
+
+struct State<K, V, E> {
+    /// `Ok(None)` for keys that were loaded but don't have a value.
+    cache: HashMap<K, Loaded<V, E>>,
+    queue: Vec<K>,
+}
+
+impl<K, V, F> Loader<K, V, F>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    F: BatchFn<K, V>,
+{
+    pub fn new(batch_fn: F) -> Self {
+        Loader {
+            batch_fn,
+            max_batch_size: None,
+            children: Vec::new(),
+            state: Mutex::new(State {
+                cache: HashMap::new(),
+                queue: Vec::new(),
+            }),
+        }
+    }
+
+    /// Split batches with more keys than this.
+    pub fn max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = Some(max_batch_size);
+        self
+    }
+
+    /// After every batch, queue the keys `keys` picks from each loaded value with `child`.
+    pub fn queue_children<CK, CV, CF, I, G>(
+        mut self,
+        child: Arc<Loader<CK, CV, CF>>,
+        keys: G,
+    ) -> Self
+    where
+        CK: 'static + Eq + Hash + Clone + Send,
+        CV: 'static + Clone + Send,
+        CF: 'static + BatchFn<CK, CV> + Send + Sync,
+        CF::Error: Send + Sync,
+        I: IntoIterator<Item = CK>,
+        G: 'static + Fn(&V) -> I + Send + Sync,
+    {
+        self.children
+            .push(Box::new(move |value| child.queue(keys(value))));
+        self
+    }
+
+    /// Load these keys with the next batch.
+    pub fn queue<I: IntoIterator<Item = K>>(&self, keys: I) {
+        let mut state = self.state();
+        let state = &mut *state;
+        let cache = &state.cache;
+        state
+            .queue
+            .extend(keys.into_iter().filter(|key| !cache.contains_key(key)));
+    }
+
+    /// The value of `key`, loading it along with the queued keys if it isn't cached.
+    pub fn load(&self, context: &F::Context, key: K) -> Result<Option<V>, LoadError<F::Error>> {
+        let mut values = self.load_many(context, vec![key])?;
+        Ok(values.pop().and_then(|value| value))
+    }
+
+    /// The values of `keys`, in the same order. Keys that aren't cached are loaded in one batch
+    /// along with the queued keys. Fails with the first error of the keys' batches.
+    pub fn load_many(
+        &self,
+        context: &F::Context,
+        keys: Vec<K>,
+    ) -> Result<Vec<Option<V>>, LoadError<F::Error>> {
+        let batch = {
+            let mut state = self.state();
+            let missing = keys
+                .iter()
+                .filter(|key| !state.cache.contains_key(key))
+                .cloned()
+                .collect::<Vec<_>>();
+            if missing.is_empty() {
+                Vec::new()
+            } else {
+                let mut batch = std::mem::take(&mut state.queue);
+                batch.extend(missing);
+                let mut seen = HashSet::new();
+                batch.retain(|key| !state.cache.contains_key(key) && seen.insert(key.clone()));
+                batch
+            }
+        };
+
+        // The lock isn't held while loading, in case the batch function uses other loaders.
+        let chunk_size = self.max_batch_size.unwrap_or(batch.len()).max(1);
+        for chunk in batch.chunks(chunk_size) {
+            match self.batch_fn.load(context, chunk) {
+                Ok(mut values) => {
+                    for value in values.values() {
+                        for queue_children in &self.children {
+                            queue_children(value);
+                        }
+                    }
+                    let mut state = self.state();
+                    for key in chunk {
+                        state.cache.insert(key.clone(), Ok(values.remove(key)));
+                    }
+                }
+                Err(err) => {
+                    let err = LoadError(Arc::new(err));
+                    let mut state = self.state();
+                    for key in chunk {
+                        state.cache.insert(key.clone(), Err(err.clone()));
+                    }
+                }
+            }
+        }
+
+        let state = self.state();
+        keys.iter()
+            .map(|key| state.cache.get(key).cloned().unwrap_or(Ok(None)))
+            .collect()
+    }
+
+    /// Cache a value loaded some other way, such as by the query that found its key.
+    pub fn prime(&self, key: K, value: V) {
+        self.state().cache.insert(key, Ok(Some(value)));
+    }
+
+    /// Forget the cached value or error of `key`, for example after a mutation changed it.
+    pub fn clear(&self, key: &K) {
+        self.state().cache.remove(key);
+    }
+
+    fn state(&self) -> MutexGuard<'_, State<K, V, F::Error>> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Doubles keys, failing for batches containing 13, and records its batches.
+    #[derive(Default)]
+    struct Doubler {
+        batches: Mutex<Vec<Vec<i32>>>,
+    }
+
+    impl BatchFn<i32, i32> for Doubler {
+        type Context = ();
+        type Error = String;
+
+        fn load(&self, _: &(), keys: &[i32]) -> Result<HashMap<i32, i32>, String> {
+            self.batches.lock().unwrap().push(keys.to_vec());
+            if keys.contains(&13) {
+                return Err("unlucky".to_string());
+            }
+            Ok(keys
+                .iter()
+                .filter(|key| **key >= 0)
+                .map(|key| (*key, key * 2))
+                .collect())
+        }
+    }
+
+    impl Loader<i32, i32, Doubler> {
+        fn batches(&self) -> Vec<Vec<i32>> {
+            self.batch_fn.batches.lock().unwrap().clone()
+        }
+    }
+
+    #[test]
+    fn queued_keys_are_loaded_with_the_first_load() {
+        let loader = Loader::new(Doubler::default());
+        loader.queue(vec![1, 2, 3]);
+
+        assert_eq!(loader.load(&(), 2).unwrap(), Some(4));
+        assert_eq!(loader.load(&(), 1).unwrap(), Some(2));
+        assert_eq!(loader.load(&(), -1).unwrap(), None);
+        assert_eq!(loader.batches(), vec![vec![1, 2, 3], vec![-1]]);
+    }
+
+    #[test]
+    fn batches_are_split() {
+        let loader = Loader::new(Doubler::default()).max_batch_size(2);
+
+        assert_eq!(
+            loader.load_many(&(), vec![1, 2, 3]).unwrap(),
+            vec![Some(2), Some(4), Some(6)]
+        );
+        assert_eq!(loader.batches(), vec![vec![1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn every_key_of_a_failed_batch_gets_the_error() {
+        let loader = Loader::new(Doubler::default());
+        loader.queue(vec![12, 13]);
+
+        assert_eq!(loader.load(&(), 13).unwrap_err().to_string(), "unlucky");
+        assert_eq!(loader.load(&(), 12).unwrap_err().to_string(), "unlucky");
+        assert_eq!(loader.batches(), vec![vec![12, 13]]);
+    }
+
+    #[test]
+    fn other_chunks_load_when_one_fails() {
+        let loader = Loader::new(Doubler::default()).max_batch_size(1);
+
+        assert!(loader.load_many(&(), vec![13, 1]).is_err());
+        assert_eq!(loader.load(&(), 1).unwrap(), Some(2));
+        assert_eq!(loader.batches(), vec![vec![13], vec![1]]);
+    }
+
+    #[test]
+    fn clearing_a_failed_key_loads_it_again() {
+        let loader = Loader::new(Doubler::default());
+        assert!(loader.load(&(), 13).is_err());
+
+        loader.clear(&13);
+        assert!(loader.load(&(), 13).is_err());
+        assert_eq!(loader.batches(), vec![vec![13], vec![13]]);
+    }
+
+    #[test]
+    fn children_of_loaded_values_are_batched() {
+        let child = Arc::new(Loader::new(Doubler::default()));
+        let parent = Loader::new(Doubler::default())
+            .queue_children(child.clone(), |value: &i32| vec![*value, value + 1]);
+
+        assert_eq!(
+            parent.load_many(&(), vec![1, 2]).unwrap(),
+            vec![Some(2), Some(4)]
+        );
+        assert_eq!(child.load(&(), 2).unwrap(), Some(4));
+        assert_eq!(child.load(&(), 5).unwrap(), Some(10));
+
+        let mut batches = child.batches();
+        batches[0].sort();
+        assert_eq!(batches, vec![vec![2, 3, 4, 5]]);
+    }
+
+    pub struct Context {
+        factor: i32,
+        multiplied: Loader<i32, i32, Multiplier>,
+    }
+
+    struct Multiplier;
+
+    impl BatchFn<i32, i32> for Multiplier {
+        type Context = Context;
+        type Error = String;
+
+        fn load(&self, context: &Context, keys: &[i32]) -> Result<HashMap<i32, i32>, String> {
+            Ok(keys
+                .iter()
+                .map(|key| (*key, key * context.factor))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn batch_functions_use_the_context_the_loader_lives_in() {
+        let context = Context {
+            factor: 3,
+            multiplied: Loader::new(Multiplier),
+        };
+
+        assert_eq!(context.multiplied.load(&context, 2).unwrap(), Some(6));
+        assert_eq!(
+            context.multiplied.load_many(&context, vec![1, 2]).unwrap(),
+            vec![Some(3), Some(6)]
+        );
+    }
+}
@@ -0,0 +1,359 @@
+//! Loading only what a query asks for, using the `QueryTrail` juniper-from-schema passes to
+//! resolvers.
+//!
+//! `select_columns!` maps the fields of a GraphQL type to the columns of its table, once per
+//! type. `columns` then selects a column only if its field is in the query. Columns that aren't
+//! selected are loaded as `NULL`, so they must be `Option`s in the loaded struct. The columns in
+//! brackets are always selected and keep their type:
+//!
+//! ```ignore
+//! #[derive(Queryable, Identifiable)]
+//! pub struct User {
+//!     id: i32,
+//!     name: Option<String>,
+//!     email: Option<String>,
+//! }
+//!
+//! select_columns!(User => users::table, Pg, [users::id], {
+//!     name => users::name,
+//!     email => users::email,
+//! });
+//!
+//! fn field_users(
+//!     &self,
+//!     executor: &Executor<'_, Context>,
+//!     trail: &QueryTrail<'_, User, Walked>,
+//! ) -> FieldResult<Vec<User>> {
+//!     let db = &gimme_graphql::get_connection(&executor.context().db_pool)?;
+//!     let users = users::table
+//!         .into_boxed()
+//!         .select(lookahead::columns::<User, _>(trail))
+//!         .load::<User>(db)?;
+//!     Ok(users)
+//! }
+//!
+//! impl UserFields for User {
+//!     fn field_name(&self, _: &Executor<'_, Context>) -> FieldResult<&String> {
+//!         lookahead::selected(&self.name)
+//!     }
+//! }
+//! ```
+//!
+//! `select_columns!` must be used where `QueryTrail` is in scope, and the query must be boxed,
+//! as the selected columns are.
+//!
+//! `eager_load` loads an association of every row with one query, if it's in the query:
+//!
+//! ```ignore
+//! let posts = lookahead::eager_load(&users, trail.posts().walk().is_some(), |users| {
+//!     Post::belonging_to(users).load::<Post>(db)
+//! })?;
+//! let users = users.into_iter().zip(posts).collect::<Vec<_>>();
+//! ```
+
+use diesel::associations::{BelongsTo, GroupedBy, Identifiable};
+use diesel::backend::Backend;
+use diesel::expression::BoxableExpression;
+#[doc(hidden)]
+pub use diesel::expression::Expression;
+use diesel::sql_types::IntoNullable;
+use diesel::{dsl, NullableExpressionMethods};
+use juniper::FieldResult;
+use std::borrow::Borrow;
+
+/// A column selected by `select_columns!`.
+pub type SelectedColumn<QS, DB, ST> = Box<dyn BoxableExpression<QS, DB, SqlType = ST> + Send>;
+
+/// The SQL type of a column that is only selected if its field is in the query.
+pub type NullableSqlType<C> = <<C as Expression>::SqlType as IntoNullable>::Nullable;
+
+/// The columns a GraphQL type is loaded from, implemented with `select_columns!`.
+pub trait SelectColumns<Trail> {
+    type Columns;
+
+    /// The columns of the fields `trail` walks.
+    fn columns(trail: &Trail) -> Self::Columns;
+}
+
+/// The columns to load `T` from for the fields `trail` walks. See the module docs.
+pub fn columns<T, Trail>(trail: &Trail) -> T::Columns
+where
+    T: SelectColumns<Trail>,
+{
+    T::columns(trail)
+}
+
+/// Map the fields of a GraphQL type to the columns of its table. See the module docs.
+///
+/// `select_columns!(Type => table, backend, [always selected columns], { field => column })`
+#[macro_export]
+macro_rules! select_columns {
+    (
+        $type:ty => $table:ty, $db:ty,
+        [$($always:path),* $(,)*],
+        { $($field:ident => $column:path),* $(,)* }
+    ) => {
+        impl<'a> $crate::lookahead::SelectColumns<QueryTrail<'a, $type, ::juniper_from_schema::Walked>>
+            for $type
+        {
+            type Columns = (
+                $($crate::lookahead::SelectedColumn<
+                    $table,
+                    $db,
+                    <$always as $crate::lookahead::Expression>::SqlType,
+                >,)*
+                $($crate::lookahead::SelectedColumn<
+                    $table,
+                    $db,
+                    $crate::lookahead::NullableSqlType<$column>,
+                >,)*
+            );
+
+            fn columns(
+                trail: &QueryTrail<'a, $type, ::juniper_from_schema::Walked>,
+            ) -> Self::Columns {
+                (
+                    $($crate::lookahead::always::<$table, $db, _>($always),)*
+                    $($crate::lookahead::if_selected::<$table, $db, _>(trail.$field(), $column),)*
+                )
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+pub fn always<QS, DB, C>(column: C) -> SelectedColumn<QS, DB, C::SqlType>
+where
+    DB: Backend,
+    C: BoxableExpression<QS, DB> + Send + 'static,
+{
+    Box::new(column)
+}
+
+#[doc(hidden)]
+pub fn if_selected<QS, DB, C>(
+    selected: bool,
+    column: C,
+) -> SelectedColumn<QS, DB, NullableSqlType<C>>
+where
+    DB: Backend,
+    C: Expression,
+    C::SqlType: IntoNullable,
+    NullableSqlType<C>: Send + 'static,
+    dsl::Nullable<C>: BoxableExpression<QS, DB, SqlType = NullableSqlType<C>> + Send + 'static,
+{
+    if selected {
+        Box::new(column.nullable())
+    } else {
+        Box::new(dsl::sql::<NullableSqlType<C>>("NULL"))
+    }
+}
+
+/// The value of a column selected by `select_columns!`, for use in the resolver of its field.
+///
+/// Fails if the column wasn't selected, which means the mapping in `select_columns!` doesn't
+/// match the resolvers.
+pub fn selected<T>(value: &Option<T>) -> FieldResult<&T> {
+    value
+        .as_ref()
+        .ok_or_else(|| "column for field wasn't selected".into())
+}
+
+/// The children of each parent, loaded with one call to `load` if `walked` is true.
+///
+/// Returns one `Vec` per parent, in the same order, which are empty if `walked` is false.
+pub fn eager_load<'a, Parent, Child, E, F>(
+    parents: &'a [Parent],
+    walked: bool,
+    load: F,
+) -> Result<Vec<Vec<Child>>, E>
+where
+    F: FnOnce(&'a [Parent]) -> Result<Vec<Child>, E>,
+    Child: BelongsTo<Parent>,
+    &'a Parent: Identifiable,
+    <&'a Parent as Identifiable>::Id: Borrow<Child::ForeignKey>,
+{
+    if !walked || parents.is_empty() {
+        return Ok(parents.iter().map(|_| Vec::new()).collect());
+    }
+
+    Ok(load(parents)?.grouped_by(parents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::pg::Pg;
+    use diesel::prelude::*;
+    use juniper::{EmptyMutation, Executor, Variables};
+    use juniper_from_schema::graphql_schema;
+    use std::sync::Mutex;
+
+    graphql_schema! {
+        schema {
+            query: Query
+        }
+
+        type Query {
+            users: [User!]! @juniper(ownership: "owned")
+        }
+
+        type User {
+            id: Int! @juniper(ownership: "owned")
+            name: String @juniper(ownership: "owned")
+            email: String @juniper(ownership: "owned")
+        }
+    }
+
+    mod schema {
+        table! {
+            users {
+                id -> Integer,
+                name -> Text,
+                email -> Text,
+            }
+        }
+
+        table! {
+            posts {
+                id -> Integer,
+                user_id -> Integer,
+            }
+        }
+    }
+
+    use self::schema::{posts, users};
+
+    pub struct User;
+
+    select_columns!(User => users::table, Pg, [users::id], {
+        name => users::name,
+        email => users::email,
+    });
+
+    impl UserFields for User {
+        fn field_id(&self, _: &Executor<'_, Context>) -> FieldResult<i32> {
+            Ok(1)
+        }
+
+        fn field_name(&self, _: &Executor<'_, Context>) -> FieldResult<Option<String>> {
+            Ok(None)
+        }
+
+        fn field_email(&self, _: &Executor<'_, Context>) -> FieldResult<Option<String>> {
+            Ok(None)
+        }
+    }
+
+    #[derive(Default)]
+    pub struct Context {
+        sql: Mutex<String>,
+    }
+
+    impl juniper::Context for Context {}
+
+    pub struct Query;
+
+    impl QueryFields for Query {
+        fn field_users(
+            &self,
+            executor: &Executor<'_, Context>,
+            trail: &QueryTrail<'_, User, Walked>,
+        ) -> FieldResult<Vec<User>> {
+            let query = users::table.into_boxed().select(columns::<User, _>(trail));
+            *executor.context().sql.lock().unwrap() =
+                diesel::debug_query::<Pg, _>(&query).to_string();
+            Ok(vec![User])
+        }
+    }
+
+    fn sql(query: &str) -> String {
+        let root_node = juniper::RootNode::new(Query, EmptyMutation::new());
+        let context = Context::default();
+        let (_, errors) =
+            juniper::execute(query, None, &root_node, &Variables::new(), &context).unwrap();
+        assert!(errors.is_empty(), "{:?}", errors);
+        context.sql.into_inner().unwrap()
+    }
+
+    #[test]
+    fn only_the_columns_of_walked_fields_are_selected() {
+        let sql = sql("{ users { id name } }");
+
+        assert!(
+            sql.contains(r#""users"."id", "users"."name", NULL"#),
+            "{}",
+            sql
+        );
+        assert!(!sql.contains("email"), "{}", sql);
+    }
+
+    #[test]
+    fn always_selected_columns_are_selected_without_their_fields() {
+        let sql = sql("{ users { email } }");
+
+        assert!(
+            sql.contains(r#""users"."id", NULL, "users"."email""#),
+            "{}",
+            sql
+        );
+    }
+
+    #[derive(Identifiable, Debug)]
+    #[table_name = "users"]
+    struct UserRow {
+        id: i32,
+    }
+
+    #[derive(Identifiable, Associations, Debug, PartialEq)]
+    #[belongs_to(UserRow, foreign_key = "user_id")]
+    #[table_name = "posts"]
+    struct Post {
+        id: i32,
+        user_id: i32,
+    }
+
+    fn post(id: i32, user_id: i32) -> Post {
+        Post { id, user_id }
+    }
+
+    #[test]
+    fn eager_loaded_children_are_grouped_by_parent_in_parent_order() {
+        let users = vec![UserRow { id: 3 }, UserRow { id: 1 }, UserRow { id: 2 }];
+        let mut loaded_for = Vec::new();
+
+        let posts = eager_load(&users, true, |users| {
+            loaded_for.extend(users.iter().map(|user| user.id));
+            Ok::<_, ()>(vec![post(10, 1), post(11, 3), post(12, 1)])
+        })
+        .unwrap();
+
+        assert_eq!(loaded_for, vec![3, 1, 2]);
+        assert_eq!(
+            posts,
+            vec![vec![post(11, 3)], vec![post(10, 1), post(12, 1)], vec![]]
+        );
+    }
+
+    #[test]
+    fn nothing_is_loaded_unless_the_field_is_walked_and_there_are_parents() {
+        let users = vec![UserRow { id: 1 }, UserRow { id: 2 }];
+        let unexpected = |_: &[UserRow]| -> Result<Vec<Post>, ()> { panic!("loaded") };
+
+        assert_eq!(
+            eager_load(&users, false, unexpected).unwrap(),
+            vec![vec![], vec![]]
+        );
+        assert!(eager_load(&[], true, unexpected).unwrap().is_empty());
+    }
+
+    #[test]
+    fn eager_load_errors_are_returned() {
+        let users = vec![UserRow { id: 1 }];
+
+        assert_eq!(
+            eager_load(&users, true, |_| Err::<Vec<Post>, _>("failed")),
+            Err("failed")
+        );
+    }
+}
@@ -0,0 +1,415 @@
+//! Prometheus metrics. Requires the `metrics` feature.
+//!
+//! The metrics are served in the Prometheus text format at `GraphqlApp::metrics_path`, which
+//! defaults to `/metrics`. If `GraphqlApp::metrics_admin_port` returns a port they're served on
+//! that port instead, so they aren't reachable through the public one.
+//!
+//! | Metric | Labels |
+//! |---|---|
+//! | `graphql_requests_total` | `operation_name`, `operation_type` |
+//! | `graphql_request_duration_seconds` | `operation_name`, `operation_type` |
+//! | `graphql_errors_total` | `operation_name`, `operation_type` |
+//! | `graphql_resolver_duration_seconds` | `type_name`, `field_name` |
+//! | `graphql_panics_total` | |
+//! | `db_pool_connections` | |
+//! | `db_pool_idle_connections` | |
+//! | `db_pool_wait_seconds` | |
+//! | `db_pool_timeouts_total` | |
+//!
+//! Resolver durations are only recorded for the fields of the query and mutation types. The
+//! `operation_name` label is only set for operations in documents from the query allowlist, so
+//! clients can't create new time series by making up names. Other operations are labelled
+//! `other`, including persisted queries, since any client can persist a query.
+
+use hyper::header::{self, HeaderValue};
+use hyper::rt::Future;
+use hyper::service::service_fn_ok;
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use lazy_static::lazy_static;
+use prometheus::{
+    core::Collector, Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, Opts, Registry, TextEncoder,
+};
+use r2d2::{ManageConnection, Pool};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub(crate) struct MetricsConfig {
+    pub(crate) path: &'static str,
+    pub(crate) admin_port: Option<u16>,
+}
+
+struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    errors: IntCounterVec,
+    resolver_duration: HistogramVec,
+    panics: IntCounter,
+    pool_connections: IntGauge,
+    pool_idle_connections: IntGauge,
+    pool_wait: Histogram,
+    pool_timeouts: IntCounter,
+}
+
+lazy_static! {
+    static ref METRICS: Metrics = Metrics::new();
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let operation_labels = &["operation_name", "operation_type"];
+        Metrics {
+            requests: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("graphql_requests_total", "GraphQL operations executed"),
+                    operation_labels,
+                ),
+            )
+            .expect("graphql_requests_total is a valid metric and only registered here"),
+            request_duration: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new(
+                        "graphql_request_duration_seconds",
+                        "Time taken to execute GraphQL operations",
+                    ),
+                    operation_labels,
+                ),
+            )
+            .expect("graphql_request_duration_seconds is a valid metric and only registered here"),
+            errors: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new(
+                        "graphql_errors_total",
+                        "Errors in the responses of GraphQL operations",
+                    ),
+                    operation_labels,
+                ),
+            )
+            .expect("graphql_errors_total is a valid metric and only registered here"),
+            resolver_duration: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new(
+                        "graphql_resolver_duration_seconds",
+                        "Time taken by the resolvers of top-level fields",
+                    ),
+                    &["type_name", "field_name"],
+                ),
+            )
+            .expect("graphql_resolver_duration_seconds is a valid metric and only registered here"),
+            panics: register(
+                &registry,
+                IntCounter::new("graphql_panics_total", "Panics caught while executing"),
+            )
+            .expect("graphql_panics_total is a valid metric and only registered here"),
+            pool_connections: register(
+                &registry,
+                IntGauge::new("db_pool_connections", "Connections in the database pool"),
+            )
+            .expect("db_pool_connections is a valid metric and only registered here"),
+            pool_idle_connections: register(
+                &registry,
+                IntGauge::new(
+                    "db_pool_idle_connections",
+                    "Idle connections in the database pool",
+                ),
+            )
+            .expect("db_pool_idle_connections is a valid metric and only registered here"),
+            pool_wait: register(
+                &registry,
+                Histogram::with_opts(HistogramOpts::new(
+                    "db_pool_wait_seconds",
+                    "Time spent waiting for a connection from the database pool",
+                )),
+            )
+            .expect("db_pool_wait_seconds is a valid metric and only registered here"),
+            pool_timeouts: register(
+                &registry,
+                IntCounter::new(
+                    "db_pool_timeouts_total",
+                    "Times waiting for a connection from the database pool timed out",
+                ),
+            )
+            .expect("db_pool_timeouts_total is a valid metric and only registered here"),
+            registry,
+        }
+    }
+}
+
+/// Add a newly created metric to the registry. Fails if the metric's name or labels are invalid,
+/// or if another metric with the same name is already registered.
+fn register<M>(registry: &Registry, metric: prometheus::Result<M>) -> prometheus::Result<M>
+where
+    M: 'static + Collector + Clone,
+{
+    let metric = metric?;
+    registry.register(Box::new(metric.clone()))?;
+    Ok(metric)
+}
+
+pub(crate) fn observe_operation(
+    operation_name: &str,
+    operation_type: Option<&str>,
+    duration: Duration,
+    error_count: usize,
+) {
+    let labels = &[operation_name, operation_type.unwrap_or("unknown")];
+    METRICS.requests.with_label_values(labels).inc();
+    METRICS
+        .request_duration
+        .with_label_values(labels)
+        .observe(seconds(duration));
+    if error_count > 0 {
+        METRICS
+            .errors
+            .with_label_values(labels)
+            .inc_by(error_count as i64);
+    }
+}
+
+pub(crate) fn observe_resolver(type_name: &str, field_name: &str, duration: Duration) {
+    METRICS
+        .resolver_duration
+        .with_label_values(&[type_name, field_name])
+        .observe(seconds(duration));
+}
+
+pub(crate) fn observe_pool_checkout(wait: Duration) {
+    METRICS.pool_wait.observe(seconds(wait));
+}
+
+pub(crate) fn observe_pool_timeout() {
+    METRICS.pool_timeouts.inc();
+}
+
+pub(crate) fn observe_panic() {
+    METRICS.panics.inc();
+}
+
+/// Renders the metrics, reading the pool's state when scraped.
+pub(crate) struct MetricsEndpoint {
+    pool_state: Box<dyn Fn() -> r2d2::State + Send + Sync>,
+}
+
+impl MetricsEndpoint {
+    pub(crate) fn new<M: ManageConnection>(pool: Pool<M>) -> Self {
+        MetricsEndpoint {
+            pool_state: Box::new(move || pool.state()),
+        }
+    }
+
+    pub(crate) fn content_type(&self) -> String {
+        TextEncoder::new().format_type().to_string()
+    }
+
+    pub(crate) fn render(&self) -> Vec<u8> {
+        let state = (self.pool_state)();
+        METRICS.pool_connections.set(i64::from(state.connections));
+        METRICS
+            .pool_idle_connections
+            .set(i64::from(state.idle_connections));
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&METRICS.registry.gather(), &mut buffer)
+            .expect("failed to encode metrics");
+        buffer
+    }
+}
+
+/// Serve the metrics on their own port from a background thread.
+pub(crate) fn serve_on_admin_port(endpoint: Arc<MetricsEndpoint>, path: &'static str, port: u16) {
+    let addr = ([127, 0, 0, 1], port).into();
+
+    let new_service = move || {
+        let endpoint = endpoint.clone();
+        service_fn_ok(move |req: Request<Body>| {
+            if req.method() == Method::GET && req.uri().path() == path {
+                let mut resp = Response::new(Body::from(endpoint.render()));
+                if let Ok(content_type) = HeaderValue::from_str(&endpoint.content_type()) {
+                    resp.headers_mut()
+                        .insert(header::CONTENT_TYPE, content_type);
+                }
+                resp
+            } else {
+                let mut resp = Response::new(Body::empty());
+                *resp.status_mut() = StatusCode::NOT_FOUND;
+                resp
+            }
+        })
+    };
+
+    let server = Server::bind(&addr)
+        .serve(new_service)
+        .map_err(|e| panic!("metrics server error: {}", e));
+
+    log::info!("Serving metrics on http://{}{}", addr, path);
+
+    std::thread::spawn(move || hyper::rt::run(server));
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::allowlist::QueryAllowlist;
+    use crate::analysis::{FieldCosts, QueryLimits};
+    use crate::apollo_tracing::ApolloTracing;
+    use crate::authorization::AuthorizationRules;
+    use crate::instrumentation;
+    use crate::persisted_queries::{query_hash, InMemoryPersistedQueryStore};
+    use crate::request::{ExecutionConfig, GraphqlBatchRequest, RequestInfo};
+    use crate::request_id::RequestId;
+    use crate::telemetry::PoolCheckouts;
+    use crate::test_schema;
+    use juniper::EmptyMutation;
+    use prometheus::core::Metric;
+    use serde_json::json;
+
+    const LISTED: &str = "query MetricsListed { users(limit: 2) { id } }";
+
+    fn config() -> ExecutionConfig {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("listed.graphql"), LISTED).unwrap();
+
+        ExecutionConfig {
+            query_limits: QueryLimits {
+                max_depth: None,
+                max_complexity: None,
+                field_costs: FieldCosts::new(),
+            },
+            persisted_query_store: Box::new(InMemoryPersistedQueryStore::new(10)),
+            allowlist: Some(
+                QueryAllowlist::from_directory(dir.path())
+                    .log_only()
+                    .load()
+                    .unwrap(),
+            ),
+            enable_introspection: true,
+            expose_internal_errors: false,
+            authorization_rules: AuthorizationRules::default(),
+            apollo_tracing: ApolloTracing::Disabled,
+            redacted_variables: Vec::new(),
+            slow_query_threshold: None,
+            count_sql_statements: false,
+            n_plus_one_threshold: 1,
+        }
+    }
+
+    fn run(config: &ExecutionConfig, request: serde_json::Value) {
+        let root_node = instrumentation::schema(test_schema::Query, EmptyMutation::<()>::new());
+        let request = serde_json::from_value::<GraphqlBatchRequest>(request).unwrap();
+        let request_info = RequestInfo {
+            id: RequestId::generate(),
+            wants_apollo_tracing: false,
+            pool_checkouts: PoolCheckouts::default(),
+        };
+        request.execute(&root_node, &(), &request_info, config);
+    }
+
+    fn requests(operation_name: &str) -> i64 {
+        METRICS
+            .requests
+            .with_label_values(&[operation_name, "query"])
+            .get()
+    }
+
+    fn rendered() -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&METRICS.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn operations_are_counted_and_timed() {
+        let config = config();
+
+        run(&config, json!({ "query": LISTED }));
+        run(
+            &config,
+            json!({ "query": LISTED, "operationName": "MetricsListed" }),
+        );
+
+        assert_eq!(requests("MetricsListed"), 2);
+        assert_eq!(
+            METRICS
+                .request_duration
+                .with_label_values(&["MetricsListed", "query"])
+                .metric()
+                .get_histogram()
+                .get_sample_count(),
+            2
+        );
+        assert!(
+            METRICS
+                .resolver_duration
+                .with_label_values(&["Query", "users"])
+                .metric()
+                .get_histogram()
+                .get_sample_count()
+                >= 2
+        );
+
+        let rendered = rendered();
+        assert!(rendered.contains(
+            "graphql_requests_total{operation_name=\"MetricsListed\",operation_type=\"query\"} 2"
+        ));
+        assert!(rendered.contains(
+            "graphql_request_duration_seconds_count\
+             {operation_name=\"MetricsListed\",operation_type=\"query\"} 2"
+        ));
+        assert!(rendered.contains(
+            "graphql_resolver_duration_seconds_bucket{field_name=\"users\",type_name=\"Query\""
+        ));
+    }
+
+    #[test]
+    fn unlisted_and_persisted_operations_are_labelled_other() {
+        let config = config();
+        let unlisted = "query MetricsUnlisted { user(id: 1) { id } }";
+        let persisted = "query MetricsPersisted { user(id: 2) { id } }";
+        let persisted_query = json!({
+            "persistedQuery": { "version": 1, "sha256Hash": query_hash(persisted) }
+        });
+        let before = requests("other");
+
+        run(&config, json!({ "query": unlisted }));
+        run(
+            &config,
+            json!({ "query": persisted, "extensions": persisted_query }),
+        );
+        run(
+            &config,
+            json!({ "extensions": persisted_query, "operationName": "MetricsPersisted" }),
+        );
+
+        assert!(requests("other") - before >= 3);
+        let rendered = rendered();
+        assert!(!rendered.contains("MetricsUnlisted"));
+        assert!(!rendered.contains("MetricsPersisted"));
+    }
+
+    #[test]
+    fn errors_are_counted() {
+        let config = config();
+        let errors = || METRICS.errors.with_label_values(&["other", "query"]).get();
+        let before = errors();
+
+        run(
+            &config,
+            json!({ "query": "query MetricsErrors { user(id: 1) { email } }" }),
+        );
+
+        assert!(errors() - before >= 1);
+    }
+}
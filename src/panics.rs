@@ -0,0 +1,161 @@
+//! Turning panics in resolvers into GraphQL errors.
+//!
+//! Requests are executed inside `catch_unwind`, so a panicking resolver doesn't take down the
+//! worker thread. The client gets `Internal server error` with status 500, and the panic is
+//! logged with the operation name and a backtrace.
+//!
+//! Connections the context holds are returned to the pool as usual once the request is done.
+//! Diesel doesn't roll back a transaction whose closure panics though. `CountingConnection`
+//! does: a transaction it had open when the panic was caught is rolled back before the
+//! connection is used again, at the latest when it's next checked out of the pool. With other
+//! connection types resolvers shouldn't panic while a transaction is open.
+
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Once, Weak};
+
+static INSTALL_HOOK: Once = Once::new();
+
+thread_local! {
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    static CAUGHT: RefCell<Option<Panic>> = const { RefCell::new(None) };
+    static FLAGS: RefCell<Vec<Weak<AtomicBool>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A panic caught by [`catch`].
+pub(crate) struct Panic {
+    pub(crate) message: String,
+    pub(crate) location: String,
+    pub(crate) backtrace: backtrace::Backtrace,
+}
+
+/// Install a panic hook that records panics happening inside [`catch`] rather than printing
+/// them. Panics elsewhere go to the previous hook.
+pub(crate) fn install_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !CATCHING.with(|catching| catching.get()) {
+                previous(info);
+                return;
+            }
+
+            let message = match info.payload().downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => match info.payload().downcast_ref::<String>() {
+                    Some(message) => message.clone(),
+                    None => "Box<Any>".to_string(),
+                },
+            };
+            let location = info
+                .location()
+                .map(|location| location.to_string())
+                .unwrap_or_else(|| "unknown location".to_string());
+
+            CAUGHT.with(|caught| {
+                *caught.borrow_mut() = Some(Panic {
+                    message,
+                    location,
+                    backtrace: backtrace::Backtrace::new(),
+                })
+            });
+        }));
+    });
+}
+
+/// Run `f`, returning the panic if it panics.
+pub(crate) fn catch<F, T>(f: F) -> Result<T, Panic>
+where
+    F: FnOnce() -> T,
+{
+    let was_catching = CATCHING.with(|catching| catching.replace(true));
+    let outer_flags = FLAGS.with(|flags| flags.borrow().len());
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|catching| catching.set(was_catching));
+    let flags = FLAGS.with(|flags| flags.borrow_mut().split_off(outer_flags));
+
+    result.map_err(|_| {
+        for flag in flags.iter().filter_map(Weak::upgrade) {
+            flag.store(true, Ordering::SeqCst);
+        }
+
+        #[cfg(feature = "metrics")]
+        crate::metrics::observe_panic();
+
+        CAUGHT
+            .with(|caught| caught.borrow_mut().take())
+            .unwrap_or_else(|| Panic {
+                message: "panic hook not installed".to_string(),
+                location: "unknown location".to_string(),
+                backtrace: backtrace::Backtrace::new(),
+            })
+    })
+}
+
+/// Set `flag` if the [`catch`] currently running on this thread catches a panic. Does nothing
+/// outside of `catch`.
+pub(crate) fn flag_on_panic(flag: &Arc<AtomicBool>) {
+    if CATCHING.with(|catching| catching.get()) {
+        FLAGS.with(|flags| flags.borrow_mut().push(Arc::downgrade(flag)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_are_set_when_a_panic_is_caught() {
+        install_hook();
+        let flag = Arc::new(AtomicBool::new(false));
+
+        let result = catch(|| {
+            flag_on_panic(&flag);
+            panic!("boom");
+        });
+
+        let panic = result.err().unwrap();
+        assert_eq!(panic.message, "boom");
+        assert!(flag.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn flags_are_left_alone_without_a_panic() {
+        let flag = Arc::new(AtomicBool::new(false));
+
+        assert_eq!(catch(|| flag_on_panic(&flag)).ok(), Some(()));
+        assert!(!flag.load(Ordering::SeqCst));
+        FLAGS.with(|flags| assert!(flags.borrow().is_empty()));
+    }
+
+    #[test]
+    fn flags_belong_to_the_innermost_catch() {
+        install_hook();
+        let outer = Arc::new(AtomicBool::new(false));
+        let inner = Arc::new(AtomicBool::new(false));
+
+        let result = catch(|| {
+            flag_on_panic(&outer);
+            catch(|| {
+                flag_on_panic(&inner);
+                panic!("inner");
+            })
+            .err()
+            .unwrap();
+        });
+
+        assert!(result.is_ok());
+        assert!(inner.load(Ordering::SeqCst));
+        assert!(!outer.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn flags_outside_catch_are_ignored() {
+        let flag = Arc::new(AtomicBool::new(false));
+
+        flag_on_panic(&flag);
+
+        FLAGS.with(|flags| assert!(flags.borrow().is_empty()));
+    }
+}
@@ -0,0 +1,378 @@
+//! Apollo's [automatic persisted queries][apq].
+//!
+//! Clients send the SHA-256 hash of a query in `extensions.persistedQuery` instead of the full
+//! query text. If the hash is unknown the server answers with a `PersistedQueryNotFound` error
+//! and the client retries with both the query and the hash, which stores the query.
+//!
+//! Queries are kept in memory by default. To share them between processes use
+//! [`DieselPersistedQueryStore`], which keeps them in a `persisted_queries` table. The `migrate`
+//! command of the [`cli`](crate::cli) creates the table.
+//!
+//! [apq]: https://www.apollographql.com/docs/apollo-server/performance/apq/
+
+use diesel::dsl;
+use diesel::insertable::Insertable;
+use diesel::prelude::*;
+use diesel::query_builder::InsertStatement;
+use diesel::query_dsl::methods::ExecuteDsl;
+use diesel::query_dsl::LoadQuery;
+use diesel::r2d2::ConnectionManager;
+use diesel::result::{DatabaseErrorKind, Error};
+use lru::LruCache;
+use r2d2::Pool;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::sync::Mutex;
+
+mod schema {
+    table! {
+        persisted_queries (hash) {
+            hash -> Text,
+            query -> Text,
+        }
+    }
+}
+
+/// Storage for persisted queries, keyed by the hex encoded SHA-256 hash of the query.
+pub trait PersistedQueryStore: Send + Sync {
+    fn get(&self, hash: &str) -> Option<String>;
+
+    fn insert(&self, hash: &str, query: &str);
+
+    /// Create the storage the store needs, if it doesn't exist yet. Run by the `migrate`
+    /// command of the [`cli`](crate::cli).
+    fn migrate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Keeps the most recently used queries in memory.
+pub struct InMemoryPersistedQueryStore {
+    cache: Mutex<LruCache<String, String>>,
+}
+
+impl InMemoryPersistedQueryStore {
+    pub fn new(capacity: usize) -> Self {
+        InMemoryPersistedQueryStore {
+            cache: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+}
+
+impl PersistedQueryStore for InMemoryPersistedQueryStore {
+    fn get(&self, hash: &str) -> Option<String> {
+        self.cache
+            .lock()
+            .expect("persisted query cache poisoned")
+            .get(&hash.to_string())
+            .cloned()
+    }
+
+    fn insert(&self, hash: &str, query: &str) {
+        self.cache
+            .lock()
+            .expect("persisted query cache poisoned")
+            .put(hash.to_string(), query.to_string());
+    }
+}
+
+type FindQuery = dsl::Limit<
+    dsl::Select<
+        dsl::Find<schema::persisted_queries::table, String>,
+        schema::persisted_queries::query,
+    >,
+>;
+
+type InsertQuery = InsertStatement<
+    schema::persisted_queries::table,
+    <(
+        dsl::Eq<schema::persisted_queries::hash, String>,
+        dsl::Eq<schema::persisted_queries::query, String>,
+    ) as Insertable<schema::persisted_queries::table>>::Values,
+>;
+
+/// Stores queries in the `persisted_queries` table, using the app's connection pool.
+pub struct DieselPersistedQueryStore<C: 'static + Connection> {
+    database_connection_pool: Pool<ConnectionManager<C>>,
+}
+
+impl<C: 'static + Connection> DieselPersistedQueryStore<C> {
+    pub fn new(database_connection_pool: Pool<ConnectionManager<C>>) -> Self {
+        DieselPersistedQueryStore {
+            database_connection_pool,
+        }
+    }
+}
+
+impl<C> PersistedQueryStore for DieselPersistedQueryStore<C>
+where
+    C: 'static + Connection,
+    FindQuery: LoadQuery<C, String>,
+    InsertQuery: ExecuteDsl<C>,
+{
+    fn get(&self, hash: &str) -> Option<String> {
+        use self::schema::persisted_queries;
+
+        let db = match crate::get_connection(&self.database_connection_pool) {
+            Ok(db) => db,
+            Err(err) => {
+                log::error!("Failed to get db connection for persisted query: {}", err);
+                return None;
+            }
+        };
+
+        persisted_queries::table
+            .find(hash.to_string())
+            .select(persisted_queries::query)
+            .first::<String>(&*db)
+            .optional()
+            .unwrap_or_else(|err| {
+                log::error!("Failed to load persisted query: {}", err);
+                None
+            })
+    }
+
+    fn insert(&self, hash: &str, query: &str) {
+        use self::schema::persisted_queries;
+
+        let db = match crate::get_connection(&self.database_connection_pool) {
+            Ok(db) => db,
+            Err(err) => {
+                log::error!("Failed to get db connection for persisted query: {}", err);
+                return;
+            }
+        };
+
+        let result = diesel::insert_into(persisted_queries::table)
+            .values((
+                persisted_queries::hash.eq(hash.to_string()),
+                persisted_queries::query.eq(query.to_string()),
+            ))
+            .execute(&*db);
+
+        match result {
+            // Another request stored the same query first.
+            Ok(_) | Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {}
+            Err(err) => log::error!("Failed to store persisted query: {}", err),
+        }
+    }
+
+    fn migrate(&self) -> Result<(), String> {
+        let db = crate::get_connection(&self.database_connection_pool)
+            .map_err(|err| format!("failed to get db connection: {}", err))?;
+
+        db.batch_execute(
+            "CREATE TABLE IF NOT EXISTS persisted_queries (
+                hash VARCHAR(64) PRIMARY KEY,
+                query TEXT NOT NULL
+            )",
+        )
+        .map_err(|err| format!("failed to create persisted_queries table: {}", err))
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct PersistedQuery {
+    version: u32,
+    #[serde(rename = "sha256Hash")]
+    sha256_hash: String,
+}
+
+impl PersistedQuery {
+    /// The hash in lowercase, if the version is supported.
+    pub(crate) fn hash(&self) -> Option<String> {
+        if self.version == 1 {
+            Some(self.sha256_hash.to_lowercase())
+        } else {
+            None
+        }
+    }
+}
+
+pub(crate) enum PersistedQueryError {
+    NotFound,
+    UnsupportedVersion,
+    HashMismatch,
+    MissingQuery,
+}
+
+impl PersistedQueryError {
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        let (message, code) = match self {
+            PersistedQueryError::NotFound => {
+                ("PersistedQueryNotFound", "PERSISTED_QUERY_NOT_FOUND")
+            }
+            PersistedQueryError::UnsupportedVersion => (
+                "Unsupported persisted query version",
+                "PERSISTED_QUERY_UNSUPPORTED_VERSION",
+            ),
+            PersistedQueryError::HashMismatch => {
+                ("provided sha does not match query", "BAD_USER_INPUT")
+            }
+            PersistedQueryError::MissingQuery => ("Must provide query string", "BAD_USER_INPUT"),
+        };
+
+        serde_json::json!({
+            "errors": [{ "message": message, "extensions": { "code": code } }]
+        })
+    }
+
+    /// Clients retry after `PersistedQueryNotFound`, so that isn't treated as a bad request.
+    pub(crate) fn is_bad_request(&self) -> bool {
+        !matches!(self, PersistedQueryError::NotFound)
+    }
+}
+
+/// Find the query text of a request, using and updating the store if the request has a
+/// persisted query extension.
+pub(crate) fn resolve_query<'a>(
+    query: Option<&'a str>,
+    persisted_query: Option<&PersistedQuery>,
+    store: &dyn PersistedQueryStore,
+) -> Result<Cow<'a, str>, PersistedQueryError> {
+    let persisted_query = match persisted_query {
+        Some(persisted_query) => persisted_query,
+        None => {
+            return query
+                .map(Cow::Borrowed)
+                .ok_or(PersistedQueryError::MissingQuery)
+        }
+    };
+
+    if persisted_query.version != 1 {
+        return Err(PersistedQueryError::UnsupportedVersion);
+    }
+
+    let hash = persisted_query.sha256_hash.to_lowercase();
+
+    match query {
+        Some(query) => {
+            if query_hash(query) != hash {
+                return Err(PersistedQueryError::HashMismatch);
+            }
+            store.insert(&hash, query);
+            Ok(Cow::Borrowed(query))
+        }
+        None => store
+            .get(&hash)
+            .map(Cow::Owned)
+            .ok_or(PersistedQueryError::NotFound),
+    }
+}
+
+/// The SHA-256 hash of a query, in lowercase hex.
+pub(crate) fn query_hash(query: &str) -> String {
+    format!("{:x}", Sha256::digest(query.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUERY: &str = "{ users { id } }";
+
+    fn persisted_query(sha256_hash: &str) -> PersistedQuery {
+        PersistedQuery {
+            version: 1,
+            sha256_hash: sha256_hash.to_string(),
+        }
+    }
+
+    fn resolve_err(
+        query: Option<&str>,
+        persisted_query: Option<&PersistedQuery>,
+        store: &dyn PersistedQueryStore,
+    ) -> PersistedQueryError {
+        match resolve_query(query, persisted_query, store) {
+            Ok(query) => panic!("expected an error, got query {:?}", query),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn query_without_extension_is_used_as_is() {
+        let store = InMemoryPersistedQueryStore::new(10);
+
+        let query = resolve_query(Some(QUERY), None, &store).ok().unwrap();
+
+        assert_eq!(query, QUERY);
+        assert_eq!(store.get(&query_hash(QUERY)), None);
+    }
+
+    #[test]
+    fn missing_query_without_extension_is_rejected() {
+        let store = InMemoryPersistedQueryStore::new(10);
+
+        let err = resolve_err(None, None, &store);
+
+        assert!(matches!(err, PersistedQueryError::MissingQuery));
+        assert!(err.is_bad_request());
+    }
+
+    #[test]
+    fn query_with_matching_hash_is_stored() {
+        let store = InMemoryPersistedQueryStore::new(10);
+        let extension = persisted_query(&query_hash(QUERY));
+
+        let query = resolve_query(Some(QUERY), Some(&extension), &store)
+            .ok()
+            .unwrap();
+
+        assert_eq!(query, QUERY);
+        assert_eq!(store.get(&query_hash(QUERY)), Some(QUERY.to_string()));
+    }
+
+    #[test]
+    fn stored_query_is_found_by_hash() {
+        let store = InMemoryPersistedQueryStore::new(10);
+        store.insert(&query_hash(QUERY), QUERY);
+        let extension = persisted_query(&query_hash(QUERY).to_uppercase());
+
+        let query = resolve_query(None, Some(&extension), &store).ok().unwrap();
+
+        assert_eq!(query, QUERY);
+    }
+
+    #[test]
+    fn hash_mismatch_is_rejected_and_not_stored() {
+        let store = InMemoryPersistedQueryStore::new(10);
+        let other_hash = query_hash("{ user(id: 1) { id } }");
+        let extension = persisted_query(&other_hash);
+
+        let err = resolve_err(Some(QUERY), Some(&extension), &store);
+
+        assert!(matches!(err, PersistedQueryError::HashMismatch));
+        assert!(err.is_bad_request());
+        assert_eq!(store.get(&other_hash), None);
+        assert_eq!(store.get(&query_hash(QUERY)), None);
+    }
+
+    #[test]
+    fn unknown_hash_is_not_found() {
+        let store = InMemoryPersistedQueryStore::new(10);
+        let extension = persisted_query(&query_hash(QUERY));
+
+        let err = resolve_err(None, Some(&extension), &store);
+
+        assert!(matches!(err, PersistedQueryError::NotFound));
+        assert!(!err.is_bad_request());
+        assert_eq!(
+            err.to_json()["errors"][0]["extensions"]["code"],
+            "PERSISTED_QUERY_NOT_FOUND"
+        );
+    }
+
+    #[test]
+    fn unsupported_version_is_rejected() {
+        let store = InMemoryPersistedQueryStore::new(10);
+        let extension = PersistedQuery {
+            version: 2,
+            sha256_hash: query_hash(QUERY),
+        };
+
+        let err = resolve_err(Some(QUERY), Some(&extension), &store);
+
+        assert!(matches!(err, PersistedQueryError::UnsupportedVersion));
+    }
+}
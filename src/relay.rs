@@ -0,0 +1,803 @@
+//! [Relay connections](https://relay.dev/graphql/connections.htm) for cursor pagination of
+//! Diesel queries, and global object identification.
+//!
+//! `Connection<T>` and `Edge<T>` are GraphQL types named after `T`, so a `Connection<User>` is a
+//! `UserConnection` with `edges`, `nodes`, `pageInfo` and `totalCount` fields. Use them in the
+//! schema without declaring them. `relay_connection!` names them and declares the type alias
+//! juniper-from-schema needs:
+//!
+//! ```ignore
+//! // schema.graphql
+//! type Query {
+//!     users(first: Int, after: String, last: Int, before: String): UserConnection! @juniper(ownership: "owned")
+//! }
+//!
+//! // main.rs
+//! relay_connection!(UserConnection, User);
+//!
+//! fn field_users(
+//!     &self,
+//!     executor: &Executor<'_, Context>,
+//!     _: &QueryTrail<'_, UserConnection, Walked>,
+//!     first: Option<i32>,
+//!     after: Option<String>,
+//!     last: Option<i32>,
+//!     before: Option<String>,
+//! ) -> FieldResult<UserConnection> {
+//!     let db = &gimme_graphql::get_connection(&executor.context().db_pool)?;
+//!     let connection = relay::load_page(
+//!         || users::table.into_boxed(),
+//!         users::id,
+//!         |user: &User| user.id,
+//!         &PageArgs::new(first, after, last, before),
+//!         relay::wants_total_count(executor),
+//!         db,
+//!     )?;
+//!     Ok(connection)
+//! }
+//! ```
+//!
+//! Cursors are opaque to clients, but are the key of the row they point at, so they stay valid
+//! when rows are inserted or deleted.
+//!
+//! # Object identification
+//!
+//! A `NodeRegistry` resolves the `node` and `nodes` fields of the
+//! [Node interface](https://relay.dev/graphql/objectidentification.htm). Declare the interface
+//! and fields in the schema, register a loader for every type implementing it, and resolve the
+//! fields with the registry:
+//!
+//! ```ignore
+//! // schema.graphql
+//! interface Node { id: ID! }
+//! type User implements Node { id: ID! }
+//! type Query {
+//!     node(id: ID!): Node @juniper(ownership: "owned")
+//!     nodes(ids: [ID!]!): [Node]! @juniper(ownership: "owned")
+//! }
+//!
+//! // main.rs
+//! lazy_static! {
+//!     static ref NODES: NodeRegistry<Node, Context> = NodeRegistry::new()
+//!         .register("User", |ctx: &Context, id: i32| {
+//!             let db = gimme_graphql::get_connection(&ctx.db_pool)?;
+//!             Ok(users::table.find(id).first::<User>(&db).optional()?.map(Node::from))
+//!         });
+//! }
+//!
+//! fn field_node(
+//!     &self,
+//!     executor: &Executor<'_, Context>,
+//!     _: &QueryTrail<'_, Node, Walked>,
+//!     id: ID,
+//! ) -> FieldResult<Option<Node>> {
+//!     NODES.node(executor.context(), &id)
+//! }
+//!
+//! impl UserFields for User {
+//!     fn field_id(&self, _: &Executor<'_, Context>) -> FieldResult<ID> {
+//!         Ok(relay::global_id("User", self.id))
+//!     }
+//! }
+//! ```
+
+use diesel::backend::Backend;
+use diesel::connection::Connection as DieselConnection;
+use diesel::dsl;
+use diesel::expression::AsExpression;
+use diesel::query_builder::BoxedSelectStatement;
+use diesel::query_dsl::methods::{FilterDsl, LimitDsl, OrderDsl, SelectDsl};
+use diesel::query_dsl::{LoadQuery, QueryDsl, RunQueryDsl};
+use diesel::sql_types::BigInt;
+use diesel::{Expression, ExpressionMethods};
+use juniper::meta::MetaType;
+use juniper::{
+    Arguments, DefaultScalarValue, ExecutionResult, Executor, FieldResult, GraphQLObject,
+    GraphQLType, LookAheadMethods, Registry, ID,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+const CURSOR_PREFIX: &str = "cursor:";
+
+type Page<'a, ST, QS, DB> = BoxedSelectStatement<'a, ST, QS, DB>;
+
+/// The GraphQL names of the connection and edge types of `Self`, implemented with
+/// `relay_connection!`.
+pub trait ConnectionNode {
+    const CONNECTION_NAME: &'static str;
+    const EDGE_NAME: &'static str;
+}
+
+/// Declare `$connection` as the type alias of `Connection<$node>`, with the GraphQL names
+/// `$connection` and `${node}Edge`. See the module docs.
+#[macro_export]
+macro_rules! relay_connection {
+    ($connection:ident, $node:ident) => {
+        pub type $connection = $crate::relay::Connection<$node>;
+
+        impl $crate::relay::ConnectionNode for $node {
+            const CONNECTION_NAME: &'static str = stringify!($connection);
+            const EDGE_NAME: &'static str = concat!(stringify!($node), "Edge");
+        }
+    };
+}
+
+/// A page of `T`s.
+pub struct Connection<T> {
+    pub edges: Vec<Edge<T>>,
+    pub page_info: PageInfo,
+    /// Only set if it was asked for, see `wants_total_count`.
+    pub total_count: Option<i32>,
+}
+
+pub struct Edge<T> {
+    pub node: T,
+    pub cursor: String,
+}
+
+#[derive(GraphQLObject, Debug, Clone, PartialEq, Eq)]
+pub struct PageInfo {
+    pub has_next_page: bool,
+    pub has_previous_page: bool,
+    pub start_cursor: Option<String>,
+    pub end_cursor: Option<String>,
+}
+
+/// The `first`, `after`, `last` and `before` arguments of a connection field.
+#[derive(Debug, Clone)]
+pub struct PageArgs {
+    first: Option<i32>,
+    after: Option<String>,
+    last: Option<i32>,
+    before: Option<String>,
+    max_page_size: i32,
+}
+
+impl PageArgs {
+    /// Pages have at most 100 items unless `max_page_size` is changed.
+    pub fn new(
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>,
+    ) -> Self {
+        PageArgs {
+            first,
+            after,
+            last,
+            before,
+            max_page_size: 100,
+        }
+    }
+
+    /// The largest `first` or `last` allowed, which is also the page size if neither is given.
+    pub fn max_page_size(mut self, max_page_size: i32) -> Self {
+        self.max_page_size = max_page_size;
+        self
+    }
+
+    /// Whether the page is taken from the end, and how many items it has.
+    fn page_size(&self) -> Result<(bool, i32), PaginationError> {
+        let (backward, size) = match (self.first, self.last) {
+            (Some(_), Some(_)) => {
+                return Err(PaginationError::InvalidArguments(
+                    "'first' and 'last' can't be used together".to_string(),
+                ))
+            }
+            (Some(first), None) => (false, first),
+            (None, Some(last)) => (true, last),
+            (None, None) => (false, self.max_page_size),
+        };
+
+        if size < 0 {
+            Err(PaginationError::InvalidArguments(
+                "'first' and 'last' can't be negative".to_string(),
+            ))
+        } else if size > self.max_page_size {
+            Err(PaginationError::InvalidArguments(format!(
+                "'first' and 'last' can't be larger than {}",
+                self.max_page_size
+            )))
+        } else {
+            Ok((backward, size))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PaginationError {
+    InvalidCursor,
+    InvalidArguments(String),
+    Query(diesel::result::Error),
+}
+
+impl fmt::Display for PaginationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaginationError::InvalidCursor => write!(f, "invalid cursor"),
+            PaginationError::InvalidArguments(message) => write!(f, "{}", message),
+            PaginationError::Query(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for PaginationError {}
+
+impl From<diesel::result::Error> for PaginationError {
+    fn from(err: diesel::result::Error) -> Self {
+        PaginationError::Query(err)
+    }
+}
+
+pub fn encode_cursor<K: fmt::Display>(key: K) -> String {
+    base64::encode(format!("{}{}", CURSOR_PREFIX, key))
+}
+
+pub fn decode_cursor<K: FromStr>(cursor: &str) -> Result<K, PaginationError> {
+    let decoded = base64::decode(cursor).map_err(|_| PaginationError::InvalidCursor)?;
+    let decoded = String::from_utf8(decoded).map_err(|_| PaginationError::InvalidCursor)?;
+    if !decoded.starts_with(CURSOR_PREFIX) {
+        return Err(PaginationError::InvalidCursor);
+    }
+    decoded[CURSOR_PREFIX.len()..]
+        .parse()
+        .map_err(|_| PaginationError::InvalidCursor)
+}
+
+/// Whether the connection field being resolved asks for `totalCount`, which takes an extra
+/// query.
+pub fn wants_total_count<Context>(executor: &Executor<Context>) -> bool {
+    executor.look_ahead().has_child("totalCount")
+}
+
+/// Load a page of the rows of `query`, ordered by `key_column`, which must be unique.
+///
+/// `query` is called for every statement this runs. `key` returns the value of `key_column` of
+/// a row, which the cursors encode.
+///
+/// `hasPreviousPage` is only known when paginating backwards and `hasNextPage` only when
+/// paginating forwards, which the Relay spec allows. Otherwise they're true if there's an
+/// `after` or `before` cursor respectively.
+pub fn load_page<'a, ST, QS, DB, Conn, Row, Col, K, Q, KeyFn>(
+    query: Q,
+    key_column: Col,
+    key: KeyFn,
+    args: &PageArgs,
+    with_total_count: bool,
+    conn: &Conn,
+) -> Result<Connection<Row>, PaginationError>
+where
+    Q: Fn() -> Page<'a, ST, QS, DB>,
+    KeyFn: Fn(&Row) -> K,
+    DB: Backend,
+    Conn: DieselConnection<Backend = DB>,
+    Col: Expression + ExpressionMethods + Copy,
+    K: fmt::Display + FromStr + AsExpression<Col::SqlType>,
+    Page<'a, ST, QS, DB>: FilterDsl<dsl::Gt<Col, K>, Output = Page<'a, ST, QS, DB>>
+        + FilterDsl<dsl::Lt<Col, K>, Output = Page<'a, ST, QS, DB>>
+        + OrderDsl<dsl::Asc<Col>, Output = Page<'a, ST, QS, DB>>
+        + OrderDsl<dsl::Desc<Col>, Output = Page<'a, ST, QS, DB>>
+        + LimitDsl<Output = Page<'a, ST, QS, DB>>
+        + SelectDsl<dsl::CountStar, Output = Page<'a, BigInt, QS, DB>>
+        + LoadQuery<Conn, Row>,
+    Page<'a, BigInt, QS, DB>: LoadQuery<Conn, i64>,
+{
+    let (backward, page_size) = args.page_size()?;
+    let after = args
+        .after
+        .as_ref()
+        .map(|cursor| decode_cursor::<K>(cursor))
+        .transpose()?;
+    let before = args
+        .before
+        .as_ref()
+        .map(|cursor| decode_cursor::<K>(cursor))
+        .transpose()?;
+    let (has_after, has_before) = (after.is_some(), before.is_some());
+
+    let mut page = query();
+    if let Some(after) = after {
+        page = FilterDsl::filter(page, key_column.gt(after));
+    }
+    if let Some(before) = before {
+        page = FilterDsl::filter(page, key_column.lt(before));
+    }
+    page = if backward {
+        OrderDsl::order(page, key_column.desc())
+    } else {
+        OrderDsl::order(page, key_column.asc())
+    };
+    // One extra row tells whether there's another page.
+    let mut rows = LimitDsl::limit(page, i64::from(page_size) + 1).load::<Row>(conn)?;
+    let has_more = rows.len() > page_size as usize;
+    rows.truncate(page_size as usize);
+    if backward {
+        rows.reverse();
+    }
+
+    let edges = rows
+        .into_iter()
+        .map(|row| Edge {
+            cursor: encode_cursor(key(&row)),
+            node: row,
+        })
+        .collect::<Vec<_>>();
+
+    let total_count = if with_total_count {
+        let count = query().count().get_result::<i64>(conn)?;
+        Some(count.min(i64::from(i32::MAX)) as i32)
+    } else {
+        None
+    };
+
+    Ok(Connection {
+        page_info: PageInfo {
+            has_next_page: if backward { has_before } else { has_more },
+            has_previous_page: if backward { has_more } else { has_after },
+            start_cursor: edges.first().map(|edge| edge.cursor.clone()),
+            end_cursor: edges.last().map(|edge| edge.cursor.clone()),
+        },
+        edges,
+        total_count,
+    })
+}
+
+/// The global ID of the object of type `type_name` with primary key `key`.
+pub fn global_id<K: fmt::Display>(type_name: &str, key: K) -> ID {
+    ID::from(base64::encode(format!("{}:{}", type_name, key)))
+}
+
+/// The type name and primary key of a global ID.
+pub fn decode_global_id(id: &ID) -> Option<(String, String)> {
+    let decoded = String::from_utf8(base64::decode(&**id).ok()?).ok()?;
+    let separator = decoded.find(':')?;
+    Some((
+        decoded[..separator].to_string(),
+        decoded[separator + 1..].to_string(),
+    ))
+}
+
+type NodeLoader<N, Context> = Box<dyn Fn(&Context, &str) -> FieldResult<Option<N>> + Send + Sync>;
+
+/// Loads objects by their global IDs. `N` is the type of the `Node` interface. See the module
+/// docs.
+pub struct NodeRegistry<N, Context> {
+    loaders: HashMap<String, NodeLoader<N, Context>>,
+}
+
+impl<N, Context> NodeRegistry<N, Context> {
+    pub fn new() -> Self {
+        NodeRegistry {
+            loaders: HashMap::new(),
+        }
+    }
+
+    /// Load objects of type `type_name` with `loader`, which gets the primary key of the global
+    /// ID and returns `None` if the object doesn't exist.
+    pub fn register<K, F>(mut self, type_name: &str, loader: F) -> Self
+    where
+        K: FromStr,
+        F: Fn(&Context, K) -> FieldResult<Option<N>> + Send + Sync + 'static,
+    {
+        let type_name_for_error = type_name.to_string();
+        self.loaders.insert(
+            type_name.to_string(),
+            Box::new(move |context, key| {
+                let key = key.parse().map_err(|_| {
+                    format!("Invalid ID: bad primary key for {}", type_name_for_error)
+                })?;
+                loader(context, key)
+            }),
+        );
+        self
+    }
+
+    /// The object with the global ID `id`. Fails if the ID is malformed or its type isn't
+    /// registered.
+    pub fn node(&self, context: &Context, id: &ID) -> FieldResult<Option<N>> {
+        let (type_name, key) = decode_global_id(id).ok_or("Invalid ID")?;
+        let loader = self
+            .loaders
+            .get(&type_name)
+            .ok_or_else(|| format!("Invalid ID: unknown type {}", type_name))?;
+        loader(context, &key)
+    }
+
+    /// The objects with the global IDs `ids`, in the same order.
+    pub fn nodes(&self, context: &Context, ids: &[ID]) -> FieldResult<Vec<Option<N>>> {
+        ids.iter().map(|id| self.node(context, id)).collect()
+    }
+}
+
+impl<N, Context> Default for NodeRegistry<N, Context> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> GraphQLType for Connection<T>
+where
+    T: GraphQLType<TypeInfo = ()> + ConnectionNode,
+    T::Context: juniper::Context,
+{
+    type Context = T::Context;
+    type TypeInfo = ();
+
+    fn name(_: &()) -> Option<&str> {
+        Some(T::CONNECTION_NAME)
+    }
+
+    fn meta<'r>(info: &(), registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        let fields = &[
+            registry.field::<Vec<Edge<T>>>("edges", info),
+            registry.field::<Vec<T>>("nodes", info),
+            registry.field::<PageInfo>("pageInfo", info),
+            registry.field::<Option<i32>>("totalCount", info),
+        ];
+        registry.build_object_type::<Self>(info, fields).into_meta()
+    }
+
+    fn resolve_field(
+        &self,
+        info: &(),
+        field_name: &str,
+        _: &Arguments,
+        executor: &Executor<Self::Context>,
+    ) -> ExecutionResult {
+        match field_name {
+            "edges" => executor.resolve_with_ctx(info, &self.edges),
+            "nodes" => {
+                let nodes = self.edges.iter().map(|edge| &edge.node).collect::<Vec<_>>();
+                executor.resolve_with_ctx(info, &nodes)
+            }
+            "pageInfo" => executor.resolve_with_ctx(info, &self.page_info),
+            "totalCount" => executor.resolve_with_ctx(info, &self.total_count),
+            _ => Err(format!(
+                "Field {} not found on type {}",
+                field_name,
+                T::CONNECTION_NAME
+            )
+            .into()),
+        }
+    }
+}
+
+impl<T> GraphQLType for Edge<T>
+where
+    T: GraphQLType<TypeInfo = ()> + ConnectionNode,
+    T::Context: juniper::Context,
+{
+    type Context = T::Context;
+    type TypeInfo = ();
+
+    fn name(_: &()) -> Option<&str> {
+        Some(T::EDGE_NAME)
+    }
+
+    fn meta<'r>(info: &(), registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        let fields = &[
+            registry.field::<T>("node", info),
+            registry.field::<String>("cursor", info),
+        ];
+        registry.build_object_type::<Self>(info, fields).into_meta()
+    }
+
+    fn resolve_field(
+        &self,
+        info: &(),
+        field_name: &str,
+        _: &Arguments,
+        executor: &Executor<Self::Context>,
+    ) -> ExecutionResult {
+        match field_name {
+            "node" => executor.resolve_with_ctx(info, &self.node),
+            "cursor" => executor.resolve_with_ctx(info, &self.cursor),
+            _ => Err(format!("Field {} not found on type {}", field_name, T::EDGE_NAME).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_connection::{FakeConnection, FakeRow};
+
+    #[test]
+    fn cursors_round_trip() {
+        assert_eq!(decode_cursor::<i32>(&encode_cursor(42)).unwrap(), 42);
+        assert_eq!(decode_cursor::<i64>(&encode_cursor(-7i64)).unwrap(), -7);
+        assert_eq!(
+            decode_cursor::<String>(&encode_cursor("a:b")).unwrap(),
+            "a:b"
+        );
+    }
+
+    #[test]
+    fn cursors_are_opaque() {
+        let cursor = encode_cursor(42);
+
+        assert!(!cursor.contains("42"), "{}", cursor);
+    }
+
+    #[test]
+    fn malformed_cursors_are_rejected() {
+        let invalid = |cursor: &str| {
+            matches!(
+                decode_cursor::<i32>(cursor),
+                Err(PaginationError::InvalidCursor)
+            )
+        };
+
+        assert!(invalid("not base64!"));
+        assert!(invalid(&base64::encode("42")));
+        assert!(invalid(&base64::encode("cursor:abc")));
+        assert!(invalid(&base64::encode(&[0xff, 0xfe][..])));
+        assert!(invalid(&encode_cursor("1.5")));
+    }
+
+    #[test]
+    fn a_cursor_of_one_key_type_doesnt_decode_as_another() {
+        let cursor = encode_cursor("user-1");
+
+        assert!(decode_cursor::<i32>(&cursor).is_err());
+    }
+
+    #[test]
+    fn global_ids_round_trip() {
+        assert_eq!(
+            decode_global_id(&global_id("User", 42)),
+            Some(("User".to_string(), "42".to_string()))
+        );
+        assert_eq!(
+            decode_global_id(&global_id("Post", "a:b")),
+            Some(("Post".to_string(), "a:b".to_string()))
+        );
+    }
+
+    #[test]
+    fn global_ids_differ_by_type() {
+        assert_ne!(global_id("User", 1), global_id("Post", 1));
+    }
+
+    #[test]
+    fn malformed_global_ids_are_rejected() {
+        assert_eq!(decode_global_id(&ID::from("not base64!".to_string())), None);
+        assert_eq!(decode_global_id(&ID::from(base64::encode("User"))), None);
+        assert_eq!(
+            decode_global_id(&ID::from(base64::encode(&[0xff, 0x3a][..]))),
+            None
+        );
+    }
+
+    fn registry() -> NodeRegistry<String, ()> {
+        NodeRegistry::new()
+            .register("User", |_: &(), id: i32| {
+                Ok(if id > 0 {
+                    Some(format!("user {}", id))
+                } else {
+                    None
+                })
+            })
+            .register("Tag", |_: &(), name: String| {
+                Ok(Some(format!("tag {}", name)))
+            })
+    }
+
+    #[test]
+    fn nodes_are_loaded_by_their_global_ids() {
+        let nodes = registry()
+            .nodes(
+                &(),
+                &[
+                    global_id("User", 1),
+                    global_id("Tag", "rust"),
+                    global_id("User", 0),
+                ],
+            )
+            .unwrap();
+
+        assert_eq!(
+            nodes,
+            vec![
+                Some("user 1".to_string()),
+                Some("tag rust".to_string()),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn global_ids_of_unknown_types_or_with_bad_keys_fail() {
+        let registry = registry();
+        let message = |id: ID| registry.node(&(), &id).unwrap_err().message().to_string();
+
+        assert_eq!(
+            message(global_id("Post", 1)),
+            "Invalid ID: unknown type Post"
+        );
+        assert_eq!(
+            message(global_id("User", "abc")),
+            "Invalid ID: bad primary key for User"
+        );
+        assert_eq!(message(ID::from("???".to_string())), "Invalid ID");
+    }
+
+    table! {
+        users (id) {
+            id -> Integer,
+            name -> Text,
+        }
+    }
+
+    type User = (i32, String);
+
+    fn user(id: i32) -> FakeRow {
+        vec![
+            Some(id.to_be_bytes().to_vec()),
+            Some(format!("user {}", id).into_bytes()),
+        ]
+    }
+
+    fn page(
+        args: PageArgs,
+        with_total_count: bool,
+        connection: &FakeConnection,
+    ) -> Result<Connection<User>, PaginationError> {
+        load_page(
+            || users::table.into_boxed(),
+            users::id,
+            |user: &User| user.0,
+            &args,
+            with_total_count,
+            connection,
+        )
+    }
+
+    fn ids(connection: &Connection<User>) -> Vec<i32> {
+        connection.edges.iter().map(|edge| edge.node.0).collect()
+    }
+
+    const SELECT: &str = r#"SELECT "users"."id", "users"."name" FROM "users""#;
+    const ID: &str = r#""users"."id""#;
+
+    #[test]
+    fn first_and_after_take_the_rows_following_the_cursor() {
+        let connection = FakeConnection::default();
+        connection.respond_with(vec![user(2), user(3), user(4)]);
+
+        let page = page(
+            PageArgs::new(Some(2), Some(encode_cursor(1)), None, None),
+            false,
+            &connection,
+        )
+        .unwrap();
+
+        assert_eq!(
+            *connection.executed.borrow(),
+            vec![format!(
+                "{} WHERE {} > $1 ORDER BY {} ASC LIMIT $2 -- binds: [1, 3]",
+                SELECT, ID, ID
+            )]
+        );
+        assert_eq!(ids(&page), vec![2, 3]);
+        assert_eq!(page.edges[0].node.1, "user 2");
+        assert_eq!(
+            page.page_info,
+            PageInfo {
+                has_next_page: true,
+                has_previous_page: true,
+                start_cursor: Some(encode_cursor(2)),
+                end_cursor: Some(encode_cursor(3)),
+            }
+        );
+        assert_eq!(page.total_count, None);
+    }
+
+    #[test]
+    fn last_and_before_take_the_rows_preceding_the_cursor_in_order() {
+        let connection = FakeConnection::default();
+        connection.respond_with(vec![user(4), user(3), user(2)]);
+
+        let page = page(
+            PageArgs::new(None, None, Some(2), Some(encode_cursor(5))),
+            false,
+            &connection,
+        )
+        .unwrap();
+
+        assert_eq!(
+            *connection.executed.borrow(),
+            vec![format!(
+                "{} WHERE {} < $1 ORDER BY {} DESC LIMIT $2 -- binds: [5, 3]",
+                SELECT, ID, ID
+            )]
+        );
+        assert_eq!(ids(&page), vec![3, 4]);
+        assert_eq!(
+            page.page_info,
+            PageInfo {
+                has_next_page: true,
+                has_previous_page: true,
+                start_cursor: Some(encode_cursor(3)),
+                end_cursor: Some(encode_cursor(4)),
+            }
+        );
+    }
+
+    #[test]
+    fn pages_at_the_ends_have_no_next_or_previous_page() {
+        let connection = FakeConnection::default();
+        connection.respond_with(vec![user(1), user(2)]);
+        let first = page(PageArgs::new(Some(2), None, None, None), false, &connection).unwrap();
+
+        assert_eq!(ids(&first), vec![1, 2]);
+        assert!(!first.page_info.has_next_page);
+        assert!(!first.page_info.has_previous_page);
+
+        connection.respond_with(vec![user(9), user(8)]);
+        let last = page(PageArgs::new(None, None, Some(2), None), false, &connection).unwrap();
+
+        assert_eq!(ids(&last), vec![8, 9]);
+        assert!(!last.page_info.has_next_page);
+        assert!(!last.page_info.has_previous_page);
+
+        let empty = page(PageArgs::new(None, None, None, None), false, &connection).unwrap();
+
+        assert!(empty.edges.is_empty());
+        assert_eq!(empty.page_info.start_cursor, None);
+        assert_eq!(empty.page_info.end_cursor, None);
+        assert!(connection.executed.borrow()[2].ends_with("LIMIT $1 -- binds: [101]"));
+    }
+
+    #[test]
+    fn the_total_count_is_only_queried_when_asked_for() {
+        let connection = FakeConnection::default();
+        connection.respond_with(vec![user(1)]);
+        connection.respond_with(vec![vec![Some(7i64.to_be_bytes().to_vec())]]);
+
+        let page = page(PageArgs::new(Some(1), None, None, None), true, &connection).unwrap();
+
+        assert_eq!(page.total_count, Some(7));
+        assert_eq!(connection.executed.borrow().len(), 2);
+        assert_eq!(
+            connection.executed.borrow()[1],
+            r#"SELECT COUNT(*) FROM "users" -- binds: []"#
+        );
+    }
+
+    #[test]
+    fn invalid_arguments_run_no_queries() {
+        let connection = FakeConnection::default();
+        let error = |args: PageArgs| match page(args, true, &connection) {
+            Err(PaginationError::InvalidArguments(message)) => message,
+            Err(PaginationError::InvalidCursor) => "invalid cursor".to_string(),
+            _ => panic!("expected an error"),
+        };
+
+        assert_eq!(
+            error(PageArgs::new(Some(1), None, Some(1), None)),
+            "'first' and 'last' can't be used together"
+        );
+        assert_eq!(
+            error(PageArgs::new(None, None, Some(-1), None)),
+            "'first' and 'last' can't be negative"
+        );
+        assert_eq!(
+            error(PageArgs::new(Some(11), None, None, None).max_page_size(10)),
+            "'first' and 'last' can't be larger than 10"
+        );
+        assert_eq!(
+            error(PageArgs::new(Some(1), Some("???".to_string()), None, None)),
+            "invalid cursor"
+        );
+        assert!(connection.executed.borrow().is_empty());
+    }
+}
@@ -0,0 +1,588 @@
+use crate::access_log;
+use crate::allowlist::Allowlist;
+use crate::analysis::{self, QueryLimits};
+use crate::apollo_tracing::{ApolloTracing, Trace};
+use crate::authorization::{self, AuthorizationRules, Authorizer};
+use crate::errors;
+use crate::instrumentation::{self, ResolverTiming};
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::panics;
+use crate::persisted_queries::{self, PersistedQuery, PersistedQueryStore};
+use crate::request_id::RequestId;
+use crate::slow_log;
+use crate::sql_counting;
+use crate::telemetry::{self, PoolCheckouts};
+use crate::validation;
+use graphql_parser::query::Document;
+use juniper::{GraphQLType, InputValue, RootNode, Variables};
+use serde::Deserialize;
+use serde_json::json;
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+use tracing::Span;
+use url::form_urlencoded;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum GraphqlBatchRequest {
+    Single(GraphqlRequest),
+    Batch(Vec<GraphqlRequest>),
+}
+
+#[derive(Deserialize)]
+pub(crate) struct GraphqlRequest {
+    query: Option<String>,
+    #[serde(rename = "operationName")]
+    operation_name: Option<String>,
+    variables: Option<InputValue>,
+    extensions: Option<Extensions>,
+}
+
+#[derive(Deserialize)]
+struct Extensions {
+    #[serde(rename = "persistedQuery")]
+    persisted_query: Option<PersistedQuery>,
+}
+
+/// Everything that affects how a request is executed, shared by all requests.
+pub(crate) struct ExecutionConfig {
+    pub(crate) query_limits: QueryLimits,
+    pub(crate) persisted_query_store: Box<dyn PersistedQueryStore>,
+    pub(crate) allowlist: Option<Allowlist>,
+    pub(crate) enable_introspection: bool,
+    pub(crate) expose_internal_errors: bool,
+    pub(crate) authorization_rules: AuthorizationRules,
+    pub(crate) apollo_tracing: ApolloTracing,
+    /// Lowercased names of variables to redact in logs.
+    pub(crate) redacted_variables: Vec<String>,
+    pub(crate) slow_query_threshold: Option<Duration>,
+    pub(crate) count_sql_statements: bool,
+    pub(crate) n_plus_one_threshold: usize,
+}
+
+/// What the adapters know about a request that matters for executing it.
+pub(crate) struct RequestInfo {
+    pub(crate) id: RequestId,
+    /// The request has an `X-Apollo-Tracing` header.
+    pub(crate) wants_apollo_tracing: bool,
+    /// Includes the checkouts made while creating the context.
+    pub(crate) pool_checkouts: PoolCheckouts,
+}
+
+impl ExecutionConfig {
+    /// Check the parts of the config that depend on the schema. Called once at startup.
+    pub(crate) fn validate<Query, Mutation>(&self, root_node: &RootNode<Query, Mutation>)
+    where
+        Query: GraphQLType<TypeInfo = ()>,
+        Mutation: GraphQLType<TypeInfo = ()>,
+    {
+        if let Some(allowlist) = &self.allowlist {
+            allowlist.validate(root_node);
+        }
+        self.authorization_rules.validate(root_node);
+    }
+}
+
+pub(crate) struct GraphqlResponse {
+    pub(crate) is_ok: bool,
+    /// A resolver panicked, so the response should have status 500.
+    pub(crate) panicked: bool,
+    pub(crate) body: String,
+    pub(crate) operations: Vec<OperationSummary>,
+}
+
+/// What happened to an operation, for logging.
+pub(crate) struct OperationSummary {
+    pub(crate) operation_name: Option<String>,
+    pub(crate) operation_type: Option<&'static str>,
+    /// `None` if the query text couldn't be found.
+    pub(crate) query_hash: Option<String>,
+    /// With sensitive values redacted.
+    pub(crate) variables: serde_json::Value,
+    pub(crate) duration: Duration,
+    pub(crate) error_count: usize,
+}
+
+/// The result of executing one operation.
+struct ExecutedOperation {
+    is_ok: bool,
+    panicked: bool,
+    body: serde_json::Value,
+    summary: OperationSummary,
+}
+
+impl GraphqlBatchRequest {
+    /// Parse the `query`, `operationName`, `variables` and `extensions` parameters of a GET
+    /// request.
+    pub(crate) fn from_query_string(query_string: &str) -> Result<Self, String> {
+        let mut query = None;
+        let mut operation_name = None;
+        let mut variables = None;
+        let mut extensions = None;
+
+        for (key, value) in form_urlencoded::parse(query_string.as_bytes()) {
+            let (param, is_duplicate) = match &*key {
+                "query" => ("query", query.replace(value.into_owned()).is_some()),
+                "operationName" => (
+                    "operationName",
+                    operation_name.replace(value.into_owned()).is_some(),
+                ),
+                "variables" => {
+                    let parsed = serde_json::from_str::<InputValue>(&value)
+                        .map_err(|err| format!("invalid 'variables' parameter: {}", err))?;
+                    ("variables", variables.replace(parsed).is_some())
+                }
+                "extensions" => {
+                    let parsed = serde_json::from_str::<Extensions>(&value)
+                        .map_err(|err| format!("invalid 'extensions' parameter: {}", err))?;
+                    ("extensions", extensions.replace(parsed).is_some())
+                }
+                _ => continue,
+            };
+
+            if is_duplicate {
+                return Err(format!("'{}' parameter is specified multiple times", param));
+            }
+        }
+
+        if query.is_none() && extensions.is_none() {
+            return Err("'query' parameter is missing".to_string());
+        }
+
+        Ok(GraphqlBatchRequest::Single(GraphqlRequest {
+            query,
+            operation_name,
+            variables,
+            extensions,
+        }))
+    }
+
+    /// Execute the request inside the current request span. The operations of a batch each get
+    /// their own span.
+    pub(crate) fn execute<Query, Mutation, Context>(
+        &self,
+        root_node: &RootNode<Query, Mutation>,
+        context: &Context,
+        request_info: &RequestInfo,
+        config: &ExecutionConfig,
+    ) -> GraphqlResponse
+    where
+        Query: GraphQLType<TypeInfo = (), Context = Context>,
+        Mutation: GraphQLType<TypeInfo = (), Context = Context>,
+        Context: Authorizer,
+    {
+        request_info
+            .pool_checkouts
+            .in_scope(|| self.execute_in_scope(root_node, context, request_info, config))
+    }
+
+    fn execute_in_scope<Query, Mutation, Context>(
+        &self,
+        root_node: &RootNode<Query, Mutation>,
+        context: &Context,
+        request_info: &RequestInfo,
+        config: &ExecutionConfig,
+    ) -> GraphqlResponse
+    where
+        Query: GraphQLType<TypeInfo = (), Context = Context>,
+        Mutation: GraphQLType<TypeInfo = (), Context = Context>,
+        Context: Authorizer,
+    {
+        match self {
+            GraphqlBatchRequest::Single(request) => {
+                let executed =
+                    request.execute_catching_panics(root_node, context, request_info, config);
+                GraphqlResponse {
+                    is_ok: executed.is_ok,
+                    panicked: executed.panicked,
+                    body: executed.body.to_string(),
+                    operations: vec![executed.summary],
+                }
+            }
+            GraphqlBatchRequest::Batch(requests) => {
+                let results = requests
+                    .iter()
+                    .map(|request| {
+                        telemetry::operation_span().in_scope(|| {
+                            request.execute_catching_panics(
+                                root_node,
+                                context,
+                                request_info,
+                                config,
+                            )
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let is_ok = results.iter().all(|executed| executed.is_ok);
+                let panicked = results.iter().any(|executed| executed.panicked);
+                let error_count = results
+                    .iter()
+                    .map(|executed| executed.summary.error_count)
+                    .sum::<usize>();
+                Span::current().record("error_count", error_count as u64);
+                let (bodies, operations) = results
+                    .into_iter()
+                    .map(|executed| (executed.body, executed.summary))
+                    .unzip();
+                GraphqlResponse {
+                    is_ok,
+                    panicked,
+                    body: serde_json::Value::Array(bodies).to_string(),
+                    operations,
+                }
+            }
+        }
+    }
+}
+
+impl GraphqlRequest {
+    fn variables(&self) -> Variables {
+        self.variables
+            .as_ref()
+            .and_then(|variables| variables.to_object_value())
+            .map(|object| {
+                object
+                    .into_iter()
+                    .map(|(key, value)| (key.to_owned(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn operation_name(&self) -> Option<&str> {
+        self.operation_name.as_deref()
+    }
+
+    /// Like `execute`, but a panic becomes an `Internal server error` response. Records the
+    /// operation on the current span, logs it if it was slow and adds the request ID and the
+    /// Apollo trace to the response's `extensions`.
+    fn execute_catching_panics<Query, Mutation, Context>(
+        &self,
+        root_node: &RootNode<Query, Mutation>,
+        context: &Context,
+        request_info: &RequestInfo,
+        config: &ExecutionConfig,
+    ) -> ExecutedOperation
+    where
+        Query: GraphQLType<TypeInfo = (), Context = Context>,
+        Mutation: GraphQLType<TypeInfo = (), Context = Context>,
+        Context: Authorizer,
+    {
+        let request_id = &request_info.id;
+        let mut trace = if config
+            .apollo_tracing
+            .is_enabled(request_info.wants_apollo_tracing)
+        {
+            Some(Trace::start())
+        } else {
+            None
+        };
+        let started = Instant::now();
+
+        let span = Span::current();
+        if let Some(operation_name) = self.operation_name() {
+            span.record("operation_name", operation_name);
+        }
+
+        let query = self.resolve_query(config);
+        let query_hash = query
+            .as_ref()
+            .ok()
+            .map(|query| persisted_queries::query_hash(query));
+        let document = query
+            .as_ref()
+            .ok()
+            .and_then(|query| graphql_parser::parse_query(query).ok());
+        let operation_type = document
+            .as_ref()
+            .and_then(|document| validation::operation_type(document, self.operation_name()));
+        if let Some(operation_type) = operation_type {
+            span.record("operation_type", operation_type);
+        }
+        if let Some(trace) = &mut trace {
+            trace.parsing(started);
+        }
+
+        let mut resolvers = if trace.is_some() || config.slow_query_threshold.is_some() {
+            Some(Vec::new())
+        } else {
+            None
+        };
+
+        let (is_ok, panicked, mut body, statements) = match &query {
+            Ok(query) => {
+                let mut execute = || {
+                    panics::catch(|| {
+                        self.execute(
+                            query,
+                            document.as_ref(),
+                            root_node,
+                            context,
+                            request_id,
+                            trace.as_mut(),
+                            resolvers.as_mut(),
+                            config,
+                        )
+                    })
+                };
+                let (result, statements) = if config.count_sql_statements {
+                    let (result, statements) = sql_counting::count_statements(execute);
+                    (result, Some(statements))
+                } else {
+                    (execute(), None)
+                };
+
+                match result {
+                    Ok((is_ok, body)) => (is_ok, false, body, statements),
+                    Err(panic) => {
+                        log::error!(
+                            "Resolver panicked while executing operation {} (request id {}): '{}' at {}\n{:?}",
+                            self.operation_name().unwrap_or("<anonymous>"),
+                            request_id,
+                            panic.message,
+                            panic.location,
+                            panic.backtrace
+                        );
+                        (
+                            false,
+                            true,
+                            json!({ "errors": [{ "message": "Internal server error" }] }),
+                            statements,
+                        )
+                    }
+                }
+            }
+            Err((is_ok, body)) => (*is_ok, false, body.clone(), None),
+        };
+
+        let duration = started.elapsed();
+        // The first operation also gets the checkouts made before it, such as the context's.
+        let pool_checkouts = request_info.pool_checkouts.take();
+        let resolvers = resolvers.unwrap_or_default();
+        let error_count = error_count(&body);
+        span.record("error_count", error_count as u64);
+        #[cfg(feature = "metrics")]
+        metrics::observe_operation(
+            self.metrics_operation_name(document.as_ref(), config),
+            operation_type,
+            duration,
+            error_count,
+        );
+
+        let mut variables = json!(self.variables);
+        access_log::redact(&mut variables, &config.redacted_variables);
+        let summary = OperationSummary {
+            operation_name: self.operation_name.clone(),
+            operation_type,
+            query_hash,
+            variables,
+            duration,
+            error_count,
+        };
+
+        if config
+            .slow_query_threshold
+            .is_some_and(|threshold| duration > threshold)
+        {
+            slow_log::log(
+                request_id,
+                &summary,
+                document.as_ref(),
+                &resolvers,
+                pool_checkouts,
+            );
+        }
+
+        body["extensions"]["requestId"] = json!(request_id.as_str());
+        if let Some(statements) = statements {
+            body["extensions"]["sqlStatements"] = json!(statements.total());
+            for (sql, count) in statements.repeated(config.n_plus_one_threshold) {
+                log::warn!(
+                    target: "gimme_graphql::n_plus_one",
+                    "Possible N+1 query in operation {} (request id {}), this statement ran {} times: {}",
+                    self.operation_name().unwrap_or("<anonymous>"),
+                    request_id,
+                    count,
+                    sql
+                );
+            }
+        }
+        if let (Some(mut trace), false) = (trace, panicked) {
+            trace.resolvers(resolvers);
+            body["extensions"]["tracing"] = trace.to_json();
+        }
+
+        ExecutedOperation {
+            is_ok,
+            panicked,
+            body,
+            summary,
+        }
+    }
+
+    /// The operation name to label metrics with. Clients can send any name, and every new one
+    /// would be a new time series, so only the names of operations in allowlisted documents are
+    /// used. Anything else is `other`, including persisted queries, which any client can add.
+    #[cfg(feature = "metrics")]
+    fn metrics_operation_name<'a>(
+        &self,
+        document: Option<&'a Document>,
+        config: &ExecutionConfig,
+    ) -> &'a str {
+        let document = match document {
+            Some(document) => document,
+            None => return "other",
+        };
+
+        let is_known = config
+            .allowlist
+            .as_ref()
+            .is_some_and(|allowlist| allowlist.contains(document));
+        if !is_known {
+            return "other";
+        }
+        // The name can be left out of requests for documents with one operation.
+        validation::find_operation(document, self.operation_name()).map_or("other", |operation| {
+            analysis::operation_name(operation).unwrap_or("")
+        })
+    }
+
+    /// The query text, which is looked up in the allowlist and then the persisted query store if
+    /// the request only has its hash.
+    fn resolve_query(
+        &self,
+        config: &ExecutionConfig,
+    ) -> Result<Cow<'_, str>, (bool, serde_json::Value)> {
+        let persisted_query = self
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.persisted_query.as_ref());
+
+        if self.query.is_none() {
+            let listed = persisted_query
+                .and_then(|persisted_query| persisted_query.hash())
+                .and_then(|hash| config.allowlist.as_ref()?.get(&hash));
+            if let Some(query) = listed {
+                return Ok(Cow::Owned(query.to_string()));
+            }
+        }
+
+        persisted_queries::resolve_query(
+            self.query.as_deref(),
+            persisted_query,
+            &*config.persisted_query_store,
+        )
+        .map_err(|err| (!err.is_bad_request(), err.to_json()))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn execute<Query, Mutation, Context>(
+        &self,
+        query: &str,
+        document: Option<&Document>,
+        root_node: &RootNode<Query, Mutation>,
+        context: &Context,
+        request_id: &RequestId,
+        trace: Option<&mut Trace>,
+        resolvers: Option<&mut Vec<ResolverTiming>>,
+        config: &ExecutionConfig,
+    ) -> (bool, serde_json::Value)
+    where
+        Query: GraphQLType<TypeInfo = (), Context = Context>,
+        Mutation: GraphQLType<TypeInfo = (), Context = Context>,
+        Context: Authorizer,
+    {
+        let validation_started = Instant::now();
+
+        if let Some(allowlist) = &config.allowlist {
+            if let Err(message) = allowlist.check(query, self.operation_name()) {
+                return (false, json!({ "errors": [{ "message": message }] }));
+            }
+        }
+
+        if !config.enable_introspection {
+            let uses_introspection = document.is_some_and(validation::uses_introspection);
+            if uses_introspection {
+                return (
+                    false,
+                    json!({ "errors": [{ "message": "GraphQL introspection is not allowed" }] }),
+                );
+            }
+        }
+
+        let variables = self.variables();
+
+        if let Err(message) = analysis::check_limits(
+            root_node,
+            query,
+            self.operation_name(),
+            &variables,
+            &config.query_limits,
+        ) {
+            return (false, json!({ "errors": [{ "message": message }] }));
+        }
+
+        let restricted_query = match config
+            .authorization_rules
+            .restrict(root_node, query, context)
+        {
+            Ok(restricted_query) => restricted_query,
+            Err(rejection) => return (false, rejection.to_json()),
+        };
+
+        let execute = || {
+            juniper::execute(
+                restricted_query
+                    .as_ref()
+                    .map_or(query, |query| query.as_str()),
+                self.operation_name(),
+                root_node,
+                &variables,
+                context,
+            )
+        };
+        if let Some(trace) = trace {
+            trace.validation(validation_started);
+        }
+        let result = match resolvers {
+            Some(resolvers) => {
+                let (mut result, mut timings) = instrumentation::collect_resolver_timings(execute);
+                if let Ok((_, errors)) = &mut result {
+                    instrumentation::take_resolver_paths(errors, &mut timings);
+                }
+                *resolvers = timings;
+                result
+            }
+            None => execute(),
+        };
+
+        match result {
+            Ok((data, errors)) => {
+                let mut data = json!(data);
+                let mut errors = errors.iter().map(|error| json!(error)).collect::<Vec<_>>();
+                errors::mask_errors(
+                    &mut errors,
+                    config.expose_internal_errors,
+                    request_id.as_str(),
+                );
+                if restricted_query.is_some() {
+                    errors.extend(authorization::null_forbidden_fields(&mut data));
+                }
+
+                if errors.is_empty() {
+                    (true, json!({ "data": data }))
+                } else {
+                    (true, json!({ "data": data, "errors": errors }))
+                }
+            }
+            Err(err) => (false, json!({ "errors": err })),
+        }
+    }
+}
+
+fn error_count(body: &serde_json::Value) -> usize {
+    body.get("errors")
+        .and_then(|errors| errors.as_array())
+        .map_or(0, |errors| errors.len())
+}
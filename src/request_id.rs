@@ -0,0 +1,53 @@
+//! Request IDs for correlating the logs of a request.
+//!
+//! Every request gets an ID, taken from its `X-Request-Id` header if it has a sensible one and
+//! generated otherwise. The ID is sent back in the `X-Request-Id` response header and in the
+//! `requestId` field of the GraphQL response's `extensions`. It's also the correlation ID of
+//! masked errors, and recorded on the request's `tracing` span.
+//!
+//! It can be read when creating the context with `hyper_adapter::request_id` or
+//! `rocket_adapter::request_id`.
+
+use std::fmt;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// IDs sent by clients longer than this are replaced.
+const MAX_LENGTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Use the value of the `X-Request-Id` header, or generate a new ID if it's missing or
+    /// isn't safe to put in logs.
+    pub(crate) fn from_header(value: Option<&str>) -> Self {
+        match value {
+            Some(value) if is_valid(value) => RequestId(value.to_string()),
+            _ => RequestId::generate(),
+        }
+    }
+
+    pub(crate) fn generate() -> Self {
+        RequestId(Uuid::new_v4().to_hyphenated().to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn is_valid(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= MAX_LENGTH
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.:/+=".contains(c))
+}
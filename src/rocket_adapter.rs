@@ -1,16 +1,38 @@
-use crate::{Adapter, GraphqlApp, WebFrameworkConfig};
-use juniper::{GraphQLType, RootNode};
-use juniper_rocket::GraphQLRequest;
+use crate::access_log::{AccessLog, AccessLogEntry};
+use crate::apollo_tracing::APOLLO_TRACING_HEADER;
+#[cfg(feature = "auth")]
+use crate::auth::{AuthError, Claims, JwtAuth};
+use crate::authorization::Authorizer;
+use crate::cors::Cors;
+#[cfg(feature = "auth")]
+use crate::errors::{self, ErrorCode};
+use crate::ide::{self, Ide};
+use crate::instrumentation::{self, Schema};
+#[cfg(feature = "metrics")]
+use crate::metrics::{self, MetricsEndpoint};
+use crate::request::{
+    ExecutionConfig, GraphqlBatchRequest, GraphqlResponse, OperationSummary, RequestInfo,
+};
+use crate::request_id::{RequestId, REQUEST_ID_HEADER};
+use crate::telemetry::{PoolCheckouts, RequestSpan};
+use crate::upload::{self, UploadConfig, Uploads};
+use crate::{
+    enabled_ide, shared_routes, Adapter, GraphqlApp, Profile, RouteInfo, WebFrameworkConfig,
+};
+use juniper::GraphQLType;
 use rocket::config::{Config, Environment};
 use rocket::{
-    data::{FromData, Transform},
+    fairing::{Fairing, Info, Kind},
     handler::{self, Handler},
-    http::Method,
-    request::{FromFormValue, FromRequest, Request},
+    http::{ContentType, Method, Status},
+    request::{FromRequest, Request},
+    response::{status, Response},
     Data, Outcome, Route, State,
 };
-use std::borrow::Borrow;
+use std::io::{Cursor, Read};
 use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Instant;
 
 pub use rocket;
 
@@ -24,7 +46,7 @@ where
     Connection: 'static + diesel::Connection,
     Query: 'static + Send + Sync + Default + GraphQLType<TypeInfo = (), Context = Context>,
     Mutation: 'static + Send + Sync + Default + GraphQLType<TypeInfo = (), Context = Context>,
-    Context: 'static + juniper::Context + for<'ca, 'cr> FromRequest<'ca, 'cr>,
+    Context: 'static + juniper::Context + Authorizer + for<'ca, 'cr> FromRequest<'ca, 'cr>,
 {
     type Inner = rocket::Rocket;
 
@@ -43,27 +65,45 @@ where
         >,
     {
         let WebFrameworkConfig {
+            access_log,
+            cors,
             database_connection_pool,
             graphql_path,
+            ide,
+            #[cfg(feature = "auth")]
+            jwt_auth,
+            #[cfg(feature = "metrics")]
+            metrics,
             mount_graphiql_at,
             mount_graphql_at,
             port,
+            profile,
+            execution_config,
+            upload_config,
         } = config;
 
-        let env = Environment::active()
-            .expect("failed to get active rocket env. Is the env var ROCKET_ENV set correctly?");
+        let root_node = instrumentation::schema(Query::default(), Mutation::default());
+        execution_config.validate(&root_node);
+
+        let env = match profile {
+            Profile::Development => Environment::Development,
+            Profile::Staging => Environment::Staging,
+            Profile::Production => Environment::Production,
+        };
         let rocket_config = Config::build(env)
             .port(port)
             .finalize()
             .expect("failed to finalize rocket config");
 
+        #[cfg(feature = "metrics")]
+        let metrics_endpoint = Arc::new(MetricsEndpoint::new(database_connection_pool.clone()));
+
         let rocket = rocket::custom(rocket_config)
             .manage(database_connection_pool)
-            .manage(juniper::RootNode::new(
-                Query::default(),
-                Mutation::default(),
-            ))
-            .mount(mount_graphiql_at, GraphiqlHandler::new(&graphql_path))
+            .manage(execution_config)
+            .manage(upload_config)
+            .manage(root_node)
+            .attach(RequestIdFairing)
             .mount(
                 mount_graphql_at,
                 PostGraphqlHandler::<Query, Mutation, Context>::new(),
@@ -72,34 +112,286 @@ where
                 mount_graphql_at,
                 GetGraphqlHandler::<Query, Mutation, Context>::new(),
             );
+        let rocket = if ide.is_enabled() {
+            let assets_path = format!("{}/ide-assets", mount_graphiql_at.trim_end_matches('/'));
+            rocket
+                .mount(
+                    mount_graphiql_at,
+                    IdePageHandler {
+                        ide: Arc::new(ide),
+                        graphql_path,
+                        assets_path,
+                    },
+                )
+                .mount(mount_graphiql_at, IdeAssetHandler)
+        } else {
+            rocket
+        };
+        #[cfg(feature = "auth")]
+        let rocket = match jwt_auth {
+            Some(jwt_auth) => rocket.manage(jwt_auth),
+            None => rocket,
+        };
+        #[cfg(feature = "metrics")]
+        let rocket = match metrics.admin_port {
+            Some(admin_port) => {
+                metrics::serve_on_admin_port(metrics_endpoint, metrics.path, admin_port);
+                rocket
+            }
+            None => rocket.mount(
+                "/",
+                MetricsHandler {
+                    endpoint: metrics_endpoint,
+                    path: metrics.path,
+                },
+            ),
+        };
+        let rocket = match access_log {
+            Some(access_log) => rocket.attach(AccessLogFairing {
+                access_log,
+                graphql_path: format!("{}/graphql", mount_graphql_at.trim_end_matches('/')),
+            }),
+            None => rocket,
+        };
+        let rocket = match cors {
+            Some(cors) => rocket.attach(CorsFairing { cors }),
+            None => rocket,
+        };
         let rocket = app.configure_web_framework(rocket);
 
         let error = rocket.launch();
         panic!("Failed to launch rocket: {}", error);
     }
+
+    fn routes<App>(&self, app: &App) -> Vec<RouteInfo>
+    where
+        App: GraphqlApp<
+            Adapter = Self,
+            Connection = Connection,
+            Query = Query,
+            Mutation = Mutation,
+            Context = Context,
+        >,
+    {
+        let graphql_path = format!("{}/graphql", app.mount_graphql_at().trim_end_matches('/'));
+        let mut routes = vec![
+            RouteInfo::new("GET", &graphql_path, "GraphQL queries"),
+            RouteInfo::new(
+                "POST",
+                &graphql_path,
+                "GraphQL queries, mutations and uploads",
+            ),
+        ];
+        if enabled_ide(app).is_enabled() {
+            let mount_graphiql_at = app.mount_graphiql_at().trim_end_matches('/');
+            routes.push(RouteInfo::new(
+                "GET",
+                &format!("{}/graphiql", mount_graphiql_at),
+                "GraphQL IDE",
+            ));
+            routes.push(RouteInfo::new(
+                "GET",
+                &format!("{}/ide-assets/<name>", mount_graphiql_at),
+                "GraphQL IDE assets",
+            ));
+        }
+        routes.extend(shared_routes(app));
+        routes
+    }
+}
+
+/// The ID of the request, for use when implementing `FromRequest` for the context.
+pub fn request_id<'r>(req: &'r Request) -> &'r RequestId {
+    req.local_cache(|| RequestId::from_header(req.headers().get_one(REQUEST_ID_HEADER)))
+}
+
+/// The files uploaded with the request, for the context to keep. See [`upload`].
+pub fn uploads(req: &Request) -> Uploads {
+    req.local_cache(Uploads::default).clone()
+}
+
+/// Adds the `X-Request-Id` header to every response.
+struct RequestIdFairing;
+
+impl Fairing for RequestIdFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request ID",
+            kind: Kind::Response,
+        }
+    }
+
+    fn on_response(&self, req: &Request, response: &mut Response) {
+        response.set_raw_header(REQUEST_ID_HEADER, request_id(req).to_string());
+    }
+}
+
+/// When the request was received.
+struct RequestStarted(Instant);
+
+/// The operations of a GraphQL request, set by the handlers for the access log.
+struct Operations(Vec<OperationSummary>);
+
+/// Logs GraphQL requests to the access log.
+struct AccessLogFairing {
+    access_log: AccessLog,
+    graphql_path: String,
+}
+
+impl Fairing for AccessLogFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Access log",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, req: &mut Request, _: &Data) {
+        req.local_cache(|| RequestStarted(Instant::now()));
+    }
+
+    fn on_response(&self, req: &Request, response: &mut Response) {
+        if req.uri().path() != self.graphql_path {
+            return;
+        }
+
+        let started = req.local_cache(|| RequestStarted(Instant::now())).0;
+        let operations = req.local_cache(|| Operations(Vec::new()));
+        self.access_log.log(&AccessLogEntry {
+            method: req.method().as_str(),
+            path: req.uri().path(),
+            status: response.status().code,
+            duration: started.elapsed(),
+            client_ip: self.access_log.client_ip(
+                req.remote().map(|addr| addr.ip()),
+                req.headers().get_one("X-Forwarded-For"),
+            ),
+            request_id: request_id(req),
+            operations: &operations.0,
+        });
+    }
+}
+
+#[cfg(feature = "metrics")]
+#[derive(Clone)]
+struct MetricsHandler {
+    endpoint: Arc<MetricsEndpoint>,
+    path: &'static str,
+}
+
+#[cfg(feature = "metrics")]
+impl Handler for MetricsHandler {
+    fn handle<'r>(&self, _: &'r Request, _: Data) -> handler::Outcome<'r> {
+        let response = Response::build()
+            .raw_header("Content-Type", self.endpoint.content_type())
+            .sized_body(Cursor::new(self.endpoint.render()))
+            .finalize();
+        Outcome::Success(response)
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl From<MetricsHandler> for Vec<Route> {
+    fn from(handler: MetricsHandler) -> Self {
+        let path = handler.path;
+        vec![Route::new(Method::Get, path, handler)]
+    }
+}
+
+/// Adds CORS headers to every response and answers preflight requests, which otherwise
+/// wouldn't match any route.
+struct CorsFairing {
+    cors: Cors,
+}
+
+impl Fairing for CorsFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS",
+            kind: Kind::Response,
+        }
+    }
+
+    fn on_response(&self, req: &Request, response: &mut Response) {
+        let origin = req.headers().get_one("Origin");
+        let is_preflight = req.method() == Method::Options
+            && req.headers().contains("Access-Control-Request-Method");
+
+        let headers = if is_preflight {
+            response.set_status(Status::NoContent);
+            response.take_body();
+            self.cors.preflight_headers(
+                origin,
+                req.headers().get_one("Access-Control-Request-Method"),
+                req.headers().get_one("Access-Control-Request-Headers"),
+            )
+        } else {
+            self.cors.response_headers(origin)
+        };
+
+        for (name, value) in headers {
+            response.adjoin_raw_header(name, value);
+        }
+    }
 }
 
 #[derive(Clone)]
-struct GraphiqlHandler {
+struct IdePageHandler {
+    ide: Arc<Ide>,
     graphql_path: &'static str,
+    assets_path: String,
 }
 
-impl GraphiqlHandler {
-    fn new(graphql_path: &'static str) -> Self {
-        Self { graphql_path }
+impl Handler for IdePageHandler {
+    fn handle<'r>(&self, _: &'r Request, _: Data) -> handler::Outcome<'r> {
+        let page = match self.ide.page(self.graphql_path, &self.assets_path) {
+            Some(page) => page,
+            None => return Outcome::Failure(Status::NotFound),
+        };
+
+        let response = Response::build()
+            .header(ContentType::HTML)
+            .raw_header("Cache-Control", ide::PAGE_CACHE_CONTROL)
+            .raw_header("Content-Security-Policy", page.content_security_policy)
+            .sized_body(Cursor::new(page.html))
+            .finalize();
+        Outcome::Success(response)
     }
 }
 
-impl Handler for GraphiqlHandler {
-    fn handle<'r>(&self, req: &'r Request, _: Data) -> handler::Outcome<'r> {
-        let src = juniper_rocket::graphiql_source(self.graphql_path);
-        Outcome::from(req, src)
+impl From<IdePageHandler> for Vec<Route> {
+    fn from(handler: IdePageHandler) -> Self {
+        vec![Route::new(Method::Get, "/graphiql", handler)]
     }
 }
 
-impl Into<Vec<Route>> for GraphiqlHandler {
-    fn into(self) -> Vec<Route> {
-        vec![Route::new(Method::Get, "/graphiql", self)]
+#[derive(Clone)]
+struct IdeAssetHandler;
+
+impl Handler for IdeAssetHandler {
+    fn handle<'r>(&self, req: &'r Request, data: Data) -> handler::Outcome<'r> {
+        let name = match req.get_param::<String>(0) {
+            Some(Ok(name)) => name,
+            _ => return Outcome::Forward(data),
+        };
+
+        let (content_type, contents) = match ide::asset(&name) {
+            Some(asset) => asset,
+            None => return Outcome::Failure(Status::NotFound),
+        };
+
+        let response = Response::build()
+            .raw_header("Content-Type", content_type)
+            .raw_header("Cache-Control", ide::ASSET_CACHE_CONTROL)
+            .sized_body(Cursor::new(contents))
+            .finalize();
+        Outcome::Success(response)
+    }
+}
+
+impl From<IdeAssetHandler> for Vec<Route> {
+    fn from(handler: IdeAssetHandler) -> Self {
+        vec![Route::new(Method::Get, "/ide-assets/<name>", handler)]
     }
 }
 
@@ -132,52 +424,103 @@ impl<Query, Mutation, Context> Handler for PostGraphqlHandler<Query, Mutation, C
 where
     Query: 'static + Send + Sync + Default + GraphQLType<TypeInfo = (), Context = Context>,
     Mutation: 'static + Send + Sync + Default + GraphQLType<TypeInfo = (), Context = Context>,
-    Context: 'static + juniper::Context + for<'ca, 'cr> FromRequest<'ca, 'cr>,
+    Context: 'static + juniper::Context + Authorizer + for<'ca, 'cr> FromRequest<'ca, 'cr>,
 {
     fn handle<'r>(&self, req: &'r Request, data: Data) -> handler::Outcome<'r> {
-        let context = match Context::from_request(req) {
+        let request_id = request_id(req);
+        let request_span = RequestSpan::new(request_id);
+        let _enter = request_span.span().enter();
+
+        #[cfg(feature = "auth")]
+        {
+            if let Err(err) = authentication(req) {
+                return unauthorized(err);
+            }
+        }
+
+        let pool_checkouts = PoolCheckouts::default();
+        let context = match pool_checkouts.in_scope(|| Context::from_request(req)) {
             Outcome::Success(s) => s,
             Outcome::Forward(_) => return Outcome::Forward(data),
             Outcome::Failure((f, _)) => return Outcome::Failure(f),
         };
 
-        let schema = match State::<RootNode<Query, Mutation>>::from_request(req) {
+        let schema = match State::<Schema<Query, Mutation>>::from_request(req) {
             Outcome::Success(s) => s,
             Outcome::Forward(_) => return Outcome::Forward(data),
             Outcome::Failure((f, _)) => return Outcome::Failure(f),
         };
 
-        let transform = <GraphQLRequest as FromData>::transform(req, data);
+        let execution_config = match State::<ExecutionConfig>::from_request(req) {
+            Outcome::Success(s) => s,
+            Outcome::Forward(_) => return Outcome::Forward(data),
+            Outcome::Failure((f, _)) => return Outcome::Failure(f),
+        };
 
-        let outcome = match transform {
-            Transform::Owned(Outcome::Success(v)) => Transform::Owned(Outcome::Success(v)),
-            Transform::Borrowed(Outcome::Success(ref v)) => {
-                Transform::Borrowed(Outcome::Success(Borrow::borrow(v)))
+        let multipart_boundary = req
+            .headers()
+            .get_one("Content-Type")
+            .and_then(upload::multipart_boundary);
+
+        let graphql_request = match multipart_boundary {
+            Some(boundary) => {
+                let upload_config = match State::<UploadConfig>::from_request(req) {
+                    Outcome::Success(s) => s,
+                    Outcome::Forward(_) => return Outcome::Forward(data),
+                    Outcome::Failure((f, _)) => return Outcome::Failure(f),
+                };
+
+                match upload::decode(data.open(), &boundary, &upload_config, &uploads(req)) {
+                    Ok(graphql_request) => graphql_request,
+                    Err(err) => {
+                        let status = if err.is_too_large() {
+                            Status::PayloadTooLarge
+                        } else {
+                            Status::BadRequest
+                        };
+                        return Outcome::from(req, status::Custom(status, err.to_string()));
+                    }
+                }
             }
-            Transform::Borrowed(o) => Transform::Borrowed(o.map(|_| unreachable!())),
-            Transform::Owned(o) => Transform::Owned(o),
-        };
+            None => {
+                if !req.content_type().map_or(false, |ct| ct.is_json()) {
+                    return Outcome::Forward(data);
+                }
 
-        let graphql_request = match <GraphQLRequest as FromData>::from_data(req, outcome) {
-            Outcome::Success(s) => s,
-            Outcome::Forward(f) => return Outcome::Forward(f),
-            Outcome::Failure((f, _)) => return Outcome::Failure(f),
+                let mut body = String::new();
+                if let Err(err) = data.open().read_to_string(&mut body) {
+                    rocket::logger::warn(&format!("Failed to read request body: {}", err));
+                    return Outcome::Failure(Status::InternalServerError);
+                }
+
+                match serde_json::from_str::<GraphqlBatchRequest>(&body) {
+                    Ok(graphql_request) => graphql_request,
+                    Err(err) => {
+                        return Outcome::from(req, status::BadRequest(Some(err.to_string())));
+                    }
+                }
+            }
         };
 
-        let responder = graphql_request.execute(&schema, &context);
+        let request_info = RequestInfo {
+            id: request_id.clone(),
+            wants_apollo_tracing: req.headers().contains(APOLLO_TRACING_HEADER),
+            pool_checkouts,
+        };
+        let response = graphql_request.execute(&schema, &context, &request_info, &execution_config);
 
-        Outcome::from(req, responder)
+        Outcome::from(req, graphql_response(req, response))
     }
 }
 
-impl<Query, Mutation, Context> Into<Vec<Route>> for PostGraphqlHandler<Query, Mutation, Context>
+impl<Query, Mutation, Context> From<PostGraphqlHandler<Query, Mutation, Context>> for Vec<Route>
 where
     Query: 'static + Send + Sync + Default + GraphQLType<TypeInfo = (), Context = Context>,
     Mutation: 'static + Send + Sync + Default + GraphQLType<TypeInfo = (), Context = Context>,
-    Context: 'static + juniper::Context + for<'ca, 'cr> FromRequest<'ca, 'cr>,
+    Context: 'static + juniper::Context + Authorizer + for<'ca, 'cr> FromRequest<'ca, 'cr>,
 {
-    fn into(self) -> Vec<Route> {
-        vec![Route::new(Method::Post, "/graphql", self)]
+    fn from(handler: PostGraphqlHandler<Query, Mutation, Context>) -> Self {
+        vec![Route::new(Method::Post, "/graphql", handler)]
     }
 }
 
@@ -210,69 +553,131 @@ impl<Query, Mutation, Context> Handler for GetGraphqlHandler<Query, Mutation, Co
 where
     Query: 'static + Send + Sync + Default + GraphQLType<TypeInfo = (), Context = Context>,
     Mutation: 'static + Send + Sync + Default + GraphQLType<TypeInfo = (), Context = Context>,
-    Context: 'static + juniper::Context + for<'ca, 'cr> FromRequest<'ca, 'cr>,
+    Context: 'static + juniper::Context + Authorizer + for<'ca, 'cr> FromRequest<'ca, 'cr>,
 {
     fn handle<'r>(&self, req: &'r Request, data: Data) -> handler::Outcome<'r> {
-        let context = match Context::from_request(req) {
+        let request_id = request_id(req);
+        let request_span = RequestSpan::new(request_id);
+        let _enter = request_span.span().enter();
+
+        #[cfg(feature = "auth")]
+        {
+            if let Err(err) = authentication(req) {
+                return unauthorized(err);
+            }
+        }
+
+        let pool_checkouts = PoolCheckouts::default();
+        let context = match pool_checkouts.in_scope(|| Context::from_request(req)) {
             Outcome::Success(s) => s,
             Outcome::Forward(_) => return Outcome::Forward(data),
             Outcome::Failure((f, _)) => return Outcome::Failure(f),
         };
 
-        let schema = match State::<RootNode<Query, Mutation>>::from_request(req) {
+        let schema = match State::<Schema<Query, Mutation>>::from_request(req) {
             Outcome::Success(s) => s,
             Outcome::Forward(_) => return Outcome::Forward(data),
             Outcome::Failure((f, _)) => return Outcome::Failure(f),
         };
 
-        let mut graphql_request: Option<juniper_rocket::GraphQLRequest> = None;
-        if let Some(items) = req.raw_query_items() {
-            for item in items {
-                match (item.key.as_str(), item.value) {
-                    ("request", value) => {
-                        let value = match <GraphQLRequest as FromFormValue>::from_form_value(value)
-                        {
-                            Ok(v) => v,
-                            Err(e) => {
-                                rocket::logger::warn(&format!(
-                                    "Failed to parse 'request': {:?}",
-                                    e
-                                ));
-
-                                return Outcome::Forward(data);
-                            }
-                        };
-                        graphql_request = Some(value);
-                    }
-                    #[allow(unreachable_patterns, unreachable_code)]
-                    _ => continue,
-                }
-            }
-        }
-
-        let graphql_request = match graphql_request
-            .or_else(<juniper_rocket::GraphQLRequest as ::rocket::request::FromFormValue>::default)
-        {
-            Some(v) => v,
-            None => {
-                rocket::logger::warn_("Missing required query parameter 'request'.");
-                return ::rocket::Outcome::Forward(data);
-            }
+        let execution_config = match State::<ExecutionConfig>::from_request(req) {
+            Outcome::Success(s) => s,
+            Outcome::Forward(_) => return Outcome::Forward(data),
+            Outcome::Failure((f, _)) => return Outcome::Failure(f),
         };
 
-        let responder = graphql_request.execute(&schema, &context);
+        let graphql_request =
+            match GraphqlBatchRequest::from_query_string(req.uri().query().unwrap_or("")) {
+                Ok(graphql_request) => graphql_request,
+                Err(err) => return Outcome::from(req, status::BadRequest(Some(err))),
+            };
 
-        Outcome::from(req, responder)
+        let request_info = RequestInfo {
+            id: request_id.clone(),
+            wants_apollo_tracing: req.headers().contains(APOLLO_TRACING_HEADER),
+            pool_checkouts,
+        };
+        let response = graphql_request.execute(&schema, &context, &request_info, &execution_config);
+
+        Outcome::from(req, graphql_response(req, response))
     }
 }
 
-impl<Query, Mutation, Context> Into<Vec<Route>> for GetGraphqlHandler<Query, Mutation, Context>
+impl<Query, Mutation, Context> From<GetGraphqlHandler<Query, Mutation, Context>> for Vec<Route>
 where
     Query: 'static + Send + Sync + Default + GraphQLType<TypeInfo = (), Context = Context>,
     Mutation: 'static + Send + Sync + Default + GraphQLType<TypeInfo = (), Context = Context>,
-    Context: 'static + juniper::Context + for<'ca, 'cr> FromRequest<'ca, 'cr>,
+    Context: 'static + juniper::Context + Authorizer + for<'ca, 'cr> FromRequest<'ca, 'cr>,
 {
-    fn into(self) -> Vec<Route> {
-        vec![Route::new(Method::Get, "/graphql", self)]
+    fn from(handler: GetGraphqlHandler<Query, Mutation, Context>) -> Self {
+        vec![Route::new(Method::Get, "/graphql", handler)]
     }
 }
+
+#[cfg(feature = "auth")]
+struct Authentication(Result<Option<Claims>, AuthError>);
+
+/// Verifies the request's bearer token once and caches the result for the rest of the request.
+#[cfg(feature = "auth")]
+fn authentication<'r>(req: &'r Request) -> &'r Result<Option<Claims>, AuthError> {
+    &req.local_cache(|| {
+        let result = match State::<JwtAuth>::from_request(req) {
+            Outcome::Success(jwt_auth) => {
+                jwt_auth.authenticate(req.headers().get_one("Authorization"))
+            }
+            _ => Ok(None),
+        };
+        Authentication(result)
+    })
+    .0
+}
+
+#[cfg(feature = "auth")]
+fn unauthorized<'r>(err: &AuthError) -> handler::Outcome<'r> {
+    let body = errors::error_response(ErrorCode::Unauthenticated, &err.to_string(), None);
+    let response = Response::build()
+        .status(Status::Unauthorized)
+        .header(ContentType::JSON)
+        .raw_header("WWW-Authenticate", "Bearer")
+        .sized_body(Cursor::new(body.to_string()))
+        .finalize();
+    Outcome::Success(response)
+}
+
+/// The verified claims of the request's bearer token, for use when implementing `FromRequest`
+/// for the context.
+///
+/// Returns `None` for anonymous requests and fails with `401 Unauthorized` if the token was
+/// rejected or the claims don't match `T`.
+#[cfg(feature = "auth")]
+pub fn claims<T: serde::de::DeserializeOwned>(
+    req: &Request,
+) -> rocket::request::Outcome<Option<T>, ()> {
+    let claims = match authentication(req) {
+        Ok(Some(claims)) => claims.deserialize(),
+        Ok(None) => return Outcome::Success(None),
+        Err(_) => return Outcome::Failure((Status::Unauthorized, ())),
+    };
+
+    match claims {
+        Ok(claims) => Outcome::Success(Some(claims)),
+        Err(err) => {
+            rocket::logger::warn(&format!("Rejecting request: {}", err));
+            Outcome::Failure((Status::Unauthorized, ()))
+        }
+    }
+}
+
+fn graphql_response(req: &Request, response: GraphqlResponse) -> juniper_rocket::GraphQLResponse {
+    let operations = response.operations;
+    req.local_cache(move || Operations(operations));
+
+    let status = if response.panicked {
+        Status::InternalServerError
+    } else if response.is_ok {
+        Status::Ok
+    } else {
+        Status::BadRequest
+    };
+    juniper_rocket::GraphQLResponse(status, response.body)
+}
@@ -0,0 +1,1107 @@
+//! Generating CRUD schemas and resolvers from Diesel `table!` definitions.
+//!
+//! `parse_tables` reads the tables of a Diesel schema file, and `generate` writes a GraphQL
+//! schema with a type, a list query, a by-id query and create, update and delete mutations for
+//! each table, along with the Rust code resolving them:
+//!
+//! ```ignore
+//! let source = fs::read_to_string("src/schema.rs")?;
+//! let scaffold = scaffold::generate(&scaffold::parse_tables(&source)?);
+//! fs::write("schema.graphql", scaffold.sdl)?;
+//! fs::write("src/graphql.rs", scaffold.rust)?;
+//! ```
+//!
+//! The output is a starting point to edit, not something to regenerate. The Rust code goes in
+//! the module that calls `graphql_schema_from_file!`, and expects a `Context` with a `db_pool`
+//! field like the examples have.
+//!
+//! Columns are mapped to `Int`, `Float`, `String` and `Boolean`. Columns of other types are left
+//! out of the schema and aren't selected. Primary keys made of one `Integer` column are assumed
+//! to be generated by the database and left out of the create input.
+//!
+//! Fields left out of an update input keep their value. juniper can't tell a field set to null
+//! from one left out, so the update input has a `clearEmail: Boolean` field for each nullable
+//! column, `email` here, that sets it to null.
+
+use std::fmt::Write;
+
+/// A table parsed from a `table!` definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    pub name: String,
+    pub primary_key: Vec<String>,
+    pub columns: Vec<Column>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    /// The SQL type without `Nullable` or a path, such as `Int4`.
+    pub sql_type: String,
+    pub nullable: bool,
+}
+
+/// The generated schema and resolvers.
+#[derive(Debug, Clone)]
+pub struct Scaffold {
+    pub sdl: String,
+    pub rust: String,
+}
+
+/// The tables defined with `table!` in `source`, such as the file written by
+/// `diesel print-schema`.
+pub fn parse_tables(source: &str) -> Result<Vec<Table>, String> {
+    let mut parser = Parser {
+        tokens: tokenize(source),
+        position: 0,
+    };
+
+    let mut tables = Vec::new();
+    while let Some(token) = parser.next_token() {
+        if token == "table" && parser.peek() == Some("!") {
+            parser.position += 1;
+            parser.expect("{")?;
+            parser.table_macro(&mut tables)?;
+        }
+    }
+    Ok(tables)
+}
+
+/// The schema and resolvers for `tables`. See the module docs.
+pub fn generate(tables: &[Table]) -> Scaffold {
+    let tables = tables.iter().map(TableNames::new).collect::<Vec<_>>();
+    Scaffold {
+        sdl: generate_sdl(&tables),
+        rust: generate_rust(&tables),
+    }
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|token| token.as_str())
+    }
+
+    fn next_token(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next_token() {
+            Some(ref token) if token == expected => Ok(()),
+            Some(token) => Err(format!("expected `{}`, found `{}`", expected, token)),
+            None => Err(format!(
+                "expected `{}`, found the end of the file",
+                expected
+            )),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        match self.next_token() {
+            Some(ref token) if is_ident(token) => Ok(token.clone()),
+            Some(token) => Err(format!("expected a name, found `{}`", token)),
+            None => Err("expected a name, found the end of the file".to_string()),
+        }
+    }
+
+    /// Skip tokens up to and including `end`, along with nested brackets.
+    fn skip_past(&mut self, end: &str) -> Result<(), String> {
+        let mut depth = 0;
+        while let Some(token) = self.next_token() {
+            match token.as_str() {
+                "[" | "(" | "{" => depth += 1,
+                "]" | ")" | "}" if depth > 0 => depth -= 1,
+                _ if depth == 0 && token == end => return Ok(()),
+                _ => {}
+            }
+        }
+        Err(format!("expected `{}`, found the end of the file", end))
+    }
+
+    /// The contents of a `table!` after its opening brace.
+    fn table_macro(&mut self, tables: &mut Vec<Table>) -> Result<(), String> {
+        loop {
+            match self.peek() {
+                Some("}") => {
+                    self.position += 1;
+                    return Ok(());
+                }
+                Some("use") => self.skip_past(";")?,
+                Some("#") => {
+                    self.position += 1;
+                    self.expect("[")?;
+                    self.skip_past("]")?;
+                }
+                _ => tables.push(self.table()?),
+            }
+        }
+    }
+
+    fn table(&mut self) -> Result<Table, String> {
+        let mut name = self.ident()?;
+        if self.peek() == Some(".") {
+            // Tables in a schema other than the default one.
+            self.position += 1;
+            name = self.ident()?;
+        }
+
+        let mut primary_key = Vec::new();
+        if self.peek() == Some("(") {
+            self.position += 1;
+            while self.peek() != Some(")") {
+                primary_key.push(self.ident()?);
+                if self.peek() == Some(",") {
+                    self.position += 1;
+                }
+            }
+            self.position += 1;
+        } else {
+            primary_key.push("id".to_string());
+        }
+
+        self.expect("{")
+            .map_err(|err| format!("{} in table {}", err, name))?;
+        let mut columns = Vec::new();
+        loop {
+            match self.peek() {
+                Some("}") => {
+                    self.position += 1;
+                    break;
+                }
+                Some("#") => {
+                    self.position += 1;
+                    self.expect("[")?;
+                    self.skip_past("]")?;
+                }
+                _ => columns.push(
+                    self.column()
+                        .map_err(|err| format!("{} in table {}", err, name))?,
+                ),
+            }
+        }
+
+        Ok(Table {
+            name,
+            primary_key,
+            columns,
+        })
+    }
+
+    fn column(&mut self) -> Result<Column, String> {
+        let name = self.ident()?;
+        self.expect("->")?;
+
+        let mut sql_type = String::new();
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                Some(",") if depth == 0 => {
+                    self.position += 1;
+                    break;
+                }
+                Some("}") if depth == 0 => break,
+                Some(token) => {
+                    match token {
+                        "<" => depth += 1,
+                        ">" => depth -= 1,
+                        _ => {}
+                    }
+                    sql_type.push_str(token);
+                    self.position += 1;
+                }
+                None => return Err(format!("expected the type of column {}", name)),
+            }
+        }
+
+        let (sql_type, nullable) = match strip_path(&sql_type) {
+            sql_type if sql_type.starts_with("Nullable<") && sql_type.ends_with('>') => (
+                strip_path(&sql_type["Nullable<".len()..sql_type.len() - 1]),
+                true,
+            ),
+            sql_type => (sql_type, false),
+        };
+
+        Ok(Column {
+            name,
+            sql_type: sql_type.to_string(),
+            nullable,
+        })
+    }
+}
+
+/// Split Rust source into identifiers, `->`, `::` and single characters, leaving out comments.
+fn tokenize(source: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    chars.next();
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            '"' => {
+                let mut string = String::from("\"");
+                while let Some(c) = chars.next() {
+                    string.push(c);
+                    match c {
+                        '\\' => string.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+                tokens.push(string);
+            }
+            '-' if chars.peek() == Some(&'>') => {
+                chars.next();
+                tokens.push("->".to_string());
+            }
+            ':' if chars.peek() == Some(&':') => {
+                chars.next();
+                tokens.push("::".to_string());
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    ident.push(c);
+                    chars.next();
+                }
+                tokens.push(ident);
+            }
+            c => tokens.push(c.to_string()),
+        }
+    }
+    tokens
+}
+
+fn is_ident(token: &str) -> bool {
+    token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+}
+
+/// `Integer` for `diesel::sql_types::Integer`, keeping type parameters as they are.
+fn strip_path(sql_type: &str) -> &str {
+    let end = sql_type.find('<').unwrap_or(sql_type.len());
+    match sql_type[..end].rfind("::") {
+        Some(start) => &sql_type[start + 2..],
+        None => sql_type,
+    }
+}
+
+/// The GraphQL and Rust types of an SQL type, or `None` if it isn't supported.
+fn column_types(sql_type: &str) -> Option<(&'static str, &'static str)> {
+    match sql_type {
+        "Int4" | "Integer" | "Serial" => Some(("Int", "i32")),
+        "Float8" | "Double" => Some(("Float", "f64")),
+        "Text" | "Varchar" | "VarChar" | "Citext" => Some(("String", "String")),
+        "Bool" => Some(("Boolean", "bool")),
+        _ => None,
+    }
+}
+
+/// A table with the names of everything generated for it.
+struct TableNames<'a> {
+    table: &'a Table,
+    /// The columns with supported types, and their GraphQL and Rust types.
+    columns: Vec<(&'a Column, &'static str, &'static str)>,
+    skipped_columns: Vec<&'a Column>,
+    /// `User` for `users`.
+    type_name: String,
+    /// `users` for `users`.
+    list_field: String,
+    /// `user` for `users`.
+    find_field: String,
+    /// Whether the primary key is generated by the database.
+    generated_key: bool,
+}
+
+impl<'a> TableNames<'a> {
+    fn new(table: &'a Table) -> Self {
+        let mut columns = Vec::new();
+        let mut skipped_columns = Vec::new();
+        for column in &table.columns {
+            match column_types(&column.sql_type) {
+                Some((graphql_type, rust_type)) => columns.push((column, graphql_type, rust_type)),
+                None => skipped_columns.push(column),
+            }
+        }
+
+        let singular = singularize(&table.name);
+        let list_field = camel_case(&table.name);
+        let mut find_field = camel_case(&singular);
+        if find_field == list_field {
+            find_field.push_str("ById");
+        }
+        let generated_key = match table.primary_key.as_slice() {
+            [key] => columns
+                .iter()
+                .any(|(column, graphql_type, _)| &column.name == key && *graphql_type == "Int"),
+            _ => false,
+        };
+
+        TableNames {
+            table,
+            columns,
+            skipped_columns,
+            type_name: pascal_case(&singular),
+            list_field,
+            find_field,
+            generated_key,
+        }
+    }
+
+    fn key_columns(&self) -> impl Iterator<Item = &(&'a Column, &'static str, &'static str)> {
+        self.columns
+            .iter()
+            .filter(move |(column, _, _)| self.table.primary_key.contains(&column.name))
+    }
+
+    fn input_columns(&self) -> impl Iterator<Item = &(&'a Column, &'static str, &'static str)> {
+        self.columns.iter().filter(move |(column, _, _)| {
+            !(self.generated_key && self.table.primary_key.contains(&column.name))
+        })
+    }
+
+    /// Columns that can be updated, which excludes the primary key.
+    fn changeable_columns(
+        &self,
+    ) -> impl Iterator<Item = &(&'a Column, &'static str, &'static str)> {
+        self.columns
+            .iter()
+            .filter(move |(column, _, _)| !self.table.primary_key.contains(&column.name))
+    }
+
+    /// `id: Int!` for each primary key column.
+    fn key_arguments(&self) -> String {
+        self.key_columns()
+            .map(|(column, graphql_type, _)| {
+                format!("{}: {}!", camel_case(&column.name), graphql_type)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// `id: i32` for each primary key column.
+    fn key_parameters(&self) -> String {
+        self.key_columns()
+            .map(|(column, _, rust_type)| format!("{}: {}", column.name, rust_type))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The argument of `find`.
+    fn key_value(&self) -> String {
+        let keys = self
+            .key_columns()
+            .map(|(column, _, _)| column.name.clone())
+            .collect::<Vec<_>>();
+        if keys.len() == 1 {
+            keys[0].clone()
+        } else {
+            format!("({})", keys.join(", "))
+        }
+    }
+
+    fn has_key(&self) -> bool {
+        !self.table.primary_key.is_empty()
+            && self.key_columns().count() == self.table.primary_key.len()
+    }
+
+    fn has_create(&self) -> bool {
+        self.input_columns().next().is_some()
+    }
+
+    fn has_update(&self) -> bool {
+        self.has_key() && self.changeable_columns().next().is_some()
+    }
+}
+
+fn generate_sdl(tables: &[TableNames]) -> String {
+    let mut sdl = String::new();
+    sdl.push_str("schema {\n  query: Query\n  mutation: Mutation\n}\n\n");
+
+    sdl.push_str("type Query {\n");
+    for table in tables {
+        writeln!(
+            sdl,
+            "  {}(limit: Int = 20, offset: Int = 0): [{}!]! @juniper(ownership: \"owned\")",
+            table.list_field, table.type_name
+        )
+        .unwrap();
+        if table.has_key() {
+            writeln!(
+                sdl,
+                "  {}({}): {} @juniper(ownership: \"owned\")",
+                table.find_field,
+                table.key_arguments(),
+                table.type_name
+            )
+            .unwrap();
+        }
+    }
+    sdl.push_str("}\n\n");
+
+    sdl.push_str("type Mutation {\n");
+    for table in tables {
+        if table.has_create() {
+            writeln!(
+                sdl,
+                "  create{0}(input: Create{0}Input!): {0}! @juniper(ownership: \"owned\")",
+                table.type_name
+            )
+            .unwrap();
+        }
+        if table.has_update() {
+            writeln!(
+                sdl,
+                "  update{0}({1}, input: Update{0}Input!): {0} @juniper(ownership: \"owned\")",
+                table.type_name,
+                table.key_arguments()
+            )
+            .unwrap();
+        }
+        if table.has_key() {
+            writeln!(
+                sdl,
+                "  delete{}({}): Boolean! @juniper(ownership: \"owned\")",
+                table.type_name,
+                table.key_arguments()
+            )
+            .unwrap();
+        }
+    }
+    sdl.push_str("}\n");
+
+    for table in tables {
+        sdl.push('\n');
+        if !table.skipped_columns.is_empty() {
+            writeln!(
+                sdl,
+                "# Columns left out: {}",
+                describe_columns(&table.skipped_columns)
+            )
+            .unwrap();
+        }
+        writeln!(sdl, "type {} {{", table.type_name).unwrap();
+        for (column, graphql_type, _) in &table.columns {
+            writeln!(
+                sdl,
+                "  {}: {}{}",
+                camel_case(&column.name),
+                graphql_type,
+                if column.nullable { "" } else { "!" }
+            )
+            .unwrap();
+        }
+        sdl.push_str("}\n");
+
+        if table.has_create() {
+            writeln!(sdl, "\ninput Create{}Input {{", table.type_name).unwrap();
+            for (column, graphql_type, _) in table.input_columns() {
+                writeln!(
+                    sdl,
+                    "  {}: {}{}",
+                    camel_case(&column.name),
+                    graphql_type,
+                    if column.nullable { "" } else { "!" }
+                )
+                .unwrap();
+            }
+            sdl.push_str("}\n");
+        }
+
+        if table.has_update() {
+            writeln!(sdl, "\ninput Update{}Input {{", table.type_name).unwrap();
+            for (column, graphql_type, _) in table.changeable_columns() {
+                writeln!(sdl, "  {}: {}", camel_case(&column.name), graphql_type).unwrap();
+                if column.nullable {
+                    writeln!(
+                        sdl,
+                        "  {}: Boolean",
+                        camel_case(&format!("clear_{}", column.name))
+                    )
+                    .unwrap();
+                }
+            }
+            sdl.push_str("}\n");
+        }
+    }
+
+    sdl
+}
+
+fn generate_rust(tables: &[TableNames]) -> String {
+    let mut rust = String::new();
+    writeln!(
+        rust,
+        "use crate::schema::{{{}}};",
+        tables
+            .iter()
+            .map(|table| table.table.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+    .unwrap();
+    rust.push_str("use diesel::prelude::*;\n");
+    rust.push_str("use gimme_graphql::get_connection;\n");
+    rust.push_str("use juniper::{Executor, FieldResult};\n\n");
+
+    rust.push_str("#[derive(Default)]\npub struct Query;\n\n");
+    rust.push_str("impl QueryFields for Query {\n");
+    for (index, table) in tables.iter().enumerate() {
+        if index > 0 {
+            rust.push('\n');
+        }
+        query_resolvers(&mut rust, table);
+    }
+    rust.push_str("}\n\n");
+
+    rust.push_str("#[derive(Default)]\npub struct Mutation;\n\n");
+    rust.push_str("impl MutationFields for Mutation {\n");
+    for (index, table) in tables.iter().enumerate() {
+        if index > 0 {
+            rust.push('\n');
+        }
+        mutation_resolvers(&mut rust, table);
+    }
+    rust.push_str("}\n");
+
+    for table in tables {
+        rust.push('\n');
+        model(&mut rust, table);
+    }
+
+    rust
+}
+
+fn query_resolvers(rust: &mut String, table: &TableNames) {
+    let name = &table.table.name;
+    writeln!(
+        rust,
+        r#"    fn field_{list_field}(
+        &self,
+        executor: &Executor<'_, Context>,
+        _: &QueryTrail<'_, {type_name}, Walked>,
+        limit: i32,
+        offset: i32,
+    ) -> FieldResult<Vec<{type_name}>> {{
+        let db = &get_connection(&executor.context().db_pool)?;
+        let {name} = {name}::table
+            .select({columns})
+            .limit(limit.into())
+            .offset(offset.into())
+            .load::<{type_name}>(db)?;
+        Ok({name})
+    }}"#,
+        list_field = snake_case(&table.list_field),
+        type_name = table.type_name,
+        name = name,
+        columns = columns_constant(table),
+    )
+    .unwrap();
+
+    if table.has_key() {
+        writeln!(
+            rust,
+            r#"
+    fn field_{find_field}(
+        &self,
+        executor: &Executor<'_, Context>,
+        _: &QueryTrail<'_, {type_name}, Walked>,
+        {key_parameters},
+    ) -> FieldResult<Option<{type_name}>> {{
+        let db = &get_connection(&executor.context().db_pool)?;
+        let {singular} = {name}::table
+            .find({key_value})
+            .select({columns})
+            .first::<{type_name}>(db)
+            .optional()?;
+        Ok({singular})
+    }}"#,
+            find_field = snake_case(&table.find_field),
+            type_name = table.type_name,
+            key_parameters = table.key_parameters(),
+            singular = snake_case(&table.type_name),
+            name = name,
+            key_value = table.key_value(),
+            columns = columns_constant(table),
+        )
+        .unwrap();
+    }
+}
+
+fn mutation_resolvers(rust: &mut String, table: &TableNames) {
+    let name = &table.table.name;
+    let singular = snake_case(&table.type_name);
+    let mut resolvers = Vec::new();
+
+    if table.has_create() {
+        let mut input_fields = String::new();
+        for (column, _, _) in table.input_columns() {
+            input_fields.push_str(&format!("            {0}: input.{0},\n", column.name));
+        }
+        resolvers.push(format!(
+            r#"    fn field_create_{singular}(
+        &self,
+        executor: &Executor<'_, Context>,
+        _: &QueryTrail<'_, {type_name}, Walked>,
+        input: Create{type_name}Input,
+    ) -> FieldResult<{type_name}> {{
+        let db = &get_connection(&executor.context().db_pool)?;
+        let new_{singular} = New{type_name} {{
+{input_fields}        }};
+        let {singular} = diesel::insert_into({name}::table)
+            .values(&new_{singular})
+            .returning({columns})
+            .get_result::<{type_name}>(db)?;
+        Ok({singular})
+    }}"#,
+            singular = singular,
+            type_name = table.type_name,
+            input_fields = input_fields,
+            name = name,
+            columns = columns_constant(table),
+        ));
+    }
+
+    if table.has_update() {
+        let change_fields = table
+            .changeable_columns()
+            .map(|(column, _, _)| {
+                if column.nullable {
+                    format!(
+                        "            {0}: if input.clear_{0} == Some(true) {{\n                Some(None)\n            }} else {{\n                input.{0}.map(Some)\n            }},\n",
+                        column.name
+                    )
+                } else {
+                    format!("            {0}: input.{0},\n", column.name)
+                }
+            })
+            .collect::<String>();
+        let unchanged = table
+            .changeable_columns()
+            .map(|(column, _, _)| format!("changes.{}.is_none()", column.name))
+            .collect::<Vec<_>>()
+            .join(" && ");
+        resolvers.push(format!(
+            r#"    fn field_update_{singular}(
+        &self,
+        executor: &Executor<'_, Context>,
+        _: &QueryTrail<'_, {type_name}, Walked>,
+        {key_parameters},
+        input: Update{type_name}Input,
+    ) -> FieldResult<Option<{type_name}>> {{
+        let db = &get_connection(&executor.context().db_pool)?;
+        let changes = {type_name}Changes {{
+{change_fields}        }};
+        // Diesel refuses to run an update without changes.
+        if {unchanged} {{
+            let {singular} = {name}::table
+                .find({key_value})
+                .select({columns})
+                .first::<{type_name}>(db)
+                .optional()?;
+            return Ok({singular});
+        }}
+        let {singular} = diesel::update({name}::table.find({key_value}))
+            .set(&changes)
+            .returning({columns})
+            .get_result::<{type_name}>(db)
+            .optional()?;
+        Ok({singular})
+    }}"#,
+            singular = singular,
+            type_name = table.type_name,
+            key_parameters = table.key_parameters(),
+            change_fields = change_fields,
+            unchanged = unchanged,
+            name = name,
+            key_value = table.key_value(),
+            columns = columns_constant(table),
+        ));
+    }
+
+    if table.has_key() {
+        resolvers.push(format!(
+            r#"    fn field_delete_{singular}(
+        &self,
+        executor: &Executor<'_, Context>,
+        {key_parameters},
+    ) -> FieldResult<bool> {{
+        let db = &get_connection(&executor.context().db_pool)?;
+        let deleted = diesel::delete({name}::table.find({key_value})).execute(db)?;
+        Ok(deleted > 0)
+    }}"#,
+            singular = singular,
+            key_parameters = table.key_parameters(),
+            name = name,
+            key_value = table.key_value(),
+        ));
+    }
+
+    writeln!(rust, "{}", resolvers.join("\n\n")).unwrap();
+}
+
+/// The struct of a table with its field resolvers, and the structs for inserting and updating.
+fn model(rust: &mut String, table: &TableNames) {
+    let name = &table.table.name;
+    let rust_type = |column: &Column, rust_type: &str| {
+        if column.nullable {
+            format!("Option<{}>", rust_type)
+        } else {
+            rust_type.to_string()
+        }
+    };
+
+    let columns = table
+        .columns
+        .iter()
+        .map(|(column, _, _)| format!("{}::{}", name, column.name))
+        .collect::<Vec<_>>();
+    let columns = if columns.len() == 1 {
+        format!("({},)", columns[0])
+    } else {
+        format!("({})", columns.join(", "))
+    };
+    writeln!(
+        rust,
+        "const {}: {} = {};\n",
+        columns_constant(table),
+        columns,
+        columns
+    )
+    .unwrap();
+
+    if !table.skipped_columns.is_empty() {
+        writeln!(
+            rust,
+            "// Columns left out: {}",
+            describe_columns(&table.skipped_columns)
+        )
+        .unwrap();
+    }
+    writeln!(
+        rust,
+        "#[derive(Queryable)]\npub struct {} {{",
+        table.type_name
+    )
+    .unwrap();
+    for (column, _, column_type) in &table.columns {
+        writeln!(
+            rust,
+            "    pub {}: {},",
+            column.name,
+            rust_type(column, column_type)
+        )
+        .unwrap();
+    }
+    rust.push_str("}\n\n");
+
+    writeln!(
+        rust,
+        "impl {}Fields for {} {{",
+        table.type_name, table.type_name
+    )
+    .unwrap();
+    for (index, (column, _, column_type)) in table.columns.iter().enumerate() {
+        if index > 0 {
+            rust.push('\n');
+        }
+        writeln!(
+            rust,
+            r#"    fn field_{field}(&self, _: &Executor<'_, Context>) -> FieldResult<&{rust_type}> {{
+        Ok(&self.{field})
+    }}"#,
+            field = column.name,
+            rust_type = rust_type(column, column_type),
+        )
+        .unwrap();
+    }
+    rust.push_str("}\n");
+
+    if table.has_create() {
+        writeln!(
+            rust,
+            "\n#[derive(Insertable)]\n#[table_name = \"{}\"]\nstruct New{} {{",
+            name, table.type_name
+        )
+        .unwrap();
+        for (column, _, column_type) in table.input_columns() {
+            writeln!(
+                rust,
+                "    {}: {},",
+                column.name,
+                rust_type(column, column_type)
+            )
+            .unwrap();
+        }
+        rust.push_str("}\n");
+    }
+
+    if table.has_update() {
+        // Fields that are `None` are left unchanged, and nullable columns set to `Some(None)` are
+        // set to null.
+        writeln!(
+            rust,
+            "\n#[derive(AsChangeset)]\n#[table_name = \"{}\"]\nstruct {}Changes {{",
+            name, table.type_name
+        )
+        .unwrap();
+        for (column, _, column_type) in table.changeable_columns() {
+            writeln!(
+                rust,
+                "    {}: Option<{}>,",
+                column.name,
+                rust_type(column, column_type)
+            )
+            .unwrap();
+        }
+        rust.push_str("}\n");
+    }
+}
+
+/// The name of the constant with the selected columns of a table.
+fn columns_constant(table: &TableNames) -> String {
+    format!("{}_COLUMNS", snake_case(&table.type_name).to_uppercase())
+}
+
+fn describe_columns(columns: &[&Column]) -> String {
+    columns
+        .iter()
+        .map(|column| format!("{} ({})", column.name, column.sql_type))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `user` for `users`, `category` for `categories`.
+fn singularize(name: &str) -> String {
+    if let Some(stem) = name.strip_suffix("ies") {
+        format!("{}y", stem)
+    } else if ["sses", "shes", "ches", "xes"]
+        .iter()
+        .any(|suffix| name.ends_with(suffix))
+    {
+        name[..name.len() - 2].to_string()
+    } else if name.ends_with('s') && !name.ends_with("ss") {
+        name[..name.len() - 1].to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// `blogPost` for `blog_post`.
+fn camel_case(name: &str) -> String {
+    let pascal_case = pascal_case(name);
+    let mut chars = pascal_case.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// `BlogPost` for `blog_post`.
+fn pascal_case(name: &str) -> String {
+    name.split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// `blog_post` for `blogPost` or `BlogPost`.
+fn snake_case(name: &str) -> String {
+    let mut snake_case = String::new();
+    for (index, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if index > 0 {
+                snake_case.push('_');
+            }
+            snake_case.extend(c.to_lowercase());
+        } else {
+            snake_case.push(c);
+        }
+    }
+    snake_case
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        // @generated automatically by Diesel CLI.
+        table! {
+            use diesel::sql_types::*;
+
+            /// Representation of the `users` table.
+            users (id) {
+                id -> Int4,
+                name -> Varchar,
+                /* The address mail is sent to. */
+                email -> Nullable<diesel::sql_types::Text>,
+                tags -> Array<Text>,
+            }
+        }
+
+        table! {
+            blog.post_tags (post_id, tag) {
+                post_id -> Integer,
+                #[sql_name = "tag_name"]
+                tag -> Text,
+            }
+        }
+
+        joinable!(post_tags -> users (post_id));
+    "#;
+
+    #[test]
+    fn tables_are_parsed() {
+        let tables = parse_tables(SCHEMA).unwrap();
+
+        let column = |name: &str, sql_type: &str, nullable| Column {
+            name: name.to_string(),
+            sql_type: sql_type.to_string(),
+            nullable,
+        };
+        assert_eq!(
+            tables,
+            vec![
+                Table {
+                    name: "users".to_string(),
+                    primary_key: vec!["id".to_string()],
+                    columns: vec![
+                        column("id", "Int4", false),
+                        column("name", "Varchar", false),
+                        column("email", "Text", true),
+                        column("tags", "Array<Text>", false),
+                    ],
+                },
+                Table {
+                    name: "post_tags".to_string(),
+                    primary_key: vec!["post_id".to_string(), "tag".to_string()],
+                    columns: vec![
+                        column("post_id", "Integer", false),
+                        column("tag", "Text", false)
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn the_primary_key_defaults_to_id() {
+        let tables = parse_tables("table! { posts { id -> Integer, } }").unwrap();
+
+        assert_eq!(tables[0].primary_key, vec!["id".to_string()]);
+    }
+
+    #[test]
+    fn malformed_tables_are_errors() {
+        assert_eq!(
+            parse_tables("table! { users { id Integer } }").unwrap_err(),
+            "expected `->`, found `Integer` in table users"
+        );
+        assert_eq!(
+            parse_tables("table! { users { id -> ").unwrap_err(),
+            "expected the type of column id in table users"
+        );
+        assert_eq!(
+            parse_tables("table! { users ").unwrap_err(),
+            "expected `{`, found the end of the file in table users"
+        );
+    }
+
+    #[test]
+    fn table_names_are_singularized() {
+        assert_eq!(singularize("users"), "user");
+        assert_eq!(singularize("categories"), "category");
+        assert_eq!(singularize("addresses"), "address");
+        assert_eq!(singularize("wishes"), "wish");
+        assert_eq!(singularize("matches"), "match");
+        assert_eq!(singularize("boxes"), "box");
+        assert_eq!(singularize("glass"), "glass");
+        assert_eq!(singularize("news"), "new");
+        assert_eq!(singularize("staff"), "staff");
+    }
+
+    #[test]
+    fn names_are_converted_between_cases() {
+        assert_eq!(camel_case("blog_post"), "blogPost");
+        assert_eq!(camel_case("id"), "id");
+        assert_eq!(camel_case("_private__name_"), "privateName");
+        assert_eq!(pascal_case("blog_post"), "BlogPost");
+        assert_eq!(pascal_case(""), "");
+        assert_eq!(snake_case("blogPost"), "blog_post");
+        assert_eq!(snake_case("BlogPost"), "blog_post");
+        assert_eq!(snake_case("id"), "id");
+        assert_eq!(snake_case(&camel_case("post_tags")), "post_tags");
+    }
+
+    #[test]
+    fn nullable_columns_can_be_cleared() {
+        let scaffold = generate(&parse_tables(SCHEMA).unwrap());
+
+        assert!(
+            scaffold
+                .sdl
+                .contains("input UpdateUserInput {\n  name: String\n  email: String\n  clearEmail: Boolean\n}"),
+            "{}",
+            scaffold.sdl
+        );
+        assert!(
+            scaffold
+                .rust
+                .contains("    name: Option<String>,\n    email: Option<Option<String>>,\n"),
+            "{}",
+            scaffold.rust
+        );
+        assert!(
+            scaffold
+                .rust
+                .contains("            email: if input.clear_email == Some(true) {"),
+            "{}",
+            scaffold.rust
+        );
+    }
+
+    #[test]
+    fn updates_without_changes_return_the_row() {
+        let scaffold = generate(&parse_tables(SCHEMA).unwrap());
+
+        assert!(
+            scaffold
+                .rust
+                .contains("        if changes.name.is_none() && changes.email.is_none() {\n            let user = users::table\n                .find(id)"),
+            "{}",
+            scaffold.rust
+        );
+    }
+}
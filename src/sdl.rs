@@ -0,0 +1,187 @@
+//! Printing juniper types in the GraphQL schema language.
+
+use juniper::meta::{
+    Argument, DeprecationStatus, EnumMeta, Field, InputObjectMeta, MetaType, UnionMeta,
+};
+use juniper::{DefaultScalarValue, GraphQLType, RootNode};
+use std::fmt::Write;
+
+/// Scalars every schema has, which aren't declared.
+const BUILT_IN_SCALARS: &[&str] = &["Boolean", "Float", "ID", "Int", "String"];
+
+/// The SDL of a schema, with its types sorted by name.
+pub(crate) fn print_schema<Query, Mutation>(root_node: &RootNode<Query, Mutation>) -> String
+where
+    Query: GraphQLType<TypeInfo = ()>,
+    Mutation: GraphQLType<TypeInfo = ()>,
+{
+    let schema = &root_node.schema;
+    let mut sdl = String::from("schema {\n");
+    if let Some(name) = schema.concrete_query_type().name() {
+        writeln!(sdl, "  query: {}", name).unwrap();
+    }
+    if let Some(name) = schema
+        .concrete_mutation_type()
+        .and_then(|mutation| mutation.name())
+    {
+        writeln!(sdl, "  mutation: {}", name).unwrap();
+    }
+    sdl.push_str("}\n\n");
+    sdl.push_str(&print_types(schema.concrete_type_list()));
+    sdl
+}
+
+/// The SDL of the input objects and enums in `types`, sorted by name.
+pub(crate) fn print_input_types<'a, 'r: 'a, I>(types: I) -> String
+where
+    I: IntoIterator<Item = &'a MetaType<'r>>,
+{
+    print_types(
+        types
+            .into_iter()
+            .filter(|meta| matches!(meta, MetaType::InputObject(_) | MetaType::Enum(_))),
+    )
+}
+
+/// Introspection types and built-in scalars are left out.
+fn print_types<'a, 'r: 'a, I>(types: I) -> String
+where
+    I: IntoIterator<Item = &'a MetaType<'r>>,
+{
+    let mut types = types
+        .into_iter()
+        .filter_map(|meta| {
+            let name = meta.name()?;
+            if name.starts_with("__") {
+                return None;
+            }
+            let sdl = match meta {
+                MetaType::Scalar(scalar) if !BUILT_IN_SCALARS.contains(&name) => {
+                    let mut sdl = String::new();
+                    print_description(&mut sdl, "", &scalar.description);
+                    writeln!(sdl, "scalar {}", name).unwrap();
+                    sdl
+                }
+                MetaType::Object(object) => print_object(
+                    "type",
+                    name,
+                    &object.description,
+                    &object.interface_names,
+                    &object.fields,
+                ),
+                MetaType::Interface(interface) => print_object(
+                    "interface",
+                    name,
+                    &interface.description,
+                    &[],
+                    &interface.fields,
+                ),
+                MetaType::Union(union) => print_union(union),
+                MetaType::Enum(enum_meta) => print_enum(enum_meta),
+                MetaType::InputObject(input_object) => print_input_object(input_object),
+                _ => return None,
+            };
+            Some((name, sdl))
+        })
+        .collect::<Vec<_>>();
+    types.sort_by(|a, b| a.0.cmp(b.0));
+    types
+        .into_iter()
+        .map(|(_, sdl)| sdl)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn print_object(
+    keyword: &str,
+    name: &str,
+    description: &Option<String>,
+    interface_names: &[String],
+    fields: &[Field<DefaultScalarValue>],
+) -> String {
+    let mut sdl = String::new();
+    print_description(&mut sdl, "", description);
+    write!(sdl, "{} {}", keyword, name).unwrap();
+    if !interface_names.is_empty() {
+        write!(sdl, " implements {}", interface_names.join(" & ")).unwrap();
+    }
+    sdl.push_str(" {\n");
+    for field in fields.iter().filter(|field| !field.name.starts_with("__")) {
+        print_description(&mut sdl, "  ", &field.description);
+        write!(sdl, "  {}", field.name).unwrap();
+        if let Some(arguments) = field.arguments.as_ref().filter(|args| !args.is_empty()) {
+            let arguments = arguments.iter().map(print_argument).collect::<Vec<_>>();
+            write!(sdl, "({})", arguments.join(", ")).unwrap();
+        }
+        write!(sdl, ": {}", field.field_type).unwrap();
+        print_deprecation(&mut sdl, &field.deprecation_status);
+        sdl.push('\n');
+    }
+    sdl.push_str("}\n");
+    sdl
+}
+
+fn print_union(union: &UnionMeta) -> String {
+    let mut sdl = String::new();
+    print_description(&mut sdl, "", &union.description);
+    writeln!(
+        sdl,
+        "union {} = {}",
+        union.name,
+        union.of_type_names.join(" | ")
+    )
+    .unwrap();
+    sdl
+}
+
+fn print_input_object(input_object: &InputObjectMeta<DefaultScalarValue>) -> String {
+    let mut sdl = String::new();
+    print_description(&mut sdl, "", &input_object.description);
+    writeln!(sdl, "input {} {{", input_object.name).unwrap();
+    for field in &input_object.input_fields {
+        print_description(&mut sdl, "  ", &field.description);
+        writeln!(sdl, "  {}", print_argument(field)).unwrap();
+    }
+    sdl.push_str("}\n");
+    sdl
+}
+
+fn print_enum(enum_meta: &EnumMeta<DefaultScalarValue>) -> String {
+    let mut sdl = String::new();
+    print_description(&mut sdl, "", &enum_meta.description);
+    writeln!(sdl, "enum {} {{", enum_meta.name).unwrap();
+    for value in &enum_meta.values {
+        print_description(&mut sdl, "  ", &value.description);
+        write!(sdl, "  {}", value.name).unwrap();
+        print_deprecation(&mut sdl, &value.deprecation_status);
+        sdl.push('\n');
+    }
+    sdl.push_str("}\n");
+    sdl
+}
+
+fn print_argument(argument: &Argument<DefaultScalarValue>) -> String {
+    match &argument.default_value {
+        Some(default_value) => format!(
+            "{}: {} = {}",
+            argument.name, argument.arg_type, default_value
+        ),
+        None => format!("{}: {}", argument.name, argument.arg_type),
+    }
+}
+
+fn print_deprecation(sdl: &mut String, deprecation_status: &DeprecationStatus) {
+    match deprecation_status {
+        DeprecationStatus::Current => {}
+        DeprecationStatus::Deprecated(None) => sdl.push_str(" @deprecated"),
+        DeprecationStatus::Deprecated(Some(reason)) => {
+            write!(sdl, " @deprecated(reason: {:?})", reason).unwrap()
+        }
+    }
+}
+
+fn print_description(sdl: &mut String, indent: &str, description: &Option<String>) {
+    if let Some(description) = description {
+        writeln!(sdl, "{}\"\"\"{}\"\"\"", indent, description).unwrap();
+    }
+}
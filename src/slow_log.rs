@@ -0,0 +1,215 @@
+//! Logging operations that took longer than `GraphqlApp::slow_query_threshold`.
+//!
+//! Slow operations are logged at WARN with the target `gimme_graphql::slow_query`. The line has
+//! the query's hash, the query as formatted by `graphql-parser` with its string literals
+//! redacted, the variables with sensitive values redacted, the slowest resolvers and the number
+//! of connections checked out of the database pool while executing. Queries that don't parse
+//! are left out. Only the fields of the query and mutation types, and nested fields that call
+//! `time_resolver`, are timed, see the `instrumentation` module.
+//!
+//! String literals are redacted because clients put the same values in them as in variables,
+//! and unlike variables there's no name to tell whether they're sensitive.
+
+use crate::access_log::REDACTED;
+use crate::instrumentation::ResolverTiming;
+use crate::request::OperationSummary;
+use crate::request_id::RequestId;
+use crate::telemetry;
+use graphql_parser::query::{
+    Definition, Directive, Document, OperationDefinition, Selection, SelectionSet, Value,
+    VariableDefinition,
+};
+use std::cmp::Reverse;
+
+/// How many resolvers to include in the log line.
+const SLOWEST_RESOLVERS: usize = 5;
+
+pub(crate) fn log(
+    request_id: &RequestId,
+    operation: &OperationSummary,
+    document: Option<&Document>,
+    resolvers: &[ResolverTiming],
+    pool_checkouts: usize,
+) {
+    let mut resolvers = resolvers.iter().collect::<Vec<_>>();
+    resolvers.sort_by_key(|resolver| Reverse(resolver.duration));
+    let slowest_resolvers = resolvers
+        .iter()
+        .take(SLOWEST_RESOLVERS)
+        .map(|resolver| {
+            format!(
+                "{}.{} {}ms",
+                resolver.parent_type,
+                resolver.field_name,
+                telemetry::millis(resolver.duration)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    log::warn!(
+        target: "gimme_graphql::slow_query",
+        "Slow {} {} took {}ms (request id {}, query hash {}), {} database connections checked out\nSlowest resolvers: {}\nVariables: {}\n{}",
+        operation.operation_type.unwrap_or("operation"),
+        operation
+            .operation_name
+            .as_ref()
+            .map_or("<anonymous>", |name| name.as_str()),
+        telemetry::millis(operation.duration),
+        request_id,
+        operation.query_hash.as_ref().map_or("none", |hash| hash.as_str()),
+        pool_checkouts,
+        if slowest_resolvers.is_empty() {
+            "none".to_string()
+        } else {
+            slowest_resolvers.join(", ")
+        },
+        operation.variables,
+        document.map_or_else(
+            || "<unparsable query>".to_string(),
+            |document| redact_strings(document).to_string()
+        ),
+    );
+}
+
+/// A copy of the document with every string literal replaced by `[REDACTED]`.
+fn redact_strings(document: &Document) -> Document {
+    let mut document = document.clone();
+    for definition in &mut document.definitions {
+        match definition {
+            Definition::Operation(OperationDefinition::SelectionSet(selection_set)) => {
+                redact_selection_set(selection_set)
+            }
+            Definition::Operation(OperationDefinition::Query(query)) => {
+                redact_variable_defaults(&mut query.variable_definitions);
+                redact_directives(&mut query.directives);
+                redact_selection_set(&mut query.selection_set);
+            }
+            Definition::Operation(OperationDefinition::Mutation(mutation)) => {
+                redact_variable_defaults(&mut mutation.variable_definitions);
+                redact_directives(&mut mutation.directives);
+                redact_selection_set(&mut mutation.selection_set);
+            }
+            Definition::Operation(OperationDefinition::Subscription(subscription)) => {
+                redact_variable_defaults(&mut subscription.variable_definitions);
+                redact_directives(&mut subscription.directives);
+                redact_selection_set(&mut subscription.selection_set);
+            }
+            Definition::Fragment(fragment) => {
+                redact_directives(&mut fragment.directives);
+                redact_selection_set(&mut fragment.selection_set);
+            }
+        }
+    }
+    document
+}
+
+fn redact_variable_defaults(variable_definitions: &mut [VariableDefinition]) {
+    for variable_definition in variable_definitions {
+        if let Some(default_value) = &mut variable_definition.default_value {
+            redact_value(default_value);
+        }
+    }
+}
+
+fn redact_selection_set(selection_set: &mut SelectionSet) {
+    for selection in &mut selection_set.items {
+        match selection {
+            Selection::Field(field) => {
+                for (_, value) in &mut field.arguments {
+                    redact_value(value);
+                }
+                redact_directives(&mut field.directives);
+                redact_selection_set(&mut field.selection_set);
+            }
+            Selection::FragmentSpread(spread) => redact_directives(&mut spread.directives),
+            Selection::InlineFragment(fragment) => {
+                redact_directives(&mut fragment.directives);
+                redact_selection_set(&mut fragment.selection_set);
+            }
+        }
+    }
+}
+
+fn redact_directives(directives: &mut [Directive]) {
+    for directive in directives {
+        for (_, value) in &mut directive.arguments {
+            redact_value(value);
+        }
+    }
+}
+
+fn redact_value(value: &mut Value) {
+    match value {
+        Value::String(string) => *string = REDACTED.to_string(),
+        Value::List(values) => values.iter_mut().for_each(redact_value),
+        Value::Object(fields) => fields.values_mut().for_each(redact_value),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redacted(query: &str) -> String {
+        redact_strings(&graphql_parser::parse_query(query).unwrap()).to_string()
+    }
+
+    #[test]
+    fn argument_strings_are_redacted() {
+        let query = redacted(r#"{ login(email: "a@example.com", password: "hunter2") { token } }"#);
+
+        assert!(!query.contains("a@example.com"), "{}", query);
+        assert!(!query.contains("hunter2"), "{}", query);
+        assert!(
+            query.contains(r#"login(email: "[REDACTED]", password: "[REDACTED]")"#),
+            "{}",
+            query
+        );
+    }
+
+    #[test]
+    fn strings_in_input_objects_and_lists_are_redacted() {
+        let query = redacted(
+            r#"mutation { createUser(input: { name: "Ann", tags: ["a", "b"], age: 3 }) { id } }"#,
+        );
+
+        assert!(!query.contains("Ann"), "{}", query);
+        assert!(!query.contains(r#""a""#), "{}", query);
+        assert!(query.contains("age: 3"), "{}", query);
+    }
+
+    #[test]
+    fn strings_in_defaults_directives_and_fragments_are_redacted() {
+        let query = redacted(
+            r#"
+            query Users($token: String = "abc") @cached(key: "k1") {
+                users { ...UserFields @include(if: true) ... on User @skip(if: false) { bio(lang: "en") } }
+            }
+            fragment UserFields on User @tag(name: "f1") { name(format: "short") }
+            "#,
+        );
+
+        for secret in &["abc", "k1", "en", "f1", "short"] {
+            assert!(!query.contains(&format!("\"{}\"", secret)), "{}", query);
+        }
+        assert!(
+            query.contains("$token: String = \"[REDACTED]\""),
+            "{}",
+            query
+        );
+    }
+
+    #[test]
+    fn other_values_are_kept() {
+        let query = redacted(
+            "query Users($limit: Int) { users(limit: $limit, role: ADMIN, active: true) { id } }",
+        );
+
+        assert!(
+            query.contains("users(limit: $limit, role: ADMIN, active: true)"),
+            "{}",
+            query
+        );
+    }
+}
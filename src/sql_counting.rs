@@ -0,0 +1,382 @@
+//! Counting the SQL statements of each GraphQL request, to find N+1 queries.
+//!
+//! Use `CountingConnection` as the app's connection type so the statements resolvers run
+//! through the pool can be counted:
+//!
+//! ```ignore
+//! impl GraphqlApp for App {
+//!     type Connection = CountingConnection<PgConnection>;
+//!     // ...
+//! }
+//! ```
+//!
+//! While `GraphqlApp::count_sql_statements` is enabled, which by default it only is in
+//! development, the number of statements an operation ran is added to the `sqlStatements`
+//! field of the response's `extensions`. If the same statement, ignoring its bind parameters,
+//! runs more than `GraphqlApp::n_plus_one_threshold` times in one operation, a warning is
+//! logged with the target `gimme_graphql::n_plus_one`. Otherwise the connection behaves exactly
+//! like the wrapped one. If the pool's connections aren't `CountingConnection`s, statements
+//! can't be counted, so `sqlStatements` is left out and a warning is logged at startup.
+//!
+//! If a resolver panics while one of these connections has a transaction open, the transaction
+//! is rolled back before the connection runs anything else. See the `panics` module.
+//!
+//! Only statements built with Diesel's query builder, including `sql_query`, are counted.
+//! Statements run with `batch_execute`, such as those starting and ending transactions, are
+//! not.
+
+use crate::panics;
+use diesel::backend::{Backend, UsesAnsiSavepointSyntax};
+use diesel::connection::{
+    AnsiTransactionManager, Connection, SimpleConnection, TransactionManager,
+};
+use diesel::deserialize::{Queryable, QueryableByName};
+use diesel::query_builder::{AsQuery, QueryBuilder, QueryFragment, QueryId};
+use diesel::result::{ConnectionResult, QueryResult};
+use diesel::sql_types::HasSqlType;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+thread_local! {
+    static STATEMENTS: RefCell<Option<StatementCounts>> = const { RefCell::new(None) };
+}
+
+/// Set once any `CountingConnection` is established.
+static ESTABLISHED: AtomicBool = AtomicBool::new(false);
+
+/// Whether any `CountingConnection` has been established. The pool establishes its
+/// connections when it's built, so afterwards this tells whether they're counted.
+pub(crate) fn connections_are_counted() -> bool {
+    ESTABLISHED.load(Ordering::SeqCst)
+}
+
+/// How many times each statement ran, collected by [`count_statements`].
+#[derive(Default)]
+pub(crate) struct StatementCounts {
+    counts: HashMap<String, usize>,
+}
+
+impl StatementCounts {
+    pub(crate) fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    /// The statements that ran more than `threshold` times, most frequent first.
+    pub(crate) fn repeated(&self, threshold: usize) -> Vec<(&str, usize)> {
+        let mut repeated = self
+            .counts
+            .iter()
+            .filter(|(_, count)| **count > threshold)
+            .map(|(sql, count)| (sql.as_str(), *count))
+            .collect::<Vec<_>>();
+        repeated.sort_by_key(|(_, count)| Reverse(*count));
+        repeated
+    }
+}
+
+/// Run `f`, counting the statements `CountingConnection`s run on this thread.
+pub(crate) fn count_statements<F, T>(f: F) -> (T, StatementCounts)
+where
+    F: FnOnce() -> T,
+{
+    struct Reset;
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            STATEMENTS.with(|statements| statements.borrow_mut().take());
+        }
+    }
+
+    STATEMENTS.with(|statements| *statements.borrow_mut() = Some(StatementCounts::default()));
+    let reset = Reset;
+    let result = f();
+    let counts = STATEMENTS.with(|statements| statements.borrow_mut().take());
+    drop(reset);
+
+    (result, counts.unwrap_or_default())
+}
+
+fn record<DB, T>(source: &T)
+where
+    DB: Backend,
+    DB::QueryBuilder: Default,
+    T: QueryFragment<DB>,
+{
+    STATEMENTS.with(|statements| {
+        if let Some(statements) = statements.borrow_mut().as_mut() {
+            let mut query_builder = DB::QueryBuilder::default();
+            let sql = match source.to_sql(&mut query_builder) {
+                Ok(()) => query_builder.finish(),
+                Err(_) => "<unknown statement>".to_string(),
+            };
+            *statements.counts.entry(sql).or_insert(0) += 1;
+        }
+    });
+}
+
+/// A connection that counts the statements it runs. See the module docs.
+pub struct CountingConnection<C> {
+    inner: C,
+    /// Set when a panic is caught after the connection started a transaction.
+    panicked: Arc<AtomicBool>,
+}
+
+impl<C> CountingConnection<C> {
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<C> CountingConnection<C>
+where
+    C: Connection<TransactionManager = AnsiTransactionManager>,
+    C::Backend: UsesAnsiSavepointSyntax,
+    <C::Backend as Backend>::QueryBuilder: Default,
+{
+    fn transaction_depth(&self) -> u32 {
+        TransactionManager::<Self>::get_transaction_depth(self.inner.transaction_manager())
+    }
+
+    /// Roll back the transactions a panic left open. If that fails the connection keeps
+    /// failing, so the pool's check on checkout discards it.
+    fn roll_back_after_panic(&self) -> QueryResult<()> {
+        if !self.panicked.swap(false, Ordering::SeqCst) || self.transaction_depth() == 0 {
+            return Ok(());
+        }
+
+        log::warn!("Rolling back a transaction left open by a panic");
+        while self.transaction_depth() > 0 {
+            let result = TransactionManager::<Self>::rollback_transaction(
+                self.inner.transaction_manager(),
+                self,
+            );
+            if let Err(err) = result {
+                self.panicked.store(true, Ordering::SeqCst);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<C> SimpleConnection for CountingConnection<C>
+where
+    C: Connection<TransactionManager = AnsiTransactionManager>,
+    C::Backend: UsesAnsiSavepointSyntax,
+    <C::Backend as Backend>::QueryBuilder: Default,
+{
+    fn batch_execute(&self, query: &str) -> QueryResult<()> {
+        self.roll_back_after_panic()?;
+        // Transactions are started with `batch_execute` outside of any other transaction.
+        if self.transaction_depth() == 0 {
+            panics::flag_on_panic(&self.panicked);
+        }
+        self.inner.batch_execute(query)
+    }
+}
+
+impl<C> Connection for CountingConnection<C>
+where
+    C: Connection<TransactionManager = AnsiTransactionManager>,
+    C::Backend: UsesAnsiSavepointSyntax,
+    <C::Backend as Backend>::QueryBuilder: Default,
+{
+    type Backend = C::Backend;
+    type TransactionManager = AnsiTransactionManager;
+
+    fn establish(database_url: &str) -> ConnectionResult<Self> {
+        let connection = C::establish(database_url).map(|inner| CountingConnection {
+            inner,
+            panicked: Arc::new(AtomicBool::new(false)),
+        })?;
+        ESTABLISHED.store(true, Ordering::SeqCst);
+        Ok(connection)
+    }
+
+    fn execute(&self, query: &str) -> QueryResult<usize> {
+        self.roll_back_after_panic()?;
+        self.inner.execute(query)
+    }
+
+    fn query_by_index<T, U>(&self, source: T) -> QueryResult<Vec<U>>
+    where
+        T: AsQuery,
+        T::Query: QueryFragment<Self::Backend> + QueryId,
+        Self::Backend: HasSqlType<T::SqlType>,
+        U: Queryable<T::SqlType, Self::Backend>,
+    {
+        self.roll_back_after_panic()?;
+        let query = source.as_query();
+        record::<Self::Backend, _>(&query);
+        self.inner.query_by_index(query)
+    }
+
+    fn query_by_name<T, U>(&self, source: &T) -> QueryResult<Vec<U>>
+    where
+        T: QueryFragment<Self::Backend> + QueryId,
+        U: QueryableByName<Self::Backend>,
+    {
+        self.roll_back_after_panic()?;
+        record::<Self::Backend, _>(source);
+        self.inner.query_by_name(source)
+    }
+
+    fn execute_returning_count<T>(&self, source: &T) -> QueryResult<usize>
+    where
+        T: QueryFragment<Self::Backend> + QueryId,
+    {
+        self.roll_back_after_panic()?;
+        record::<Self::Backend, _>(source);
+        self.inner.execute_returning_count(source)
+    }
+
+    fn transaction_manager(&self) -> &Self::TransactionManager {
+        self.inner.transaction_manager()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_connection::FakeConnection;
+    use diesel::prelude::*;
+    use diesel::result::Error;
+
+    table! {
+        users (id) {
+            id -> Integer,
+            name -> Text,
+        }
+    }
+
+    fn connection() -> CountingConnection<FakeConnection> {
+        CountingConnection::establish("").unwrap()
+    }
+
+    fn executed(connection: &CountingConnection<FakeConnection>) -> Vec<String> {
+        connection.inner().executed.borrow().clone()
+    }
+
+    #[test]
+    fn statements_are_counted_ignoring_bind_parameters() {
+        let connection = connection();
+        assert!(connections_are_counted());
+
+        let ((), counts) = count_statements(|| {
+            for id in 1..=3 {
+                users::table
+                    .find(id)
+                    .select(users::name)
+                    .load::<String>(&connection)
+                    .unwrap();
+            }
+            diesel::update(users::table)
+                .set(users::name.eq("name"))
+                .execute(&connection)
+                .unwrap();
+            connection.batch_execute("SELECT 1").unwrap();
+        });
+
+        assert_eq!(counts.total(), 4);
+        assert_eq!(
+            counts.repeated(2),
+            vec![(
+                "SELECT \"users\".\"name\" FROM \"users\" WHERE \"users\".\"id\" = $1",
+                3
+            )]
+        );
+        assert!(counts.repeated(3).is_empty());
+    }
+
+    #[test]
+    fn repeated_statements_are_sorted_by_count() {
+        let connection = connection();
+
+        let ((), counts) = count_statements(|| {
+            for _ in 0..2 {
+                users::table
+                    .select(users::id)
+                    .load::<i32>(&connection)
+                    .unwrap();
+            }
+            for _ in 0..3 {
+                users::table
+                    .select(users::name)
+                    .load::<String>(&connection)
+                    .unwrap();
+            }
+        });
+
+        let repeated = counts
+            .repeated(1)
+            .into_iter()
+            .map(|(_, count)| count)
+            .collect::<Vec<_>>();
+        assert_eq!(repeated, vec![3, 2]);
+        assert_eq!(counts.repeated(0).len(), 2);
+    }
+
+    #[test]
+    fn statements_outside_count_statements_are_not_counted() {
+        let connection = connection();
+        users::table
+            .select(users::id)
+            .load::<i32>(&connection)
+            .unwrap();
+
+        let ((), counts) = count_statements(|| ());
+        users::table
+            .select(users::id)
+            .load::<i32>(&connection)
+            .unwrap();
+
+        assert_eq!(counts.total(), 0);
+        STATEMENTS.with(|statements| assert!(statements.borrow().is_none()));
+    }
+
+    #[test]
+    fn transactions_left_open_by_a_panic_are_rolled_back() {
+        panics::install_hook();
+        let connection = connection();
+
+        let result = panics::catch(|| connection.transaction::<(), Error, _>(|| panic!("boom")));
+
+        assert!(result.is_err());
+        assert_eq!(connection.transaction_depth(), 1);
+        connection.execute("SELECT 1").unwrap();
+        assert_eq!(connection.transaction_depth(), 0);
+        assert_eq!(executed(&connection), vec!["BEGIN", "ROLLBACK", "SELECT 1"]);
+    }
+
+    #[test]
+    fn failed_rollbacks_keep_failing() {
+        panics::install_hook();
+        let connection = connection();
+        connection.inner().fail_rollback.set(true);
+
+        let _ = panics::catch(|| connection.transaction::<(), Error, _>(|| panic!("boom")));
+
+        assert!(connection.execute("SELECT 1").is_err());
+        assert!(connection.execute("SELECT 1").is_err());
+        assert_eq!(executed(&connection), vec!["BEGIN", "ROLLBACK", "ROLLBACK"]);
+
+        connection.inner().fail_rollback.set(false);
+        connection.execute("SELECT 1").unwrap();
+        assert_eq!(connection.transaction_depth(), 0);
+    }
+
+    #[test]
+    fn transactions_without_a_panic_are_left_alone() {
+        panics::install_hook();
+        let connection = connection();
+
+        let result = panics::catch(|| connection.transaction::<(), Error, _>(|| Ok(())));
+
+        assert!(result.is_ok());
+        connection.execute("SELECT 1").unwrap();
+        assert_eq!(executed(&connection), vec!["BEGIN", "COMMIT", "SELECT 1"]);
+    }
+}
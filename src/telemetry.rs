@@ -0,0 +1,289 @@
+//! `tracing` spans for requests and database pool checkouts.
+//!
+//! Every request runs in a `graphql_request` span recording its request ID, operation name,
+//! operation type, error count and duration. The operations of a batch each get a
+//! `graphql_operation` span inside it. Checking out a connection from the pool with
+//! [`get_connection`] is recorded in a `db_pool_checkout` span inside the span of the request
+//! that did it. Connections checked out with `Pool::get` get a span after the fact that only
+//! carries the checkout's duration.
+//!
+//! The spans only go somewhere if the app installs a `tracing` subscriber before calling
+//! `run_graphql_app`.
+
+use crate::request_id::RequestId;
+use r2d2::event::{CheckoutEvent, HandleEvent, TimeoutEvent};
+use r2d2::{ManageConnection, Pool, PooledConnection};
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{field, Span};
+
+thread_local! {
+    /// The checkouts of the request this thread is working on. Pool events don't say which
+    /// request they belong to, so this is how they find it.
+    static CURRENT_CHECKOUTS: RefCell<Option<PoolCheckouts>> = const { RefCell::new(None) };
+}
+
+/// Counts the connections a request checks out of the pool, whichever threads it runs on.
+#[derive(Clone, Default)]
+pub(crate) struct PoolCheckouts {
+    count: Arc<AtomicUsize>,
+}
+
+impl PoolCheckouts {
+    /// Run `f`, counting the checkouts it makes towards this request.
+    pub(crate) fn in_scope<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        struct Restore(Option<PoolCheckouts>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT_CHECKOUTS.with(|current| *current.borrow_mut() = self.0.take());
+            }
+        }
+
+        let _restore =
+            Restore(CURRENT_CHECKOUTS.with(|current| current.borrow_mut().replace(self.clone())));
+        f()
+    }
+
+    /// The number of checkouts since the last call.
+    pub(crate) fn take(&self) -> usize {
+        self.count.swap(0, Ordering::SeqCst)
+    }
+}
+
+/// Check out a connection inside a `db_pool_checkout` span, which records how long the
+/// checkout took and which connection it got. Use it in place of `Pool::get` so the span
+/// covers the wait for a connection.
+pub fn get_connection<M: ManageConnection>(
+    pool: &Pool<M>,
+) -> Result<PooledConnection<M>, r2d2::Error> {
+    tracing::info_span!(
+        "db_pool_checkout",
+        connection_id = field::Empty,
+        duration_ms = field::Empty,
+        timeout_ms = field::Empty,
+    )
+    .in_scope(|| pool.get())
+}
+
+/// Whether the current span is the one `get_connection` entered.
+fn in_checkout_span() -> Option<Span> {
+    let span = Span::current();
+    if span
+        .metadata()
+        .is_some_and(|metadata| metadata.name() == "db_pool_checkout")
+    {
+        Some(span)
+    } else {
+        None
+    }
+}
+
+/// The span of a request, which records the request's duration when dropped.
+pub(crate) struct RequestSpan {
+    span: Span,
+    started: Instant,
+}
+
+impl RequestSpan {
+    pub(crate) fn new(request_id: &RequestId) -> Self {
+        RequestSpan {
+            span: tracing::info_span!(
+                "graphql_request",
+                request_id = request_id.as_str(),
+                operation_name = field::Empty,
+                operation_type = field::Empty,
+                error_count = field::Empty,
+                duration_ms = field::Empty,
+            ),
+            started: Instant::now(),
+        }
+    }
+
+    pub(crate) fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+impl Drop for RequestSpan {
+    fn drop(&mut self) {
+        self.span
+            .record("duration_ms", millis(self.started.elapsed()));
+    }
+}
+
+/// A span for one operation of a batch.
+pub(crate) fn operation_span() -> Span {
+    tracing::info_span!(
+        "graphql_operation",
+        operation_name = field::Empty,
+        operation_type = field::Empty,
+        error_count = field::Empty,
+    )
+}
+
+/// Records pool checkouts as spans inside the span of the current request.
+#[derive(Debug)]
+pub(crate) struct PoolEventHandler;
+
+impl HandleEvent for PoolEventHandler {
+    fn handle_checkout(&self, event: CheckoutEvent) {
+        CURRENT_CHECKOUTS.with(|current| {
+            if let Some(checkouts) = &*current.borrow() {
+                checkouts.count.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        match in_checkout_span() {
+            Some(span) => {
+                span.record("connection_id", event.connection_id());
+                span.record("duration_ms", millis(event.duration()));
+            }
+            // Checked out with `Pool::get`. The checkout is over by the time this is called, so
+            // the span only carries its duration.
+            None => drop(tracing::info_span!(
+                "db_pool_checkout",
+                connection_id = event.connection_id(),
+                duration_ms = millis(event.duration()),
+            )),
+        }
+
+        #[cfg(feature = "metrics")]
+        crate::metrics::observe_pool_checkout(event.duration());
+    }
+
+    fn handle_timeout(&self, event: TimeoutEvent) {
+        match in_checkout_span() {
+            Some(span) => {
+                span.record("timeout_ms", millis(event.timeout()));
+                tracing::warn!("timed out waiting for a database connection");
+            }
+            None => tracing::info_span!("db_pool_checkout", timeout_ms = millis(event.timeout()))
+                .in_scope(|| tracing::warn!("timed out waiting for a database connection")),
+        }
+
+        #[cfg(feature = "metrics")]
+        crate::metrics::observe_pool_timeout();
+    }
+}
+
+pub(crate) fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    struct Manager;
+
+    impl ManageConnection for Manager {
+        type Connection = ();
+        type Error = std::fmt::Error;
+
+        fn connect(&self) -> Result<(), std::fmt::Error> {
+            Ok(())
+        }
+
+        fn is_valid(&self, _: &mut ()) -> Result<(), std::fmt::Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut ()) -> bool {
+            false
+        }
+    }
+
+    fn pool(max_size: u32) -> Pool<Manager> {
+        Pool::builder()
+            .max_size(max_size)
+            .connection_timeout(Duration::from_millis(10))
+            .event_handler(Box::new(PoolEventHandler))
+            .build(Manager)
+            .unwrap()
+    }
+
+    fn current() -> Option<PoolCheckouts> {
+        CURRENT_CHECKOUTS.with(|current| current.borrow().clone())
+    }
+
+    #[test]
+    fn checkouts_in_scope_are_counted() {
+        let pool = pool(2);
+        let checkouts = PoolCheckouts::default();
+
+        checkouts.in_scope(|| {
+            get_connection(&pool).unwrap();
+            pool.get().unwrap();
+        });
+        get_connection(&pool).unwrap();
+
+        assert_eq!(checkouts.take(), 2);
+        assert_eq!(checkouts.take(), 0);
+    }
+
+    #[test]
+    fn checkouts_on_other_threads_count_towards_the_same_request() {
+        let pool = pool(2);
+        let checkouts = PoolCheckouts::default();
+
+        let other = checkouts.clone();
+        let other_pool = pool.clone();
+        thread::spawn(move || other.in_scope(|| drop(get_connection(&other_pool).unwrap())))
+            .join()
+            .unwrap();
+        checkouts.in_scope(|| drop(get_connection(&pool).unwrap()));
+
+        assert_eq!(checkouts.take(), 2);
+    }
+
+    #[test]
+    fn timed_out_checkouts_are_not_counted() {
+        let pool = pool(1);
+        let checkouts = PoolCheckouts::default();
+
+        checkouts.in_scope(|| {
+            let _connection = get_connection(&pool).unwrap();
+            assert!(get_connection(&pool).is_err());
+        });
+
+        assert_eq!(checkouts.take(), 1);
+    }
+
+    #[test]
+    fn scopes_restore_the_outer_scope() {
+        let outer = PoolCheckouts::default();
+        let inner = PoolCheckouts::default();
+        let pool = pool(1);
+
+        outer.in_scope(|| {
+            inner.in_scope(|| drop(get_connection(&pool).unwrap()));
+            drop(get_connection(&pool).unwrap());
+        });
+
+        assert_eq!(inner.take(), 1);
+        assert_eq!(outer.take(), 1);
+        assert!(current().is_none());
+    }
+
+    #[test]
+    fn scopes_are_left_when_they_panic() {
+        let checkouts = PoolCheckouts::default();
+
+        let result = std::panic::catch_unwind(|| checkouts.in_scope(|| panic!("boom")));
+
+        assert!(result.is_err());
+        assert!(current().is_none());
+    }
+
+    #[test]
+    fn durations_are_converted_to_millis() {
+        assert_eq!(millis(Duration::new(2, 345_678_901)), 2345);
+    }
+}
@@ -0,0 +1,115 @@
+//! A fake Postgres connection for the tests of the modules that run SQL.
+
+use diesel::connection::{AnsiTransactionManager, Connection, SimpleConnection};
+use diesel::deserialize::{FromSqlRow, Queryable, QueryableByName};
+use diesel::pg::Pg;
+use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
+use diesel::result::{ConnectionResult, Error, QueryResult};
+use diesel::row::Row;
+use diesel::sql_types::HasSqlType;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+
+/// A row as Postgres sends it, one binary encoded value per column.
+pub(crate) type FakeRow = Vec<Option<Vec<u8>>>;
+
+/// Records the SQL it's asked to run, and answers queries with the rows pushed with
+/// `respond_with`, in order. Queries nothing was pushed for return no rows.
+#[derive(Default)]
+pub(crate) struct FakeConnection {
+    transaction_manager: AnsiTransactionManager,
+    pub(crate) executed: RefCell<Vec<String>>,
+    pub(crate) fail_rollback: Cell<bool>,
+    responses: RefCell<VecDeque<Vec<FakeRow>>>,
+}
+
+impl FakeConnection {
+    pub(crate) fn respond_with(&self, rows: Vec<FakeRow>) {
+        self.responses.borrow_mut().push_back(rows);
+    }
+
+    fn run(&self, sql: &str) -> QueryResult<()> {
+        self.executed.borrow_mut().push(sql.to_string());
+        if sql == "ROLLBACK" && self.fail_rollback.get() {
+            return Err(Error::QueryBuilderError("rollback failed".into()));
+        }
+        Ok(())
+    }
+}
+
+impl SimpleConnection for FakeConnection {
+    fn batch_execute(&self, query: &str) -> QueryResult<()> {
+        self.run(query)
+    }
+}
+
+impl Connection for FakeConnection {
+    type Backend = Pg;
+    type TransactionManager = AnsiTransactionManager;
+
+    fn establish(_: &str) -> ConnectionResult<Self> {
+        Ok(FakeConnection::default())
+    }
+
+    fn execute(&self, query: &str) -> QueryResult<usize> {
+        self.run(query).map(|()| 0)
+    }
+
+    fn query_by_index<T, U>(&self, source: T) -> QueryResult<Vec<U>>
+    where
+        T: AsQuery,
+        T::Query: QueryFragment<Pg> + QueryId,
+        Pg: HasSqlType<T::SqlType>,
+        U: Queryable<T::SqlType, Pg>,
+    {
+        let query = source.as_query();
+        self.run(&diesel::debug_query::<Pg, _>(&query).to_string())?;
+        let rows = self.responses.borrow_mut().pop_front().unwrap_or_default();
+        rows.into_iter()
+            .map(|values| {
+                let mut row = Values { values, index: 0 };
+                U::Row::build_from_row(&mut row)
+                    .map(U::build)
+                    .map_err(Error::DeserializationError)
+            })
+            .collect()
+    }
+
+    fn query_by_name<T, U>(&self, _: &T) -> QueryResult<Vec<U>>
+    where
+        T: QueryFragment<Pg> + QueryId,
+        U: QueryableByName<Pg>,
+    {
+        Ok(Vec::new())
+    }
+
+    fn execute_returning_count<T>(&self, _: &T) -> QueryResult<usize>
+    where
+        T: QueryFragment<Pg> + QueryId,
+    {
+        Ok(1)
+    }
+
+    fn transaction_manager(&self) -> &AnsiTransactionManager {
+        &self.transaction_manager
+    }
+}
+
+struct Values {
+    values: FakeRow,
+    index: usize,
+}
+
+impl Row<Pg> for Values {
+    fn take(&mut self) -> Option<&[u8]> {
+        let index = self.index;
+        self.index += 1;
+        self.values.get(index)?.as_ref().map(Vec::as_slice)
+    }
+
+    fn next_is_null(&self, count: usize) -> bool {
+        self.values[self.index..self.index + count]
+            .iter()
+            .all(Option::is_none)
+    }
+}
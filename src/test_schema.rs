@@ -0,0 +1,45 @@
+//! A small schema for the tests of the modules that analyse queries.
+
+use crate::authorization::Authorizer;
+use juniper::{EmptyMutation, RootNode};
+
+pub(crate) type Schema = RootNode<'static, Query, EmptyMutation<()>>;
+
+pub(crate) fn schema() -> Schema {
+    RootNode::new(Query, EmptyMutation::new())
+}
+
+impl Authorizer for () {}
+
+pub(crate) struct Query;
+
+#[juniper::object]
+impl Query {
+    #[graphql(arguments(limit(default = 10)))]
+    fn users(limit: i32) -> Vec<User> {
+        (1..=limit).map(User).collect()
+    }
+
+    fn user(id: i32) -> Option<User> {
+        Some(User(id))
+    }
+}
+
+pub(crate) struct User(pub(crate) i32);
+
+#[juniper::object]
+impl User {
+    fn id(&self) -> i32 {
+        self.0
+    }
+
+    fn name(&self) -> String {
+        format!("User {}", self.0)
+    }
+
+    fn friends(&self, executor: &Executor, first: i32) -> Vec<User> {
+        crate::time_resolver(executor, "User", "friends", || {
+            (1..=first).map(|id| User(self.0 * 100 + id)).collect()
+        })
+    }
+}
@@ -0,0 +1,536 @@
+//! File uploads following the [GraphQL multipart request spec][spec].
+//!
+//! Declare `scalar Upload` in your schema. `juniper-from-schema` generates an `Upload(String)`
+//! type for it whose value is an opaque token. Keep the request's [`Uploads`] in your context,
+//! from `hyper_adapter::uploads` or `rocket_adapter::uploads`, and use it inside your resolver
+//! to get a handle to the file:
+//!
+//! ```ignore
+//! fn field_upload_file(
+//!     &self,
+//!     executor: &juniper::Executor<'_, Context>,
+//!     file: Upload,
+//! ) -> juniper::FieldResult<i32> {
+//!     let file = executor.context().uploads.get(&file.0).ok_or("unknown upload")?;
+//!     let mut contents = String::new();
+//!     file.open()?.read_to_string(&mut contents)?;
+//!     // ...
+//! }
+//! ```
+//!
+//! Files are streamed to temporary files and removed again when the request has finished.
+//!
+//! [spec]: https://github.com/jaydenseric/graphql-multipart-request-spec
+
+use crate::request::GraphqlBatchRequest;
+use multipart::server::Multipart;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tempfile::NamedTempFile;
+
+pub struct UploadConfig {
+    pub(crate) max_file_size: u64,
+    pub(crate) max_total_size: u64,
+    pub(crate) temp_dir: PathBuf,
+}
+
+/// A file received as part of a multipart GraphQL request.
+#[derive(Clone)]
+pub struct UploadedFile {
+    inner: Arc<SavedFile>,
+}
+
+struct SavedFile {
+    file: NamedTempFile,
+    filename: Option<String>,
+    content_type: Option<String>,
+    size: u64,
+}
+
+impl UploadedFile {
+    /// The file name sent by the client, if any.
+    pub fn filename(&self) -> Option<&str> {
+        self.inner.filename.as_deref()
+    }
+
+    /// The content type sent by the client, if any.
+    pub fn content_type(&self) -> Option<&str> {
+        self.inner.content_type.as_deref()
+    }
+
+    /// The size of the file in bytes.
+    pub fn size(&self) -> u64 {
+        self.inner.size
+    }
+
+    /// The path of the temporary file the upload was saved to.
+    pub fn path(&self) -> &Path {
+        self.inner.file.path()
+    }
+
+    /// Open the file for reading, starting from the beginning.
+    pub fn open(&self) -> io::Result<File> {
+        self.inner.file.reopen()
+    }
+}
+
+/// The files received with a single request.
+///
+/// The adapters create it before the context, so the context can keep a clone. The files are
+/// deleted from disk when the last clone is dropped, once the request has finished.
+#[derive(Clone, Default)]
+pub struct Uploads {
+    files: Arc<Mutex<HashMap<String, UploadedFile>>>,
+}
+
+impl Uploads {
+    /// Look up the file an `Upload` scalar refers to.
+    ///
+    /// Returns `None` if the token wasn't sent with this request.
+    pub fn get(&self, token: &str) -> Option<UploadedFile> {
+        self.files
+            .lock()
+            .expect("uploads poisoned")
+            .get(token)
+            .cloned()
+    }
+
+    fn insert(&self, token: String, file: UploadedFile) {
+        self.files
+            .lock()
+            .expect("uploads poisoned")
+            .insert(token, file);
+    }
+}
+
+/// Returns the boundary if the content type is `multipart/form-data`.
+pub(crate) fn multipart_boundary(content_type: &str) -> Option<String> {
+    let mut parts = content_type.split(';').map(|part| part.trim());
+
+    let media_type = parts.next()?;
+    if !media_type.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+
+    parts
+        .filter_map(|param| {
+            let mut key_value = param.splitn(2, '=');
+            match (key_value.next(), key_value.next()) {
+                (Some(key), Some(value)) if key.trim().eq_ignore_ascii_case("boundary") => {
+                    Some(value.trim().trim_matches('"').to_string())
+                }
+                _ => None,
+            }
+        })
+        .next()
+}
+
+/// Decode a multipart request body into the GraphQL request it contains.
+///
+/// The files are saved to `config.temp_dir` and added to `uploads`, and the `null`s in the
+/// variables they're mapped to are replaced with tokens that can be given to [`Uploads::get`].
+pub(crate) fn decode<R: Read>(
+    body: R,
+    boundary: &str,
+    config: &UploadConfig,
+    uploads: &Uploads,
+) -> Result<GraphqlBatchRequest, UploadError> {
+    let mut multipart = Multipart::with_body(body, boundary);
+
+    let mut operations: Option<serde_json::Value> = None;
+    let mut map: Option<HashMap<String, Vec<String>>> = None;
+    let mut total_size = 0;
+
+    while let Some(field) = multipart.read_entry()? {
+        let name = field.headers.name.to_string();
+        let remaining = config.max_total_size.saturating_sub(total_size);
+
+        match name.as_str() {
+            "operations" => {
+                let mut data = field.data.take(remaining);
+                let value = serde_json::from_reader(&mut data).map_err(UploadError::Json)?;
+                total_size += remaining - data.limit();
+                operations = Some(value);
+            }
+            "map" => {
+                if operations.is_none() {
+                    return Err(UploadError::MisorderedField("map"));
+                }
+                let mut data = field.data.take(remaining);
+                let value = serde_json::from_reader(&mut data).map_err(UploadError::Json)?;
+                total_size += remaining - data.limit();
+                map = Some(value);
+            }
+            _ => {
+                let paths = match map.as_mut().and_then(|map| map.remove(&name)) {
+                    Some(paths) => paths,
+                    None => return Err(UploadError::UnexpectedFile(name)),
+                };
+
+                let limit = std::cmp::min(config.max_file_size, remaining);
+                let mut file = tempfile::Builder::new()
+                    .prefix("gimme-graphql-upload-")
+                    .tempfile_in(&config.temp_dir)?;
+                let size = io::copy(&mut field.data.take(limit + 1), &mut file)?;
+
+                if size > limit {
+                    return Err(if limit == config.max_file_size {
+                        UploadError::FileTooLarge(name)
+                    } else {
+                        UploadError::RequestTooLarge
+                    });
+                }
+                total_size += size;
+
+                let token = uuid::Uuid::new_v4().to_string();
+                let uploaded_file = UploadedFile {
+                    inner: Arc::new(SavedFile {
+                        file,
+                        filename: field.headers.filename.clone(),
+                        content_type: field
+                            .headers
+                            .content_type
+                            .as_ref()
+                            .map(|content_type| content_type.to_string()),
+                        size,
+                    }),
+                };
+                uploads.insert(token.clone(), uploaded_file);
+
+                let operations = operations
+                    .as_mut()
+                    .ok_or(UploadError::MisorderedField("operations"))?;
+                for path in paths {
+                    set_at_path(operations, &path, &token)?;
+                }
+            }
+        }
+    }
+
+    let operations = operations.ok_or(UploadError::MissingField("operations"))?;
+    match map {
+        None => return Err(UploadError::MissingField("map")),
+        Some(ref map) if !map.is_empty() => {
+            let name = map.keys().next().cloned().unwrap_or_default();
+            return Err(UploadError::MissingFile(name));
+        }
+        Some(_) => {}
+    }
+
+    serde_json::from_value(operations).map_err(UploadError::Json)
+}
+
+fn set_at_path(
+    operations: &mut serde_json::Value,
+    path: &str,
+    token: &str,
+) -> Result<(), UploadError> {
+    let invalid_path = || UploadError::InvalidPath(path.to_string());
+
+    let mut value = operations;
+    for segment in path.split('.') {
+        value = match value {
+            serde_json::Value::Object(object) => {
+                object.get_mut(segment).ok_or_else(invalid_path)?
+            }
+            serde_json::Value::Array(array) => {
+                let index = segment.parse::<usize>().map_err(|_| invalid_path())?;
+                array.get_mut(index).ok_or_else(invalid_path)?
+            }
+            _ => return Err(invalid_path()),
+        };
+    }
+
+    if !value.is_null() {
+        return Err(invalid_path());
+    }
+    *value = serde_json::Value::String(token.to_string());
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum UploadError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    MissingField(&'static str),
+    MisorderedField(&'static str),
+    MissingFile(String),
+    UnexpectedFile(String),
+    InvalidPath(String),
+    FileTooLarge(String),
+    RequestTooLarge,
+}
+
+impl UploadError {
+    pub(crate) fn is_too_large(&self) -> bool {
+        matches!(
+            self,
+            UploadError::FileTooLarge(_) | UploadError::RequestTooLarge
+        )
+    }
+}
+
+impl From<io::Error> for UploadError {
+    fn from(err: io::Error) -> Self {
+        UploadError::Io(err)
+    }
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadError::Io(err) => write!(f, "failed to read multipart request: {}", err),
+            UploadError::Json(err) => write!(f, "invalid JSON in multipart request: {}", err),
+            UploadError::MissingField(name) => write!(f, "missing multipart field '{}'", name),
+            UploadError::MisorderedField(name) => {
+                write!(
+                    f,
+                    "multipart field '{}' must come earlier in the request",
+                    name
+                )
+            }
+            UploadError::MissingFile(name) => {
+                write!(f, "file '{}' is in 'map' but was not sent", name)
+            }
+            UploadError::UnexpectedFile(name) => write!(f, "file '{}' is not in 'map'", name),
+            UploadError::InvalidPath(path) => {
+                write!(
+                    f,
+                    "'{}' does not point to a null value in 'operations'",
+                    path
+                )
+            }
+            UploadError::FileTooLarge(name) => write!(f, "file '{}' is too large", name),
+            UploadError::RequestTooLarge => write!(f, "multipart request is too large"),
+        }
+    }
+}
+
+impl std::error::Error for UploadError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const BOUNDARY: &str = "------------------------boundary";
+
+    fn config(max_file_size: u64) -> UploadConfig {
+        UploadConfig {
+            max_file_size,
+            max_total_size: 1024,
+            temp_dir: std::env::temp_dir(),
+        }
+    }
+
+    fn body(parts: &[(&str, Option<&str>, &str)]) -> Vec<u8> {
+        let mut body = String::new();
+        for (name, filename, contents) in parts {
+            body.push_str(&format!("--{}\r\n", BOUNDARY));
+            match filename {
+                Some(filename) => body.push_str(&format!(
+                    "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: text/plain\r\n\r\n",
+                    name, filename
+                )),
+                None => body.push_str(&format!(
+                    "Content-Disposition: form-data; name=\"{}\"\r\n\r\n",
+                    name
+                )),
+            }
+            body.push_str(contents);
+            body.push_str("\r\n");
+        }
+        body.push_str(&format!("--{}--\r\n", BOUNDARY));
+        body.into_bytes()
+    }
+
+    fn decode_err(body: &[u8], config: UploadConfig) -> UploadError {
+        match decode(body, BOUNDARY, &config, &Uploads::default()) {
+            Ok(_) => panic!("decoded an invalid request"),
+            Err(err) => err,
+        }
+    }
+
+    const OPERATIONS: &str = r#"{"query":"mutation($file: Upload!) { uploadFile(file: $file) }","variables":{"file":null}}"#;
+
+    #[test]
+    fn decodes_files_into_uploads() {
+        let body = body(&[
+            ("operations", None, OPERATIONS),
+            ("map", None, r#"{"0":["variables.file"]}"#),
+            ("0", Some("hello.txt"), "hello"),
+        ]);
+        let uploads = Uploads::default();
+
+        decode(&body[..], BOUNDARY, &config(1024), &uploads).unwrap();
+
+        let files = uploads.files.lock().unwrap();
+        assert_eq!(files.len(), 1);
+        let file = files.values().next().unwrap();
+        assert_eq!(file.filename(), Some("hello.txt"));
+        assert_eq!(file.content_type(), Some("text/plain"));
+        assert_eq!(file.size(), 5);
+        let mut contents = String::new();
+        file.open().unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello");
+    }
+
+    #[test]
+    fn uploads_are_per_request() {
+        let body = body(&[
+            ("operations", None, OPERATIONS),
+            ("map", None, r#"{"0":["variables.file"]}"#),
+            ("0", Some("hello.txt"), "hello"),
+        ]);
+        let uploads = Uploads::default();
+        decode(&body[..], BOUNDARY, &config(1024), &uploads).unwrap();
+        let token = uploads
+            .files
+            .lock()
+            .unwrap()
+            .keys()
+            .next()
+            .cloned()
+            .unwrap();
+
+        assert!(uploads.get(&token).is_some());
+        assert!(Uploads::default().get(&token).is_none());
+    }
+
+    #[test]
+    fn deletes_files_when_the_last_handle_is_dropped() {
+        let body = body(&[
+            ("operations", None, OPERATIONS),
+            ("map", None, r#"{"0":["variables.file"]}"#),
+            ("0", Some("hello.txt"), "hello"),
+        ]);
+        let uploads = Uploads::default();
+        decode(&body[..], BOUNDARY, &config(1024), &uploads).unwrap();
+        let path = uploads
+            .files
+            .lock()
+            .unwrap()
+            .values()
+            .next()
+            .unwrap()
+            .path()
+            .to_path_buf();
+
+        let context_handle = uploads.clone();
+        drop(uploads);
+        assert!(path.exists());
+        drop(context_handle);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn rejects_files_over_the_size_limit() {
+        let body = body(&[
+            ("operations", None, OPERATIONS),
+            ("map", None, r#"{"0":["variables.file"]}"#),
+            ("0", Some("hello.txt"), "hello"),
+        ]);
+
+        let err = decode_err(&body, config(4));
+
+        assert!(err.is_too_large());
+        assert_eq!(err.to_string(), "file '0' is too large");
+    }
+
+    #[test]
+    fn rejects_files_missing_from_the_map() {
+        let body = body(&[
+            ("operations", None, OPERATIONS),
+            ("map", None, r#"{}"#),
+            ("0", Some("hello.txt"), "hello"),
+        ]);
+
+        let err = decode_err(&body, config(1024));
+
+        assert_eq!(err.to_string(), "file '0' is not in 'map'");
+    }
+
+    #[test]
+    fn rejects_map_entries_without_a_file() {
+        let body = body(&[
+            ("operations", None, OPERATIONS),
+            ("map", None, r#"{"0":["variables.file"]}"#),
+        ]);
+
+        let err = decode_err(&body, config(1024));
+
+        assert_eq!(err.to_string(), "file '0' is in 'map' but was not sent");
+    }
+
+    #[test]
+    fn rejects_map_before_operations() {
+        let body = body(&[
+            ("map", None, r#"{"0":["variables.file"]}"#),
+            ("operations", None, OPERATIONS),
+        ]);
+
+        let err = decode_err(&body, config(1024));
+
+        assert_eq!(
+            err.to_string(),
+            "multipart field 'map' must come earlier in the request"
+        );
+    }
+
+    #[test]
+    fn sets_nested_paths() {
+        let mut operations = json!([
+            { "variables": { "files": [null, null] } },
+            { "variables": { "file": null } },
+        ]);
+
+        set_at_path(&mut operations, "0.variables.files.1", "token").unwrap();
+        set_at_path(&mut operations, "1.variables.file", "other").unwrap();
+
+        assert_eq!(
+            operations,
+            json!([
+                { "variables": { "files": [null, "token"] } },
+                { "variables": { "file": "other" } },
+            ])
+        );
+    }
+
+    #[test]
+    fn only_replaces_nulls() {
+        let mut operations = json!({ "variables": { "file": "already set", "list": [] } });
+
+        for path in &[
+            "variables.file",
+            "variables.missing",
+            "variables.list.0",
+            "variables.list.x",
+            "variables.file.deeper",
+        ] {
+            let err = set_at_path(&mut operations, path, "token").unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("'{}' does not point to a null value in 'operations'", path)
+            );
+        }
+    }
+
+    #[test]
+    fn finds_the_multipart_boundary() {
+        assert_eq!(
+            multipart_boundary("multipart/form-data; boundary=abc"),
+            Some("abc".to_string())
+        );
+        assert_eq!(
+            multipart_boundary("Multipart/Form-Data; charset=utf-8; Boundary=\"a b\""),
+            Some("a b".to_string())
+        );
+        assert_eq!(multipart_boundary("multipart/form-data"), None);
+        assert_eq!(multipart_boundary("application/json; boundary=abc"), None);
+    }
+}
@@ -0,0 +1,603 @@
+use crate::analysis;
+use graphql_parser::query::{
+    Definition, Document, Field, FragmentDefinition, OperationDefinition, Selection, SelectionSet,
+    Type, TypeCondition, Value,
+};
+use juniper::{
+    meta::MetaType, DefaultScalarValue, GraphQLError, GraphQLType, Registry, RootNode, Variables,
+};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// Name given to a lone anonymous operation, so its variables are printed.
+const ANONYMOUS_OPERATION_NAME: &str = "__gimme_anonymous";
+
+/// Name of the operation juniper is asked to run by `validate_with_juniper`, which no document
+/// has, so nothing runs.
+const DRY_RUN_OPERATION_NAME: &str = "__gimme_dry_run";
+
+/// Check that a document only uses types, fields, arguments and fragments that exist.
+///
+/// This doesn't run the full set of validation rules juniper runs before execution, but
+/// catches documents written against an outdated version of the schema.
+pub(crate) fn validate_document<Query, Mutation>(
+    root_node: &RootNode<Query, Mutation>,
+    document: &Document,
+) -> Vec<String>
+where
+    Query: GraphQLType<TypeInfo = ()>,
+    Mutation: GraphQLType<TypeInfo = ()>,
+{
+    let mut fragments = HashMap::new();
+    let mut operation_count = 0;
+    for definition in &document.definitions {
+        match definition {
+            Definition::Operation(_) => operation_count += 1,
+            Definition::Fragment(fragment) => {
+                fragments.insert(fragment.name.as_str(), fragment);
+            }
+        }
+    }
+
+    let mut validator = Validator {
+        root_node,
+        fragments,
+        errors: Vec::new(),
+    };
+
+    if operation_count == 0 {
+        validator
+            .errors
+            .push("Document contains no operations".to_string());
+    }
+
+    for definition in &document.definitions {
+        match definition {
+            Definition::Operation(operation) => {
+                let (root_type, selection_set, name) = match operation {
+                    OperationDefinition::SelectionSet(selection_set) => (
+                        Some(root_node.schema.concrete_query_type()),
+                        selection_set,
+                        None,
+                    ),
+                    OperationDefinition::Query(query) => (
+                        Some(root_node.schema.concrete_query_type()),
+                        &query.selection_set,
+                        query.name.as_ref(),
+                    ),
+                    OperationDefinition::Mutation(mutation) => (
+                        root_node.schema.concrete_mutation_type(),
+                        &mutation.selection_set,
+                        mutation.name.as_ref(),
+                    ),
+                    OperationDefinition::Subscription(subscription) => (
+                        None,
+                        &subscription.selection_set,
+                        subscription.name.as_ref(),
+                    ),
+                };
+
+                if name.is_none() && operation_count > 1 {
+                    validator
+                        .errors
+                        .push("Anonymous operations must be the only operation".to_string());
+                }
+
+                match root_type {
+                    Some(root_type) => {
+                        validator.selection_set(root_type, selection_set, &mut Vec::new())
+                    }
+                    None => validator
+                        .errors
+                        .push("Schema does not support this operation type".to_string()),
+                }
+            }
+            Definition::Fragment(fragment) => {
+                let TypeCondition::On(type_name) = &fragment.type_condition;
+                if root_node.schema.concrete_type_by_name(type_name).is_none() {
+                    validator.errors.push(format!(
+                        "Fragment \"{}\" is on unknown type \"{}\"",
+                        fragment.name, type_name
+                    ));
+                }
+            }
+        }
+    }
+
+    validator.errors
+}
+
+struct Validator<'a, Query, Mutation>
+where
+    Query: GraphQLType<TypeInfo = ()>,
+    Mutation: GraphQLType<TypeInfo = ()>,
+{
+    root_node: &'a RootNode<'a, Query, Mutation>,
+    fragments: HashMap<&'a str, &'a FragmentDefinition>,
+    errors: Vec<String>,
+}
+
+impl<'a, Query, Mutation> Validator<'a, Query, Mutation>
+where
+    Query: GraphQLType<TypeInfo = ()>,
+    Mutation: GraphQLType<TypeInfo = ()>,
+{
+    fn selection_set(
+        &mut self,
+        parent_type: &'a MetaType<'a>,
+        selection_set: &'a SelectionSet,
+        visited_fragments: &mut Vec<&'a str>,
+    ) {
+        for selection in &selection_set.items {
+            match selection {
+                Selection::Field(field) => self.field(parent_type, field, visited_fragments),
+                Selection::FragmentSpread(spread) => {
+                    let name = spread.fragment_name.as_str();
+                    if visited_fragments.contains(&name) {
+                        continue;
+                    }
+
+                    let fragment = match self.fragments.get(name) {
+                        Some(&fragment) => fragment,
+                        None => {
+                            self.errors
+                                .push(format!("Unknown fragment \"{}\"", spread.fragment_name));
+                            continue;
+                        }
+                    };
+
+                    let TypeCondition::On(type_name) = &fragment.type_condition;
+                    if let Some(fragment_type) =
+                        self.root_node.schema.concrete_type_by_name(type_name)
+                    {
+                        visited_fragments.push(name);
+                        self.selection_set(
+                            fragment_type,
+                            &fragment.selection_set,
+                            visited_fragments,
+                        );
+                        visited_fragments.pop();
+                    }
+                }
+                Selection::InlineFragment(fragment) => {
+                    let fragment_type = match &fragment.type_condition {
+                        Some(TypeCondition::On(type_name)) => {
+                            match self.root_node.schema.concrete_type_by_name(type_name) {
+                                Some(fragment_type) => fragment_type,
+                                None => {
+                                    self.errors.push(format!(
+                                        "Inline fragment is on unknown type \"{}\"",
+                                        type_name
+                                    ));
+                                    continue;
+                                }
+                            }
+                        }
+                        None => parent_type,
+                    };
+                    self.selection_set(fragment_type, &fragment.selection_set, visited_fragments);
+                }
+            }
+        }
+    }
+
+    fn field(
+        &mut self,
+        parent_type: &'a MetaType<'a>,
+        field: &'a Field,
+        visited_fragments: &mut Vec<&'a str>,
+    ) {
+        let parent_name = parent_type.name().unwrap_or_default();
+
+        if field.name == "__typename" {
+            return;
+        }
+        if field.name == "__schema" || field.name == "__type" {
+            if Some(parent_name) != self.root_node.schema.concrete_query_type().name() {
+                self.errors.push(format!(
+                    "Field \"{}\" is only available on the query type",
+                    field.name
+                ));
+            }
+            return;
+        }
+
+        let meta_field = match parent_type.field_by_name(&field.name) {
+            Some(meta_field) => meta_field,
+            None => {
+                self.errors.push(format!(
+                    "Unknown field \"{}\" on type \"{}\"",
+                    field.name, parent_name
+                ));
+                return;
+            }
+        };
+
+        let meta_arguments = meta_field
+            .arguments
+            .as_ref()
+            .map(|arguments| &arguments[..])
+            .unwrap_or(&[]);
+
+        for (argument_name, _) in &field.arguments {
+            if !meta_arguments
+                .iter()
+                .any(|argument| &argument.name == argument_name)
+            {
+                self.errors.push(format!(
+                    "Unknown argument \"{}\" on field \"{}.{}\"",
+                    argument_name, parent_name, field.name
+                ));
+            }
+        }
+
+        for argument in meta_arguments {
+            let is_required = argument.arg_type.is_non_null() && argument.default_value.is_none();
+            let is_given = field
+                .arguments
+                .iter()
+                .any(|(argument_name, _)| argument_name == &argument.name);
+            if is_required && !is_given {
+                self.errors.push(format!(
+                    "Missing required argument \"{}\" on field \"{}.{}\"",
+                    argument.name, parent_name, field.name
+                ));
+            }
+        }
+
+        let field_type = match self
+            .root_node
+            .schema
+            .concrete_type_by_name(meta_field.field_type.innermost_name())
+        {
+            Some(field_type) => field_type,
+            None => return,
+        };
+
+        let has_selection = !field.selection_set.items.is_empty();
+        if field_type.is_leaf() && has_selection {
+            self.errors.push(format!(
+                "Field \"{}.{}\" is a leaf and can't have a selection",
+                parent_name, field.name
+            ));
+        } else if !field_type.is_leaf() && !has_selection {
+            self.errors.push(format!(
+                "Field \"{}.{}\" must have a selection",
+                parent_name, field.name
+            ));
+        } else {
+            self.selection_set(field_type, &field.selection_set, visited_fragments);
+        }
+    }
+}
+
+/// Run all of juniper's validation rules on a document against the schema of `Query` and
+/// `Mutation`, without resolving anything.
+///
+/// juniper only validates documents on the way to executing them, so the document is executed
+/// with an operation name it doesn't have, which fails once validation has passed. No variables
+/// are given, so required variables are made optional with a default first, `null` if they
+/// don't have one, which juniper still treats as non-null where they're used.
+pub(crate) fn validate_with_juniper<Query, Mutation>(document: &Document) -> Vec<String>
+where
+    Query: GraphQLType<TypeInfo = ()>,
+    Mutation: GraphQLType<TypeInfo = ()>,
+{
+    let mut document = document.clone();
+    let operation_count = document
+        .definitions
+        .iter()
+        .filter(|definition| match definition {
+            Definition::Operation(_) => true,
+            Definition::Fragment(_) => false,
+        })
+        .count();
+    for definition in &mut document.definitions {
+        let (name, variable_definitions) = match definition {
+            Definition::Operation(OperationDefinition::Query(query)) => {
+                (&mut query.name, &mut query.variable_definitions)
+            }
+            Definition::Operation(OperationDefinition::Mutation(mutation)) => {
+                (&mut mutation.name, &mut mutation.variable_definitions)
+            }
+            Definition::Operation(OperationDefinition::Subscription(subscription)) => (
+                &mut subscription.name,
+                &mut subscription.variable_definitions,
+            ),
+            _ => continue,
+        };
+        // graphql_parser only prints the variables of named operations.
+        if name.is_none() && operation_count == 1 {
+            *name = Some(ANONYMOUS_OPERATION_NAME.to_string());
+        }
+        for variable in variable_definitions {
+            if let Type::NonNullType(inner) = &variable.var_type {
+                variable.var_type = (**inner).clone();
+                variable.default_value = variable.default_value.take().or(Some(Value::Null));
+            }
+        }
+    }
+
+    let root_node = RootNode::new(
+        SchemaOnly::<Query>(PhantomData),
+        SchemaOnly::<Mutation>(PhantomData),
+    );
+    let source = document.to_string();
+    let result = juniper::execute(
+        &source,
+        Some(DRY_RUN_OPERATION_NAME),
+        &root_node,
+        &Variables::new(),
+        &(),
+    );
+    match result {
+        Err(GraphQLError::ValidationError(errors)) => errors
+            .iter()
+            .map(|error| error.message().to_string())
+            .collect(),
+        Err(GraphQLError::ParseError(error)) => vec![format!("Failed to parse: {}", error.item)],
+        _ => Vec::new(),
+    }
+}
+
+/// A root type with the schema of `T` that resolves nothing, so it needs no context.
+struct SchemaOnly<T>(PhantomData<T>);
+
+impl<T> GraphQLType for SchemaOnly<T>
+where
+    T: GraphQLType<TypeInfo = ()>,
+{
+    type Context = ();
+    type TypeInfo = ();
+
+    fn name(info: &()) -> Option<&str> {
+        T::name(info)
+    }
+
+    fn meta<'r>(info: &(), registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        T::meta(info, registry)
+    }
+}
+
+/// Whether any operation or fragment in the document selects `__schema` or `__type`.
+pub(crate) fn uses_introspection(document: &Document) -> bool {
+    document
+        .definitions
+        .iter()
+        .any(|definition| match definition {
+            Definition::Operation(OperationDefinition::SelectionSet(selection_set)) => {
+                selects_introspection(selection_set)
+            }
+            Definition::Operation(OperationDefinition::Query(query)) => {
+                selects_introspection(&query.selection_set)
+            }
+            Definition::Operation(OperationDefinition::Mutation(mutation)) => {
+                selects_introspection(&mutation.selection_set)
+            }
+            Definition::Operation(OperationDefinition::Subscription(subscription)) => {
+                selects_introspection(&subscription.selection_set)
+            }
+            Definition::Fragment(fragment) => selects_introspection(&fragment.selection_set),
+        })
+}
+
+fn selects_introspection(selection_set: &SelectionSet) -> bool {
+    selection_set.items.iter().any(|selection| match selection {
+        Selection::Field(field) => {
+            field.name == "__schema"
+                || field.name == "__type"
+                || selects_introspection(&field.selection_set)
+        }
+        Selection::FragmentSpread(_) => false,
+        Selection::InlineFragment(fragment) => selects_introspection(&fragment.selection_set),
+    })
+}
+
+/// The type of the operation that `operation_name` selects: `query`, `mutation` or
+/// `subscription`.
+///
+/// Returns `None` if there's no such operation.
+pub(crate) fn operation_type(
+    document: &Document,
+    operation_name: Option<&str>,
+) -> Option<&'static str> {
+    Some(match find_operation(document, operation_name)? {
+        OperationDefinition::SelectionSet(_) | OperationDefinition::Query(_) => "query",
+        OperationDefinition::Mutation(_) => "mutation",
+        OperationDefinition::Subscription(_) => "subscription",
+    })
+}
+
+/// The operation that runs when `operation_name` is requested, the way juniper picks it.
+pub(crate) fn find_operation<'a>(
+    document: &'a Document,
+    operation_name: Option<&str>,
+) -> Option<&'a OperationDefinition> {
+    let mut operations = document
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            Definition::Operation(operation) => Some(operation),
+            Definition::Fragment(_) => None,
+        })
+        .collect::<Vec<_>>();
+
+    match operation_name {
+        Some(name) => operations
+            .into_iter()
+            .find(|operation| analysis::operation_name(operation) == Some(name)),
+        None if operations.len() == 1 => operations.pop(),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_schema::{schema, Query};
+    use juniper::EmptyMutation;
+
+    fn validate(query: &str) -> Vec<String> {
+        let document = graphql_parser::parse_query(query).unwrap();
+        validate_document(&schema(), &document)
+    }
+
+    #[test]
+    fn valid_document_has_no_errors() {
+        let errors = validate(
+            "query Users { users { ...UserFields friends(first: 2) { __typename id } } }
+             fragment UserFields on User { id ... on User { name } }",
+        );
+
+        assert_eq!(errors, Vec::<String>::new());
+    }
+
+    #[test]
+    fn unknown_fields_and_arguments_are_errors() {
+        let errors = validate("{ users(first: 1) { id email } }");
+
+        assert_eq!(
+            errors,
+            vec![
+                "Unknown argument \"first\" on field \"Query.users\"",
+                "Unknown field \"email\" on type \"User\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_required_arguments_are_errors() {
+        let errors = validate("{ user { friends { id } } }");
+
+        assert_eq!(
+            errors,
+            vec![
+                "Missing required argument \"id\" on field \"Query.user\"",
+                "Missing required argument \"first\" on field \"User.friends\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn selections_must_match_leaf_types() {
+        let errors = validate("{ users { id { value } } user(id: 1) }");
+
+        assert_eq!(
+            errors,
+            vec![
+                "Field \"User.id\" is a leaf and can't have a selection",
+                "Field \"Query.user\" must have a selection",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_fragments_and_types_are_errors() {
+        let errors = validate(
+            "{ users { ...Missing ... on Post { id } } }
+             fragment PostFields on Post { id }",
+        );
+
+        assert_eq!(
+            errors,
+            vec![
+                "Unknown fragment \"Missing\"",
+                "Inline fragment is on unknown type \"Post\"",
+                "Fragment \"PostFields\" is on unknown type \"Post\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn recursive_fragments_terminate() {
+        let errors = validate(
+            "{ users { ...UserFields } }
+             fragment UserFields on User { id friends(first: 1) { ...UserFields } }",
+        );
+
+        assert_eq!(errors, Vec::<String>::new());
+    }
+
+    #[test]
+    fn operation_shape_errors() {
+        assert_eq!(
+            validate("fragment UserFields on User { id }"),
+            vec!["Document contains no operations"]
+        );
+        assert_eq!(
+            validate("{ users { id } } query Other { users { id } }"),
+            vec!["Anonymous operations must be the only operation"]
+        );
+        assert_eq!(
+            validate("mutation { createUser { id } }"),
+            vec!["Schema does not support this operation type"]
+        );
+        assert_eq!(
+            validate("{ users { __schema { types { name } } } }"),
+            vec!["Field \"__schema\" is only available on the query type"]
+        );
+    }
+
+    #[test]
+    fn introspection_is_detected_in_fragments() {
+        let document = graphql_parser::parse_query(
+            "{ users { ...Types } } fragment Types on User { id ... { __type(name: \"User\") { name } } }",
+        )
+        .unwrap();
+
+        assert!(uses_introspection(&document));
+        assert!(!uses_introspection(
+            &graphql_parser::parse_query("{ users { id } }").unwrap()
+        ));
+    }
+
+    #[test]
+    fn operation_type_is_found_by_name() {
+        let document =
+            graphql_parser::parse_query("query A { users { id } } mutation B { users { id } }")
+                .unwrap();
+
+        assert_eq!(operation_type(&document, Some("A")), Some("query"));
+        assert_eq!(operation_type(&document, Some("B")), Some("mutation"));
+        assert_eq!(operation_type(&document, Some("C")), None);
+        assert_eq!(operation_type(&document, None), None);
+    }
+
+    fn validate_with_juniper(query: &str) -> Vec<String> {
+        let document = graphql_parser::parse_query(query).unwrap();
+        super::validate_with_juniper::<Query, EmptyMutation<()>>(&document)
+    }
+
+    #[test]
+    fn juniper_accepts_valid_documents_without_variable_values() {
+        let errors = validate_with_juniper(
+            "query($id: Int!, $first: Int! = 2) { user(id: $id) { ...Friends } }
+             fragment Friends on User { friends(first: $first) { id } }",
+        );
+
+        assert_eq!(errors, Vec::<String>::new());
+    }
+
+    #[test]
+    fn juniper_rules_the_schema_check_skips_are_run() {
+        assert_eq!(
+            validate_with_juniper(r#"{ users(limit: "ten") { id } }"#),
+            vec![r#"Invalid value for argument "limit", expected type "Int""#]
+        );
+        assert_eq!(
+            validate_with_juniper("query User($id: Int) { user(id: $id) { id } }"),
+            vec![r#"Variable "id" of type "Int" used in position expecting type "Int!""#]
+        );
+        assert_eq!(
+            validate_with_juniper("query User { user(id: $id) { id } }"),
+            vec![r#"Variable "$id" is not defined by operation "User""#]
+        );
+        assert_eq!(
+            validate_with_juniper("{ users { id } } fragment Unused on User { id }"),
+            vec![r#"Fragment "Unused" is never used"#]
+        );
+    }
+}